@@ -0,0 +1,97 @@
+//! Benchmarks of the parsing and formatting done on every status poll.
+//!
+//! ```text
+//! cargo bench --bench parsing
+//! ```
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use rubin_ts_atdome::{
+    atdome_cmd_regex::ATDomeCmdRegex,
+    status::{Status, StatusFormat},
+    status_parser::StatusParser,
+};
+
+fn status() -> Status {
+    Status {
+        az_pos: 262.91,
+        last_azimuth_goto: 10.0,
+        main_door_pct: 42.0,
+        homed: true,
+        scb_link_ok: true,
+        ..Default::default()
+    }
+}
+
+fn bench_make_status(c: &mut Criterion) {
+    let reply = status().as_string_with_format(StatusFormat::Long);
+    let lines: Vec<&str> = reply.split('\n').collect();
+
+    let mut group = c.benchmark_group("make_status");
+    group.bench_function("parse", |b| {
+        b.iter_batched(
+            || StatusParser::new().unwrap(),
+            |status_parser| status_parser.make_status(black_box(&lines)).unwrap(),
+            BatchSize::SmallInput,
+        )
+    });
+    // What a status poll pays today: the regexes are compiled every time.
+    group.bench_function("compile_and_parse", |b| {
+        b.iter(|| {
+            StatusParser::new()
+                .unwrap()
+                .make_status(black_box(&lines))
+                .unwrap()
+        })
+    });
+    group.finish();
+}
+
+fn bench_into_atdome_cmd(c: &mut Criterion) {
+    let atdome_cmd_regex = ATDomeCmdRegex::new();
+
+    let mut group = c.benchmark_group("into_atdome_cmd");
+    for (name, text) in [
+        ("status", "+"),
+        ("move_azimuth", "123.45 MV"),
+        ("close_shutter", "SC"),
+        ("set_auto_shutdown", "1 RS"),
+        ("invalid", "12.x MV"),
+    ] {
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                let _ = atdome_cmd_regex.into_atdome_cmd(black_box(text));
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_as_string(c: &mut Criterion) {
+    let status = status();
+
+    let mut group = c.benchmark_group("as_string");
+    for status_format in [StatusFormat::Short, StatusFormat::Long] {
+        group.bench_function(format!("{status_format:?}"), |b| {
+            b.iter(|| black_box(&status).as_string_with_format(status_format))
+        });
+        // What the mock does: render into a buffer reused across polls.
+        let mut reply = String::with_capacity(1024);
+        group.bench_function(format!("{status_format:?}_into_buffer"), |b| {
+            b.iter(|| {
+                reply.clear();
+                black_box(&status)
+                    .write_with_format(&mut reply, status_format, "\r\n")
+                    .unwrap();
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_make_status,
+    bench_into_atdome_cmd,
+    bench_as_string
+);
+criterion_main!(benches);
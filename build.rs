@@ -0,0 +1,26 @@
+//! Embed the git hash and build date in the binaries.
+
+use std::process::Command;
+
+/// Run a command and return its trimmed output, or "unknown" on failure.
+fn command_output(program: &str, args: &[&str]) -> String {
+    Command::new(program)
+        .args(args)
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|output| output.trim().to_owned())
+        .filter(|output| !output.is_empty())
+        .unwrap_or_else(|| "unknown".to_owned())
+}
+
+fn main() {
+    let git_hash = command_output("git", &["rev-parse", "--short", "HEAD"]);
+    let build_date = command_output("date", &["-u", "+%Y-%m-%dT%H:%M:%SZ"]);
+
+    println!("cargo:rustc-env=ATDOME_GIT_HASH={git_hash}");
+    println!("cargo:rustc-env=ATDOME_BUILD_DATE={build_date}");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+}
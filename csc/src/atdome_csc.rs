@@ -0,0 +1,2579 @@
+//! Implement the ATDome CSC.
+//!
+
+use crate::{
+    ack_codes::CMD_ABORTED,
+    atdome_config::ATDomeConfig,
+    atdome_enums::{
+        AzimuthCommandedState as AzimuthCommandedStateEnum, AzimuthHomingState,
+        AzimuthState as AzimuthStateEnum, ShutterDoorCommandedState,
+    },
+    atdome_model::{ATDomeCmd, ATDomeModel},
+    azimuth::{azimuth_distance, AzimuthPath},
+    backpressure::{
+        queued, spawn_forwarder, ACK_CHANNEL, ACK_QUEUE_CAPACITY, COMMAND_CHANNEL,
+        COMMAND_QUEUE_CAPACITY, TELEMETRY_CHANNEL,
+    },
+    command_ack::{send_ack, send_progress_ack, CommandAckExt},
+    engineering_server::{serve_engineering_status, EngineeringStatus},
+    error::{ATDomeError, ATDomeResult},
+    in_position::{CommandedTargets, DomeInPosition, DOOR_TOLERANCE},
+    logging::{level_from_python, set_log_level},
+    metrics::{serve_metrics, ATDomeMetrics},
+    mock_controller::mock_controller::{run_mock_controller, MockControllerConfig},
+    motion_monitor::{
+        make_motion_ack, MakeAck, MotionAxis, MotionMonitorConfig, MotionOutcome, PendingMotion,
+    },
+    move_code::MoveCode,
+    shutdown_signal::wait_for_shutdown_signal,
+    status::Status,
+    summary_state::StateTransition,
+    task_supervisor::{ExitPolicy, TaskSupervisor},
+    topics::{
+        azimuth_commanded_state::AzimuthCommandedState,
+        azimuth_in_position::AzimuthInPosition,
+        azimuth_state::AzimuthState,
+        dropout_door_commanded_state::DropoutDoorCommandedState,
+        log_message::{LogMessage, WARNING_LEVEL},
+        main_door_commanded_state::MainDoorCommandedState,
+        move_azimuth::MoveAzimuth,
+        move_shutter_dropout_door::MoveShutterDropoutDoor,
+        move_shutter_main_door::MoveShutterMainDoor,
+        position::Position,
+        set_log_level::SetLogLevel,
+        settings_applied_dome_controller::SettingsAppliedDomeController,
+        shutter_in_position::ShutterInPosition,
+        telemetry::ATDomeTelemetry,
+        weather_sensors::WeatherSensors,
+    },
+    version::LONG_VERSION,
+};
+use std::{
+    collections::HashMap,
+    fmt,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use apache_avro::{from_value, types::Value};
+
+use tokio::{
+    sync::{mpsc, oneshot, watch},
+    task,
+    time::{sleep, timeout, Duration},
+};
+
+use handle_command::handle_command;
+use salobj::{
+    controller::Controller,
+    csc::base_csc::{BaseCSC, HEARTBEAT_TIME},
+    domain::Domain,
+    error::errors::SalObjResult,
+    generics::{
+        disable::Disable, empty_topic::EmptyTopic, enable::Enable, error_code::ErrorCode,
+        exit_control::ExitControl, heartbeat::Heartbeat, software_versions::SoftwareVersions,
+        standby::Standby, start::Start, summary_state::SummaryState,
+    },
+    remote::Remote,
+    sal_enums::State,
+    sal_info::SalInfo,
+    topics::{
+        base_sal_topic::BaseSALTopic, controller_command::ControllerCommand,
+        controller_command_ack::ControllerCommandAck, write_topic::WriteTopic,
+    },
+    utils::{command_ack::CommandAck, types::WriteTopicSet},
+};
+
+/// SAL component name and index of the CSC; ATDome is not indexed.
+const SAL_NAME: &str = "ATDome";
+const SAL_INDEX: isize = 0;
+
+/// Initial delay before retrying a failed heartbeat write.
+const HEARTBEAT_RETRY_MIN_DELAY: Duration = Duration::from_millis(100);
+/// Initial delay before retrying to reach the broker at startup.
+const BROKER_RETRY_MIN_DELAY: Duration = Duration::from_secs(1);
+
+/// Names of the supervised background tasks; command readers are named
+/// after their command, e.g. "command_reader:start".
+const HEARTBEAT_TASK: &str = "heartbeat";
+const TELEMETRY_TASK: &str = "telemetry";
+const STATUS_POLL_TASK: &str = "status_poll";
+const STATUS_WATCHDOG_TASK: &str = "status_watchdog";
+const COMMAND_READER_TASK_PREFIX: &str = "command_reader:";
+
+/// Define `HANDLED_COMMANDS` and `ATDome::dispatch_command` from a single
+/// list, so the commands acked as implemented are those dispatched.
+///
+/// `handle_command!` refers to `self`, `data` and `ack_channel`, so it is
+/// expanded in a method defined here rather than in `ATDome::run`.
+macro_rules! handled_commands {
+    ($($name:literal),* $(,)?) => {
+        /// Commands dispatched by `ATDome::run`, one at a time. Other
+        /// commands registered from the schema, except
+        /// `CONCURRENT_COMMANDS`, are acked as not implemented.
+        const HANDLED_COMMANDS: &[&str] = &[$($name),*];
+
+        impl ATDome<'_> {
+            /// Run the handler of `data`, one of `HANDLED_COMMANDS`, and
+            /// send its ack.
+            async fn dispatch_command(
+                &mut self,
+                data: CmdData,
+                ack_channel: mpsc::Sender<CommandAck>,
+            ) -> ATDomeResult<()> {
+                handle_command!($($name),*);
+                Ok(())
+            }
+        }
+    };
+}
+
+handled_commands!(
+    "start",
+    "standby",
+    "enable",
+    "disable",
+    "exitControl",
+    "moveAzimuth",
+    "homeAzimuth",
+    "openShutter",
+    "closeShutter",
+    "moveShutterMainDoor",
+    "moveShutterDropoutDoor",
+    "stopMotion",
+);
+
+/// Commands run by their reader as soon as they arrive instead of waiting
+/// for `ATDome::run` to finish the command in progress: they neither change
+/// the summary state nor move the dome, so they cannot conflict with it.
+const CONCURRENT_COMMANDS: [&str; 1] = ["setLogLevel"];
+
+/// Motion commands that cannot run while homing the azimuth. They are
+/// rejected, or queued again once homing finishes if
+/// `queue_motion_while_homing` is set.
+const HOMING_EXCLUSIVE_COMMANDS: [&str; 5] = [
+    "moveAzimuth",
+    "openShutter",
+    "closeShutter",
+    "moveShutterMainDoor",
+    "moveShutterDropoutDoor",
+];
+
+/// Internal command the status poll sends to close the shutter when a
+/// weather sensor trips; the data is the description of the trip.
+const WEATHER_CLOSE_COMMAND: &str = "weatherClose";
+/// Delay before a failed weather close is requested again, if the sensors
+/// still trip.
+const WEATHER_CLOSE_RETRY_DELAY: Duration = Duration::from_secs(10);
+/// Internal command the status watchdog sends when the controller status
+/// goes stale; the data is the description of the problem.
+const STATUS_STALE_COMMAND: &str = "statusStale";
+
+/// Time allowed to publish the queued warnings and acks when shutting down.
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(2);
+/// Time allowed to stop the dome when shutting down while enabled.
+const SHUTDOWN_STOP_TIMEOUT: Duration = Duration::from_secs(5);
+
+type CmdPayload = (CmdData, mpsc::Sender<CommandAck>);
+type CommandAckResult = (CommandAck, mpsc::Sender<CommandAck>);
+
+struct CmdData {
+    pub name: String,
+    pub data: Value,
+}
+
+#[derive(Default)]
+struct TelemetryPayload {
+    pub name: String,
+    pub data: ATDomeTelemetry,
+    /// Incremented on every sample, to count the samples overwritten
+    /// before they were published.
+    pub seq_num: u64,
+}
+
+/// Progress of the weather close for the current trip of the sensors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WeatherClose {
+    /// Not requested; the status poll requests it on a trip.
+    Idle,
+    /// Requested for the current trip.
+    Requested,
+    /// Failed; the status poll requests it again from this time on if the
+    /// sensors still trip.
+    RetryAt(Instant),
+}
+
+impl WeatherClose {
+    /// Should a trip request the close now?
+    fn is_due(&self) -> bool {
+        match self {
+            WeatherClose::Idle => true,
+            WeatherClose::Requested => false,
+            WeatherClose::RetryAt(retry_time) => Instant::now() >= *retry_time,
+        }
+    }
+}
+
+pub struct ATDome<'a> {
+    summary_state: State,
+    initial_state: State,
+    config: ATDomeConfig,
+    simulation_mode: bool,
+    mock_controller_task: Option<task::JoinHandle<ATDomeResult<()>>>,
+    model: Option<Arc<ATDomeModel>>,
+    /// Model of the last connection, reconnected on the next connect.
+    idle_model: Option<Arc<ATDomeModel>>,
+    homing_task: Option<task::JoinHandle<()>>,
+    /// Stops the homing monitor, failing its command with the reason.
+    homing_cancel: Option<oneshot::Sender<String>>,
+    homing: Arc<watch::Sender<bool>>,
+    /// Progress of the weather close, shared by the status poll and the
+    /// close.
+    weather_close: Arc<Mutex<WeatherClose>>,
+    /// When the homing monitor gives up on the homing in progress.
+    homing_deadline: Option<Instant>,
+    /// Tasks holding motion commands back until homing finishes.
+    held_commands: Vec<task::JoinHandle<()>>,
+    /// Fails the held motion commands with the reason sent.
+    held_cancel: watch::Sender<String>,
+    /// Released by `run` once the ack returned by the current command
+    /// handler is sent, so the acks of a task the handler spawned follow
+    /// it.
+    ack_release: Option<oneshot::Sender<()>>,
+    exit_requested: bool,
+    fault_error_code: Option<i32>,
+    commanded_targets: watch::Sender<CommandedTargets>,
+    pending_motions: HashMap<MotionAxis, PendingMotion>,
+    warning_sender: mpsc::Sender<String>,
+    warning_receiver: Option<mpsc::Receiver<String>>,
+    warning_task: Option<task::JoinHandle<()>>,
+    metrics: Arc<ATDomeMetrics>,
+    metrics_task: Option<task::JoinHandle<ATDomeResult<()>>>,
+    engineering_status: Arc<watch::Sender<EngineeringStatus>>,
+    engineering_server_task: Option<task::JoinHandle<ATDomeResult<()>>>,
+    domain: Domain,
+    controller: Controller<'a>,
+    controller_command_ack: Option<ControllerCommandAck>,
+    ack_sender: Option<mpsc::Sender<CommandAck>>,
+    ack_task: Option<task::JoinHandle<()>>,
+    tasks: TaskSupervisor,
+    command_sender: mpsc::Sender<CmdPayload>,
+    command_receiver: mpsc::Receiver<CmdPayload>,
+    telemetry_sender: Arc<watch::Sender<TelemetryPayload>>,
+    telemetry_receiver: watch::Receiver<TelemetryPayload>,
+}
+
+impl<'a> ATDome<'a> {
+    /// Create a new instance of the CSC.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - CSC configuration.
+    /// * `simulation_mode` - Run a mock controller in the background and
+    ///   connect to it instead of the real hardware.
+    /// * `initial_state` - Summary state the CSC transitions to when
+    ///   started: Standby, Disabled or Enabled. The CSC runs the same
+    ///   sequence as the start and enable commands to get there.
+    pub fn new(
+        config: ATDomeConfig,
+        simulation_mode: bool,
+        initial_state: State,
+    ) -> ATDomeResult<ATDome<'a>> {
+        config.validate()?;
+        let mut domain = Domain::new();
+        let controller = Controller::new(&mut domain, SAL_NAME, SAL_INDEX).map_err(sal_error)?;
+        let (command_sender, command_receiver): (
+            mpsc::Sender<CmdPayload>,
+            mpsc::Receiver<CmdPayload>,
+        ) = mpsc::channel(COMMAND_QUEUE_CAPACITY);
+
+        let (warning_sender, warning_receiver) = mpsc::channel(32);
+
+        let (telemetry_sender, telemetry_receiver): (
+            watch::Sender<TelemetryPayload>,
+            watch::Receiver<TelemetryPayload>,
+        ) = watch::channel(TelemetryPayload::default());
+
+        Ok(ATDome {
+            summary_state: State::Standby,
+            initial_state,
+            config,
+            simulation_mode,
+            mock_controller_task: None,
+            model: None,
+            idle_model: None,
+            homing_task: None,
+            homing_cancel: None,
+            homing: Arc::new(watch::Sender::new(false)),
+            weather_close: Arc::new(Mutex::new(WeatherClose::Idle)),
+            homing_deadline: None,
+            held_commands: Vec::new(),
+            held_cancel: watch::Sender::new(String::new()),
+            ack_release: None,
+            exit_requested: false,
+            fault_error_code: None,
+            commanded_targets: watch::Sender::new(CommandedTargets::default()),
+            pending_motions: HashMap::new(),
+            warning_sender,
+            warning_receiver: Some(warning_receiver),
+            warning_task: None,
+            metrics: Arc::new(ATDomeMetrics::new()?),
+            metrics_task: None,
+            engineering_status: Arc::new(watch::Sender::new(EngineeringStatus::default())),
+            engineering_server_task: None,
+            domain,
+            controller,
+            controller_command_ack: None,
+            ack_sender: None,
+            ack_task: None,
+            tasks: TaskSupervisor::new(),
+            command_sender,
+            command_receiver,
+            telemetry_sender: Arc::new(telemetry_sender),
+            telemetry_receiver,
+        })
+    }
+
+    /// Start the CSC.
+    ///
+    /// This method should run only once after instantiating the CSC and will
+    /// setup a series of background tasks that operates the CSC.
+    pub async fn start(&mut self) -> ATDomeResult<()> {
+        let sal_info = sal_info()?;
+
+        self.connect_to_broker(&sal_info).await;
+
+        self.check_for_duplicate_instance(&sal_info).await?;
+
+        self.update_summary_state().await?;
+        self.publish_software_versions().await?;
+
+        if let Some(metrics_address) = &self.config.metrics_address {
+            let metrics = self.metrics.clone();
+            let metrics_address = metrics_address.to_owned();
+            self.metrics_task = Some(task::spawn(async move {
+                serve_metrics(metrics, &metrics_address).await
+            }));
+        }
+
+        if let Some(engineering_address) = &self.config.engineering_address {
+            let engineering_status = self.engineering_status.subscribe();
+            let engineering_address = engineering_address.to_owned();
+            self.engineering_server_task = Some(task::spawn(async move {
+                serve_engineering_status(engineering_status, &engineering_address).await
+            }));
+        }
+
+        if self.simulation_mode {
+            tracing::info!(
+                "Starting mock controller on {}:{}.",
+                self.config.host,
+                self.config.port
+            );
+            let mock_controller_config = MockControllerConfig {
+                host: self.config.host.to_owned(),
+                port: self.config.port,
+                ..Default::default()
+            };
+            self.mock_controller_task =
+                Some(task::spawn(run_mock_controller(mock_controller_config)));
+        }
+
+        self.start_heartbeat(&sal_info);
+        self.start_warning_publisher(&sal_info);
+
+        self.start_ack_forwarder(&sal_info).await;
+
+        for command in sal_info.get_command_names() {
+            self.start_command_reader(&command, &sal_info)?;
+        }
+
+        self.go_to_initial_state().await
+    }
+
+    /// Register the schemas and topics, retrying until the broker is
+    /// reachable.
+    ///
+    /// Brokers often come up after the CSC when they are deployed together,
+    /// so failures are retried with an exponential backoff, capped at
+    /// `broker_retry_max_delay`, instead of stopping the CSC.
+    async fn connect_to_broker(&mut self, sal_info: &SalInfo) {
+        let max_delay = Duration::try_from_secs_f32(self.config.broker_retry_max_delay)
+            .unwrap_or_default()
+            .max(BROKER_RETRY_MIN_DELAY);
+        let mut retry_delay = BROKER_RETRY_MIN_DELAY;
+        let mut attempt = 1;
+        loop {
+            sal_info.register_schema().await;
+            match self.domain.register_topics(&sal_info.get_topics_name()) {
+                Ok(_) => break,
+                Err(error) => {
+                    tracing::warn!(
+                        "Failed to register the topics with the broker (attempt {attempt}): \
+                         {error:?}; retrying in {retry_delay:?}."
+                    );
+                    sleep(retry_delay).await;
+                    retry_delay = (retry_delay * 2).min(max_delay);
+                    attempt += 1;
+                }
+            }
+        }
+        if attempt > 1 {
+            tracing::info!("Registered the topics with the broker after {attempt} attempts.");
+        }
+    }
+
+    /// Fail if another ATDome CSC is running, so two CSCs never command the
+    /// same controller.
+    ///
+    /// Listens for heartbeats for `duplicate_check_time`: two of them mean
+    /// another CSC is alive, while a single one may be the last heartbeat
+    /// of a CSC that already exited, replayed to late joiners.
+    async fn check_for_duplicate_instance(&self, sal_info: &SalInfo) -> ATDomeResult<()> {
+        let check_time =
+            Duration::try_from_secs_f32(self.config.duplicate_check_time).unwrap_or_default();
+        if check_time.is_zero() {
+            return Ok(());
+        }
+        tracing::info!("Listening {check_time:?} for the heartbeat of another ATDome CSC.");
+
+        let mut domain = Domain::new();
+        let mut remote = Remote::new(&mut domain, sal_info);
+        remote.start().await;
+        let listening = async {
+            let mut heartbeats = 0;
+            loop {
+                match remote.next_event("logevent_heartbeat").await {
+                    Ok(_) => {
+                        heartbeats += 1;
+                        if heartbeats >= 2 {
+                            return true;
+                        }
+                    }
+                    Err(error) => {
+                        tracing::warn!("Failed to read the heartbeat: {error:?}.");
+                        return false;
+                    }
+                }
+            }
+        };
+        if timeout(check_time, listening).await.unwrap_or(false) {
+            return Err(ATDomeError::InvalidState(
+                "Another ATDome CSC is publishing its heartbeat; refusing to start.".to_owned(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Transition from Standby to the initial state requested on creation.
+    ///
+    /// Goes to Fault if the transition fails, e.g. if the controller is
+    /// not reachable.
+    async fn go_to_initial_state(&mut self) -> ATDomeResult<()> {
+        if !matches!(self.initial_state, State::Disabled | State::Enabled) {
+            return Ok(());
+        }
+        tracing::info!(
+            "Transitioning to the initial state {:?}.",
+            self.initial_state
+        );
+        if let Err(error) = self.transition(StateTransition::Start).await {
+            return self.fault(&error).await;
+        }
+        if self.initial_state == State::Enabled {
+            if let Err(error) = self.transition(StateTransition::Enable).await {
+                return self.fault(&error).await;
+            }
+        }
+        Ok(())
+    }
+
+    /// Run a summary state transition.
+    ///
+    /// The steps that can prevent the transition run first and leave the
+    /// state unchanged if they fail; the new state is then published and
+    /// `handle_summary_state` reacts to it.
+    async fn transition(&mut self, transition: StateTransition) -> ATDomeResult<()> {
+        let old_state = self.get_current_state();
+        let new_state = transition.check(old_state)?;
+        match transition {
+            StateTransition::Start => self.begin_start().await?,
+            StateTransition::Disable => self.begin_disable().await?,
+            _ => {}
+        }
+        self.set_summary_state(new_state);
+        self.update_summary_state().await?;
+        self.handle_summary_state(old_state, new_state).await
+    }
+
+    /// Connect to the controller, as part of the transition from Standby to
+    /// Disabled.
+    async fn begin_start(&mut self) -> ATDomeResult<()> {
+        self.connect().await.map_err(|error| {
+            tracing::error!("Failed to connect to the controller: {error}");
+            error
+        })
+    }
+
+    /// Stop the dome, as part of the transition from Enabled to Disabled.
+    ///
+    /// The CSC is disabled even if the dome cannot be stopped, e.g. because
+    /// the controller stopped answering.
+    async fn begin_disable(&mut self) -> ATDomeResult<()> {
+        self.cancel_homing("Aborted by disable.");
+        if let Err(error) = self.stop_motion_if_moving().await {
+            tracing::error!("Failed to stop dome motion; disabling anyway: {error}");
+        }
+        Ok(())
+    }
+
+    /// React to a change of summary state.
+    ///
+    /// Starts and stops the telemetry loop, drops the connection to the
+    /// controller and clears faults so the state commands do not have to.
+    async fn handle_summary_state(
+        &mut self,
+        old_state: State,
+        new_state: State,
+    ) -> ATDomeResult<()> {
+        match (old_state, new_state) {
+            (State::Standby, State::Disabled) => {
+                let sal_info = sal_info()?;
+                self.start_telemetry_loop(&sal_info);
+            }
+            (State::Enabled, State::Disabled) => {
+                for (_, pending_motion) in self.pending_motions.drain() {
+                    pending_motion.cancel("Aborted by disable.");
+                }
+            }
+            (_, State::Enabled) => {
+                // A trip while disabled did not close the shutter; close it
+                // on the next poll if the sensors still trip.
+                *self.weather_close.lock().unwrap() = WeatherClose::Idle;
+                if let Err(error) = self.apply_controller_settings().await {
+                    tracing::error!("Failed to apply the controller settings: {error}");
+                }
+                if self.config.home_on_enable {
+                    if let Err(error) = self.home_if_not_homed().await {
+                        tracing::error!("Failed to home the azimuth: {error}");
+                    }
+                }
+            }
+            (_, State::Standby) => {
+                // Tear down anything left from the previous session so the
+                // next start begins from a clean slate.
+                self.disconnect();
+                tracing::debug!("Stopping telemetry task.");
+                self.tasks.abort(TELEMETRY_TASK);
+                if old_state == State::Fault {
+                    self.clear_fault().await?;
+                }
+            }
+            (_, State::Offline) => self.exit_requested = true,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// This method runs the control loop of the CSC.
+    ///
+    /// Once awaited the CSC will start to respond to commands.
+    pub async fn run(&mut self) -> ATDomeResult<()> {
+        loop {
+            // Wake up at least once per heartbeat period so a dead
+            // heartbeat task is noticed even when no commands arrive.
+            let command = timeout(HEARTBEAT_TIME, self.command_receiver.recv()).await;
+            self.metrics
+                .channel_fill
+                .with_label_values(&[COMMAND_CHANNEL])
+                .set(queued(&self.command_sender) as i64);
+            self.supervise_tasks().await?;
+            let (data, ack_channel) = match command {
+                Ok(Some(command)) => command,
+                Ok(None) => break,
+                Err(_) => continue,
+            };
+            let command_name = data.name.to_owned();
+            if command_name == WEATHER_CLOSE_COMMAND {
+                if let Value::String(reason) = &data.data {
+                    self.close_for_weather(reason).await;
+                }
+                continue;
+            }
+            if command_name == STATUS_STALE_COMMAND {
+                if let Value::String(reason) = &data.data {
+                    self.fault_on_stale_status(reason).await?;
+                }
+                continue;
+            }
+            if !HANDLED_COMMANDS.contains(&command_name.as_str()) {
+                let error = ATDomeError::CommandRejected(format!(
+                    "Command {} is not implemented.",
+                    data.name
+                ));
+                reject_command(&data, &ack_channel, &error).await;
+                continue;
+            }
+            if self.config.queue_motion_while_homing
+                && self.is_homing()
+                && HOMING_EXCLUSIVE_COMMANDS.contains(&command_name.as_str())
+            {
+                self.requeue_after_homing(data, ack_channel);
+                continue;
+            }
+            let command_start = Instant::now();
+            self.dispatch_command(data, ack_channel).await?;
+            if let Some(ack_release) = self.ack_release.take() {
+                let _ = ack_release.send(());
+            }
+            self.metrics
+                .observe_command(&command_name, command_start.elapsed());
+            if self.exit_requested {
+                tracing::info!("Exit requested; stopping the CSC.");
+                self.stop_background_tasks();
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Interval between heartbeat events: the configured one, or the
+    /// salobj default if not set or not positive.
+    fn heartbeat_interval(&self) -> Duration {
+        match self.config.heartbeat_interval {
+            None => HEARTBEAT_TIME,
+            Some(interval) => match Duration::try_from_secs_f32(interval) {
+                Ok(interval) if !interval.is_zero() => interval,
+                _ => {
+                    tracing::warn!(
+                        "Invalid heartbeat interval {interval}; using {HEARTBEAT_TIME:?}."
+                    );
+                    HEARTBEAT_TIME
+                }
+            },
+        }
+    }
+
+    /// Spawn the task that publishes the heartbeat event.
+    ///
+    /// Failed writes are retried with an exponential backoff, capped at the
+    /// heartbeat period, instead of stopping the task.
+    fn start_heartbeat(&mut self, sal_info: &SalInfo) {
+        let mut heartbeat_writer = WriteTopic::new("logevent_heartbeat", sal_info, &self.domain);
+        let heartbeat_interval = self.heartbeat_interval();
+        tracing::info!("Publishing the heartbeat every {heartbeat_interval:?}.");
+
+        let heartbeat_task = async move {
+            // Only the timestamps and seq_num change between beats.
+            let mut heartbeat_topic = Heartbeat::default()
+                .with_sal_index(heartbeat_writer.get_index())
+                .with_private_origin(heartbeat_writer.get_origin())
+                .with_private_identity(&heartbeat_writer.get_identity());
+            let mut retry_delay = HEARTBEAT_RETRY_MIN_DELAY;
+            loop {
+                heartbeat_topic = heartbeat_topic
+                    .with_timestamps()
+                    .with_private_seq_num(heartbeat_writer.get_seq_num());
+                let write_res = heartbeat_writer
+                    .write_typed::<Heartbeat>(&heartbeat_topic)
+                    .await;
+                if write_res.is_err() {
+                    tracing::warn!(
+                        "Failed to write heartbeat data {write_res:?}; retrying in {retry_delay:?}."
+                    );
+                    sleep(retry_delay).await;
+                    retry_delay = (retry_delay * 2).min(heartbeat_interval);
+                    continue;
+                }
+                retry_delay = HEARTBEAT_RETRY_MIN_DELAY;
+                sleep(heartbeat_interval).await;
+            }
+        };
+
+        self.tasks
+            .spawn(HEARTBEAT_TASK, ExitPolicy::Restart, heartbeat_task);
+    }
+
+    /// Start publishing command acks.
+    ///
+    /// Acks go through a queue of `ACK_QUEUE_CAPACITY` acks so a slow
+    /// broker does not block their senders; no ack is ever dropped, the
+    /// senders wait if the queue is full.
+    async fn start_ack_forwarder(&mut self, sal_info: &SalInfo) {
+        let controller_command_ack = ControllerCommandAck::start(&self.domain, sal_info).await;
+        let (ack_sender, ack_task) = spawn_forwarder(
+            controller_command_ack.ack_sender.clone(),
+            ACK_QUEUE_CAPACITY,
+            self.metrics.channel_fill.with_label_values(&[ACK_CHANNEL]),
+        );
+        self.controller_command_ack = Some(controller_command_ack);
+        self.ack_sender = Some(ack_sender);
+        self.ack_task = Some(ack_task);
+    }
+
+    /// Spawn the task that reads `command` and forwards it to the control
+    /// loop, or runs it right away if it is one of `CONCURRENT_COMMANDS`.
+    ///
+    /// If the control loop already has `COMMAND_QUEUE_CAPACITY` commands
+    /// queued the command is rejected as busy instead of waiting.
+    fn start_command_reader(&mut self, command: &str, sal_info: &SalInfo) -> ATDomeResult<()> {
+        let Some(ack_sender) = self.ack_sender.clone() else {
+            return Err(ATDomeError::Internal(
+                "Command acks must be started before the command readers.".to_owned(),
+            ));
+        };
+        tracing::debug!("Registering command {command}.");
+        let command_sender = self.command_sender.clone();
+        let command_fill = self
+            .metrics
+            .channel_fill
+            .with_label_values(&[COMMAND_CHANNEL]);
+        let command_dropped = self
+            .metrics
+            .channel_dropped
+            .with_label_values(&[COMMAND_CHANNEL]);
+        let mut controller_command =
+            ControllerCommand::new(command, &self.domain, sal_info).map_err(sal_error)?;
+        let name = command.to_owned();
+        let concurrent = CONCURRENT_COMMANDS.contains(&command);
+        let metrics = self.metrics.clone();
+
+        let command_reader_task = async move {
+            loop {
+                let Ok(command_data) = controller_command.process_command().await else {
+                    continue;
+                };
+                let data = CmdData {
+                    name: name.to_owned(),
+                    data: command_data,
+                };
+                if concurrent {
+                    let command_start = Instant::now();
+                    send_ack(&ack_sender, run_concurrent_command(&data)).await;
+                    metrics.observe_command(&name, command_start.elapsed());
+                    continue;
+                }
+                let payload = (data, ack_sender.clone());
+                match command_sender.try_send(payload) {
+                    Ok(()) => command_fill.set(queued(&command_sender) as i64),
+                    Err(mpsc::error::TrySendError::Full((data, ack_channel))) => {
+                        command_dropped.inc();
+                        let error = ATDomeError::Busy(format!(
+                            "The CSC is busy; {COMMAND_QUEUE_CAPACITY} commands are already queued."
+                        ));
+                        reject_command(&data, &ack_channel, &error).await;
+                    }
+                    Err(mpsc::error::TrySendError::Closed((data, ack_channel))) => {
+                        let error = ATDomeError::Busy("The CSC is shutting down.".to_owned());
+                        reject_command(&data, &ack_channel, &error).await;
+                    }
+                }
+            }
+        };
+
+        self.tasks.spawn(
+            &format!("{COMMAND_READER_TASK_PREFIX}{command}"),
+            ExitPolicy::Restart,
+            command_reader_task,
+        );
+        Ok(())
+    }
+
+    /// Spawn the task that publishes the latest telemetry once per second,
+    /// starting with the first sample.
+    fn start_telemetry_loop(&mut self, sal_info: &SalInfo) {
+        let mut telemetry_writers: WriteTopicSet = sal_info
+            .get_telemetry_names()
+            .into_iter()
+            .map(|telemetry_name| {
+                (
+                    telemetry_name.to_owned(),
+                    WriteTopic::new(&telemetry_name, sal_info, &self.domain),
+                )
+            })
+            .collect();
+
+        let mut telemetry_received = self.telemetry_receiver.clone();
+        let telemetry_dropped = self
+            .metrics
+            .channel_dropped
+            .with_label_values(&[TELEMETRY_CHANNEL]);
+
+        let telemetry_loop_task = async move {
+            tracing::debug!("Telemetry task starting");
+
+            // Empty until the first status has been parsed, so a made up
+            // position is never published. A restarted loop gets the latest
+            // sample right away, since this receiver has not seen it yet.
+            let mut telemetry_data: HashMap<String, ATDomeTelemetry> = HashMap::new();
+            let mut last_seq_num = telemetry_received.borrow().seq_num;
+
+            loop {
+                let loop_time_task = task::spawn(async { sleep(Duration::from_secs(1)).await });
+
+                if timeout(Duration::from_secs(1), telemetry_received.changed())
+                    .await
+                    .is_ok()
+                {
+                    let new_telemetry = telemetry_received.borrow();
+                    tracing::debug!("Updating telemetry data for {}", new_telemetry.name);
+                    telemetry_dropped
+                        .inc_by(new_telemetry.seq_num.saturating_sub(last_seq_num + 1));
+                    last_seq_num = new_telemetry.seq_num;
+                    *telemetry_data
+                        .entry(new_telemetry.name.to_owned())
+                        .or_insert(ATDomeTelemetry::None) = new_telemetry.data.clone();
+                } else {
+                    tracing::trace!("Telemetry not updated.");
+                }
+
+                for (telemetry_name, telemetry_writer) in telemetry_writers.iter_mut() {
+                    let name = telemetry_name.as_str();
+                    if let Some(telemetry_data_to_write) = telemetry_data.get_mut(name) {
+                        match telemetry_data_to_write {
+                            ATDomeTelemetry::Position(position) => {
+                                if let Err(err) =
+                                    telemetry_writer.write_typed::<Position>(position).await
+                                {
+                                    tracing::warn!("Failed to write {name}: {err:?}");
+                                }
+                            }
+                            ATDomeTelemetry::None => {}
+                        }
+                    }
+                }
+                if let Err(error) = loop_time_task.await {
+                    tracing::error!("Telemetry loop timer failed: {}", ATDomeError::from(error));
+                }
+            }
+        };
+
+        self.tasks
+            .spawn(TELEMETRY_TASK, ExitPolicy::Restart, telemetry_loop_task);
+    }
+
+    /// Spawn the task that publishes warnings from background tasks as
+    /// logMessage events.
+    fn start_warning_publisher(&mut self, sal_info: &SalInfo) {
+        let Some(mut warning_receiver) = self.warning_receiver.take() else {
+            return;
+        };
+        let mut log_message_writer = WriteTopic::new("logevent_logMessage", sal_info, &self.domain);
+
+        self.warning_task = Some(task::spawn(async move {
+            while let Some(warning) = warning_receiver.recv().await {
+                let log_message = LogMessage::default()
+                    .with_timestamps()
+                    .with_sal_index(log_message_writer.get_index())
+                    .with_private_origin(log_message_writer.get_origin())
+                    .with_private_identity(&log_message_writer.get_identity())
+                    .with_private_seq_num(log_message_writer.get_seq_num())
+                    .with_name(SAL_NAME)
+                    .with_level(WARNING_LEVEL)
+                    .with_message(&warning)
+                    .with_process(std::process::id() as i32);
+                if let Err(err) = log_message_writer
+                    .write_typed::<LogMessage>(&log_message)
+                    .await
+                {
+                    tracing::error!("Failed to write log message: {err:?}");
+                }
+            }
+        }));
+    }
+
+    /// Restart or fault on the background tasks that exited unexpectedly.
+    async fn supervise_tasks(&mut self) -> ATDomeResult<()> {
+        for task_exit in self.tasks.check().await {
+            match task_exit.policy {
+                ExitPolicy::Restart => {
+                    tracing::warn!(task = %task_exit.name, "Restarting background task.");
+                    self.restart_task(&task_exit.name)?;
+                }
+                ExitPolicy::Fault => {
+                    if self.get_current_state() != State::Fault {
+                        self.fault(&ATDomeError::Internal(format!(
+                            "Background task {} exited unexpectedly: {}.",
+                            task_exit.name, task_exit.reason
+                        )))
+                        .await?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Start the supervised task `name` again.
+    fn restart_task(&mut self, name: &str) -> ATDomeResult<()> {
+        let sal_info = sal_info()?;
+        match name {
+            HEARTBEAT_TASK => self.start_heartbeat(&sal_info),
+            TELEMETRY_TASK => self.start_telemetry_loop(&sal_info),
+            _ => match name.strip_prefix(COMMAND_READER_TASK_PREFIX) {
+                Some(command) => self.start_command_reader(command, &sal_info)?,
+                None => tracing::error!(task = %name, "Don't know how to restart task."),
+            },
+        }
+        Ok(())
+    }
+
+    /// Stop the CSC and release everything it owns.
+    ///
+    /// Stops the dome if the CSC is enabled, publishes the Offline summary
+    /// state, disconnects from the controller, stops the background tasks
+    /// and waits for them to finish, publishes the warnings still queued
+    /// and drops the SAL controller.
+    pub async fn shutdown(mut self) -> ATDomeResult<()> {
+        tracing::info!("Shutting down CSC.");
+        if self.get_current_state() == State::Enabled {
+            match timeout(SHUTDOWN_STOP_TIMEOUT, self.stop_motion_if_moving()).await {
+                Ok(Ok(())) => {}
+                Ok(Err(error)) => tracing::error!("Failed to stop dome motion: {error}"),
+                Err(_) => tracing::error!("Timed out stopping dome motion."),
+            }
+        }
+        let result = if self.get_current_state() != State::Offline {
+            self.set_summary_state(State::Offline);
+            self.update_summary_state().await
+        } else {
+            Ok(())
+        };
+
+        self.disconnect();
+        self.tasks.shutdown().await;
+        for held_command in self.held_commands.drain(..) {
+            held_command.abort();
+        }
+
+        // Close the warning and ack channels so their tasks exit once the
+        // queued messages are written.
+        self.warning_sender = mpsc::channel(1).0;
+        if let Some(warning_task) = self.warning_task.take() {
+            flush_task(warning_task, "warnings").await;
+        }
+        self.ack_sender = None;
+        if let Some(ack_task) = self.ack_task.take() {
+            flush_task(ack_task, "acks").await;
+        }
+        self.controller_command_ack = None;
+
+        for background_task in [
+            self.mock_controller_task.take(),
+            self.metrics_task.take(),
+            self.engineering_server_task.take(),
+        ]
+        .into_iter()
+        .flatten()
+        {
+            background_task.abort();
+            match background_task.await {
+                Ok(Err(error)) => tracing::warn!("Background task failed: {error}"),
+                Err(error) if error.is_panic() => {
+                    tracing::error!("{}", ATDomeError::from(error))
+                }
+                _ => {}
+            }
+        }
+        tracing::info!("CSC shut down.");
+        result
+    }
+
+    /// Abort all tasks spawned by the CSC.
+    fn stop_background_tasks(&mut self) {
+        self.tasks.abort_all();
+        for background_task in [self.homing_task.take(), self.warning_task.take()]
+            .into_iter()
+            .flatten()
+        {
+            background_task.abort();
+        }
+        for (_, pending_motion) in self.pending_motions.drain() {
+            pending_motion.abort();
+        }
+        for held_command in self.held_commands.drain(..) {
+            held_command.abort();
+        }
+        self.model = None;
+        self.idle_model = None;
+        for background_task in [
+            self.mock_controller_task.take(),
+            self.metrics_task.take(),
+            self.engineering_server_task.take(),
+        ]
+        .into_iter()
+        .flatten()
+        {
+            background_task.abort();
+        }
+    }
+
+    /// Respond to the start command.
+    ///
+    /// This will transition the CSC from Standby to Disabled.
+    #[tracing::instrument(skip_all, fields(command = %data.name))]
+    async fn do_start(
+        &mut self,
+        data: &CmdData,
+        ack_channel: mpsc::Sender<CommandAck>,
+    ) -> ATDomeResult<CommandAckResult> {
+        tracing::info!("do_start received {:?}", data.name);
+        let start = from_value::<Start>(&data.data).unwrap();
+        if self.get_current_state() == State::Standby {
+            if let Err(error) = self.configure(&start) {
+                return Ok((
+                    transition_ack(start, StateTransition::Start, Err(error.into())),
+                    ack_channel,
+                ));
+            }
+        }
+        let result = self.transition(StateTransition::Start).await;
+        Ok((
+            transition_ack(start, StateTransition::Start, result),
+            ack_channel,
+        ))
+    }
+
+    /// Respond to the disable command.
+    ///
+    /// This command will transition the CSC from Enabled to Disabled.
+    #[tracing::instrument(skip_all, fields(command = %data.name))]
+    async fn do_disable(
+        &mut self,
+        data: &CmdData,
+        ack_channel: mpsc::Sender<CommandAck>,
+    ) -> ATDomeResult<CommandAckResult> {
+        tracing::info!("do_disabled received {:?}", data.name);
+        let disable = from_value::<Disable>(&data.data).unwrap();
+        let result = self.transition(StateTransition::Disable).await;
+        Ok((
+            transition_ack(disable, StateTransition::Disable, result),
+            ack_channel,
+        ))
+    }
+
+    #[tracing::instrument(skip_all, fields(command = %data.name))]
+    async fn do_enable(
+        &mut self,
+        data: &CmdData,
+        ack_channel: mpsc::Sender<CommandAck>,
+    ) -> ATDomeResult<CommandAckResult> {
+        tracing::info!("do_enable received {:?}", data.name);
+        let enable = from_value::<Enable>(&data.data).unwrap();
+        let result = self.transition(StateTransition::Enable).await;
+        Ok((
+            transition_ack(enable, StateTransition::Enable, result),
+            ack_channel,
+        ))
+    }
+
+    /// Respond to the standby command.
+    ///
+    /// This command will transition the CSC from Fault or Disabled into
+    /// Standby. Coming from Fault it also clears the errorCode event.
+    #[tracing::instrument(skip_all, fields(command = %data.name))]
+    async fn do_standby(
+        &mut self,
+        data: &CmdData,
+        ack_channel: mpsc::Sender<CommandAck>,
+    ) -> ATDomeResult<CommandAckResult> {
+        tracing::info!("do_standby received {:?}", data.name);
+        let standby = from_value::<Standby>(&data.data).unwrap();
+        let result = self.transition(StateTransition::Standby).await;
+        Ok((
+            transition_ack(standby, StateTransition::Standby, result),
+            ack_channel,
+        ))
+    }
+
+    /// Respond to the exitControl command.
+    ///
+    /// If the CSC is in Standby, this will terminate the CSC execution: the
+    /// command loop stops after acknowledging the command, the background
+    /// tasks are aborted and `run` returns.
+    #[tracing::instrument(skip_all, fields(command = %data.name))]
+    async fn do_exit_control(
+        &mut self,
+        data: &CmdData,
+        ack_channel: mpsc::Sender<CommandAck>,
+    ) -> ATDomeResult<CommandAckResult> {
+        let exit_control = from_value::<ExitControl>(&data.data).unwrap();
+        let result = self.transition(StateTransition::ExitControl).await;
+        Ok((
+            transition_ack(exit_control, StateTransition::ExitControl, result),
+            ack_channel,
+        ))
+    }
+
+    /// Connect to the controller and start polling its status.
+    async fn connect(&mut self) -> ATDomeResult<()> {
+        self.disconnect();
+        tracing::info!(
+            "Connecting to the controller on {}:{}.",
+            self.config.host,
+            self.config.port
+        );
+        let min_command_interval = Duration::from_secs_f32(self.config.min_command_interval);
+        let stale_status_timeout = Duration::from_secs_f32(self.config.stale_status_timeout);
+        // Reuse the model of the previous connection unless the new
+        // configuration changes how it writes commands or watches the
+        // status.
+        let idle_model = self.idle_model.take().filter(|model| {
+            model.min_command_interval() == min_command_interval
+                && model.stale_status_timeout() == Some(stale_status_timeout)
+        });
+        let connecting = async {
+            match &idle_model {
+                Some(model) => model
+                    .reconnect(&self.config.host, self.config.port)
+                    .await
+                    .map(|_| model.clone()),
+                None => ATDomeModel::create_and_start(
+                    &self.config.host,
+                    self.config.port,
+                    32,
+                    min_command_interval,
+                )
+                .await
+                .map(|model| Arc::new(model.with_stale_status_timeout(stale_status_timeout))),
+            }
+        };
+        let result = match timeout(
+            Duration::from_secs_f32(self.config.connection_timeout),
+            connecting,
+        )
+        .await
+        {
+            Ok(result) => result,
+            Err(_) => Err(ATDomeError::Timeout(format!(
+                "No connection to {}:{} after {}s.",
+                self.config.host, self.config.port, self.config.connection_timeout
+            ))),
+        };
+        let model = match result {
+            Ok(model) => model,
+            Err(error) => {
+                self.idle_model = idle_model;
+                return Err(error);
+            }
+        };
+        self.model = Some(model.clone());
+        self.engineering_status
+            .send_modify(|engineering_status| engineering_status.connected = true);
+        let mut healthy = model.subscribe_health();
+
+        let poll_interval = Duration::from_secs_f32(self.config.status_poll_interval);
+        let read_timeout = Duration::from_secs_f32(self.config.read_timeout);
+        let metrics = self.metrics.clone();
+        let engineering_status = self.engineering_status.clone();
+        let telemetry_sender = self.telemetry_sender.clone();
+        let commanded_targets = self.commanded_targets.subscribe();
+        let mut dome_in_position = DomeInPosition::new(self.config.azimuth_hysteresis);
+        let close_on_weather_trip = self.config.close_on_weather_trip;
+        let command_sender = self.command_sender.clone();
+        let weather_close = self.weather_close.clone();
+        *weather_close.lock().unwrap() = WeatherClose::Idle;
+        let sal_info = sal_info()?;
+        let mut azimuth_in_position_writer =
+            WriteTopic::new("logevent_azimuthInPosition", &sal_info, &self.domain);
+        let mut shutter_in_position_writer =
+            WriteTopic::new("logevent_shutterInPosition", &sal_info, &self.domain);
+        let mut weather_sensors_writer =
+            WriteTopic::new("logevent_weatherSensors", &sal_info, &self.domain);
+        let mut published_sensor_flags = None;
+        let status_poll_task = async move {
+            loop {
+                match timeout(read_timeout, model.get_status()).await {
+                    Ok(Ok(status)) => {
+                        engineering_status.send_modify(|engineering_status| {
+                            engineering_status.update_status(status)
+                        });
+                        telemetry_sender.send_modify(|telemetry| {
+                            telemetry.name = "position".to_owned();
+                            telemetry.data = ATDomeTelemetry::Position(Position::from(&status));
+                            telemetry.seq_num += 1;
+                        });
+
+                        {
+                            let mut weather_close = weather_close.lock().unwrap();
+                            match (status.weather_trip(), *weather_close) {
+                                (Some(reason), state) if state.is_due() => {
+                                    tracing::warn!("{reason}.");
+                                    // Retry on the next poll if the request
+                                    // could not be queued.
+                                    if !close_on_weather_trip
+                                        || request_internal_command(
+                                            &command_sender,
+                                            WEATHER_CLOSE_COMMAND,
+                                            reason,
+                                        )
+                                    {
+                                        *weather_close = WeatherClose::Requested;
+                                    }
+                                }
+                                (None, state) if state != WeatherClose::Idle => {
+                                    tracing::info!("Weather sensors clear.");
+                                    *weather_close = WeatherClose::Idle;
+                                }
+                                _ => {}
+                            }
+                        }
+
+                        let targets = *commanded_targets.borrow();
+                        if let Some(in_position) =
+                            dome_in_position.update_azimuth(&status, &targets)
+                        {
+                            tracing::info!(
+                                in_position,
+                                az_pos = status.az_pos,
+                                "Azimuth in position changed."
+                            );
+                            let azimuth_in_position = AzimuthInPosition::default()
+                                .with_timestamps()
+                                .with_sal_index(azimuth_in_position_writer.get_index())
+                                .with_private_origin(azimuth_in_position_writer.get_origin())
+                                .with_private_identity(&azimuth_in_position_writer.get_identity())
+                                .with_private_seq_num(azimuth_in_position_writer.get_seq_num())
+                                .with_in_position(in_position);
+                            if let Err(err) = azimuth_in_position_writer
+                                .write_typed::<AzimuthInPosition>(&azimuth_in_position)
+                                .await
+                            {
+                                tracing::error!("Failed to write azimuth in position: {err:?}");
+                            }
+                        }
+                        if let Some(in_position) =
+                            dome_in_position.update_shutter(&status, &targets)
+                        {
+                            tracing::info!(in_position, "Shutter in position changed.");
+                            let shutter_in_position = ShutterInPosition::default()
+                                .with_timestamps()
+                                .with_sal_index(shutter_in_position_writer.get_index())
+                                .with_private_origin(shutter_in_position_writer.get_origin())
+                                .with_private_identity(&shutter_in_position_writer.get_identity())
+                                .with_private_seq_num(shutter_in_position_writer.get_seq_num())
+                                .with_in_position(in_position);
+                            if let Err(err) = shutter_in_position_writer
+                                .write_typed::<ShutterInPosition>(&shutter_in_position)
+                                .await
+                            {
+                                tracing::error!("Failed to write shutter in position: {err:?}");
+                            }
+                        }
+
+                        let sensor_flags = status.sensor_flags();
+                        if published_sensor_flags != Some(sensor_flags) {
+                            tracing::info!(?sensor_flags, "Weather sensors changed.");
+                            let weather_sensors = WeatherSensors::default()
+                                .with_timestamps()
+                                .with_sal_index(weather_sensors_writer.get_index())
+                                .with_private_origin(weather_sensors_writer.get_origin())
+                                .with_private_identity(&weather_sensors_writer.get_identity())
+                                .with_private_seq_num(weather_sensors_writer.get_seq_num())
+                                .with_flags(&sensor_flags);
+                            // Published again on the next poll if it fails.
+                            match weather_sensors_writer
+                                .write_typed::<WeatherSensors>(&weather_sensors)
+                                .await
+                            {
+                                Ok(_) => published_sensor_flags = Some(sensor_flags),
+                                Err(err) => {
+                                    tracing::error!("Failed to write weather sensors: {err:?}")
+                                }
+                            }
+                        }
+                    }
+                    Ok(Err(error)) => {
+                        metrics.status_poll_failures.inc();
+                        tracing::warn!("Failed to get controller status: {error}");
+                    }
+                    Err(_) => {
+                        metrics.status_poll_failures.inc();
+                        let executing_command = model.executing_command();
+                        tracing::warn!(
+                            queued_commands = model.queued_commands(),
+                            executing_command = ?executing_command
+                                .as_ref()
+                                .map(|executing| &executing.command),
+                            executing_for = ?executing_command
+                                .as_ref()
+                                .map(|executing| executing.elapsed()),
+                            "Timed out waiting for controller status."
+                        );
+                    }
+                }
+                let raw_status = model.last_raw_status();
+                let connection_stats = model.connection_stats();
+                metrics.set_connection_stats(&connection_stats);
+                engineering_status.send_modify(|engineering_status| {
+                    engineering_status.raw_status = raw_status;
+                    engineering_status.connection_stats = Some(connection_stats);
+                });
+                sleep(poll_interval).await;
+            }
+        };
+        self.tasks
+            .spawn(STATUS_POLL_TASK, ExitPolicy::Fault, status_poll_task);
+
+        let command_sender = self.command_sender.clone();
+        let status_watchdog_task = async move {
+            loop {
+                if healthy.wait_for(|healthy| !healthy).await.is_err() {
+                    return;
+                }
+                request_internal_command(
+                    &command_sender,
+                    STATUS_STALE_COMMAND,
+                    format!("No valid status from the controller for {stale_status_timeout:?}"),
+                );
+                if healthy.wait_for(|healthy| *healthy).await.is_err() {
+                    return;
+                }
+            }
+        };
+        self.tasks.spawn(
+            STATUS_WATCHDOG_TASK,
+            ExitPolicy::Fault,
+            status_watchdog_task,
+        );
+        Ok(())
+    }
+
+    /// Stop polling the controller status and close the connection.
+    fn disconnect(&mut self) {
+        self.tasks.abort(STATUS_POLL_TASK);
+        self.tasks.abort(STATUS_WATCHDOG_TASK);
+        self.cancel_homing("Disconnected from the controller.");
+        for (_, pending_motion) in self.pending_motions.drain() {
+            pending_motion.cancel("Disconnected from the controller.");
+        }
+        if let Some(model) = self.model.take() {
+            model.disconnect();
+            self.idle_model = Some(model);
+            tracing::info!("Disconnected from the controller.");
+        }
+        self.engineering_status
+            .send_modify(|engineering_status| engineering_status.connected = false);
+    }
+
+    /// Apply the controller settings from the configuration and publish
+    /// the resulting settings.
+    async fn apply_controller_settings(&mut self) -> ATDomeResult<()> {
+        let Some(model) = self.model.clone() else {
+            return Ok(());
+        };
+        let mut status = model.get_status().await?;
+        if let Some(auto_shutdown_enabled) = self.config.auto_shutdown_enabled {
+            if status.auto_shutdown_enabled != auto_shutdown_enabled {
+                tracing::info!(
+                    auto_shutdown_enabled,
+                    "Setting the controller auto-shutdown."
+                );
+                model
+                    .run_command(ATDomeCmd::SetAutoShutdownEnabled(auto_shutdown_enabled))
+                    .await?;
+                status = model.get_status().await?;
+            }
+        }
+
+        let settings_applied = self
+            .controller
+            .get_event_to_write::<SettingsAppliedDomeController>(
+                "logevent_settingsAppliedDomeController",
+            )
+            .map_err(sal_error)?
+            .with_status(&status)
+            .with_heartbeat_interval(self.heartbeat_interval().as_secs_f64());
+        if let Err(err) = self
+            .controller
+            .write_event("logevent_settingsAppliedDomeController", &settings_applied)
+            .await
+        {
+            tracing::error!("Failed to write controller settings: {err:?}");
+        }
+        Ok(())
+    }
+
+    /// Start homing the azimuth if the controller reports it is not homed.
+    async fn home_if_not_homed(&mut self) -> ATDomeResult<()> {
+        let Some(model) = self.model.clone() else {
+            return Ok(());
+        };
+        let status = model.get_status().await?;
+        if status.homed || MoveCode::is_homing(status.move_code) {
+            return Ok(());
+        }
+
+        tracing::info!(az_pos = status.az_pos, "Azimuth not homed; homing.");
+        self.start_homing(model, &status, None).await
+    }
+
+    /// Stop monitoring the homing in progress, if any, failing its command
+    /// and the motion commands held back by it with `reason`; the monitor
+    /// then publishes that the azimuth is not homing. The controller is not
+    /// told to stop.
+    fn cancel_homing(&mut self, reason: &str) {
+        if let Some(homing_cancel) = self.homing_cancel.take() {
+            if homing_cancel.send(reason.to_owned()).is_err() {
+                tracing::debug!("Homing finished before it was cancelled.");
+            }
+        }
+        // Before clearing `homing`, so the held commands are not run.
+        self.held_cancel.send_replace(reason.to_owned());
+        self.homing.send_replace(false);
+    }
+
+    /// Is a homing sequence in progress?
+    fn is_homing(&self) -> bool {
+        *self.homing.borrow()
+    }
+
+    /// Reject a motion command received while homing.
+    ///
+    /// Returns an error message if the command must be rejected. With
+    /// `queue_motion_while_homing` set, `run` holds these commands back
+    /// with `requeue_after_homing` instead, so they only get here once
+    /// homing is over.
+    fn check_homing_exclusivity(&self, command: &str) -> Option<String> {
+        self.is_homing()
+            .then(|| format!("{command} not allowed while homing the azimuth."))
+    }
+
+    /// Queue a motion command again once homing finishes.
+    ///
+    /// The command is acked as in progress and waits in its own task
+    /// rather than in the control loop, so stopMotion, disable and standby
+    /// are still handled while homing. It runs after the commands received
+    /// in the meantime, or fails as busy if the command queue is full then.
+    /// If homing is cancelled the command fails with the same reason.
+    fn requeue_after_homing(&mut self, data: CmdData, ack_channel: mpsc::Sender<CommandAck>) {
+        tracing::info!("Queueing {} until homing finishes.", data.name);
+        let make_ack = held_command_ack(&data);
+        let time_left = self.homing_deadline.map_or(Duration::ZERO, |deadline| {
+            deadline.saturating_duration_since(Instant::now())
+        });
+        send_progress_ack(
+            &ack_channel,
+            make_ack(MotionOutcome::InProgress(
+                time_left.as_secs_f64(),
+                "Waiting for homing to finish.".to_owned(),
+            )),
+        );
+
+        let mut homing = self.homing.subscribe();
+        let mut held_cancel = self.held_cancel.subscribe();
+        let command_sender = self.command_sender.clone();
+        self.held_commands.retain(|task| !task.is_finished());
+        self.held_commands.push(task::spawn(async move {
+            let name = data.name.clone();
+            let outcome = tokio::select! {
+                biased;
+                Ok(()) = held_cancel.changed() => {
+                    let reason = held_cancel.borrow().clone();
+                    MotionOutcome::Failed(CMD_ABORTED, reason)
+                }
+                Ok(()) = async { homing.wait_for(|homing| !*homing).await.map(|_| ()) } => {
+                    match command_sender.try_send((data, ack_channel.clone())) {
+                        Ok(()) => return,
+                        Err(mpsc::error::TrySendError::Full(_)) => {
+                            MotionOutcome::from_error(&ATDomeError::Busy(format!(
+                                "Too many commands queued to run {name} after homing."
+                            )))
+                        }
+                        Err(mpsc::error::TrySendError::Closed(_)) => {
+                            tracing::debug!("Not running {name} after homing: the CSC stopped.");
+                            return;
+                        }
+                    }
+                }
+                // The senders live as long as the CSC.
+                else => return,
+            };
+            tracing::info!(?outcome, "{name} held back by homing finished.");
+            send_ack(&ack_channel, make_ack(outcome)).await;
+        }));
+    }
+
+    /// Time the homing monitor allows for homing to complete.
+    fn homing_timeout(&self, status: &Status) -> Duration {
+        controller_timeout(status.azimuth_move_timeout)
+            + Duration::from_secs_f32(self.config.motion_timeout_margin)
+    }
+
+    /// Send the home command and monitor the homing sequence.
+    ///
+    /// Publishes the commanded and azimuth states, then polls the
+    /// controller until it reports it is homed. Homing fails, and the dome
+    /// is stopped, if it takes longer than `homing_timeout`; it is also
+    /// ended by `cancel_homing`. Whatever the outcome, the azimuth state is
+    /// set back to not homing. If `command` is set, an in-progress ack with
+    /// the current azimuth is sent on every poll and the final ack is sent
+    /// once homing ends, after the ack returned by the command handler.
+    async fn start_homing(
+        &mut self,
+        model: Arc<ATDomeModel>,
+        status: &Status,
+        command: Option<(MakeAck, mpsc::Sender<CommandAck>)>,
+    ) -> ATDomeResult<()> {
+        model.run_command(ATDomeCmd::HomeAzimuth).await?;
+
+        let azimuth_commanded_state = self
+            .controller
+            .get_event_to_write::<AzimuthCommandedState>("logevent_azimuthCommandedState")
+            .map_err(sal_error)?
+            .with_commanded_state(AzimuthCommandedStateEnum::Home)
+            .with_azimuth(status.az_pos as f64);
+        if let Err(err) = self
+            .controller
+            .write_event("logevent_azimuthCommandedState", &azimuth_commanded_state)
+            .await
+        {
+            tracing::error!("Failed to write azimuth commanded state: {err:?}");
+        }
+
+        let azimuth_state = self
+            .controller
+            .get_event_to_write::<AzimuthState>("logevent_azimuthState")
+            .map_err(sal_error)?
+            .with_state(AzimuthStateEnum::NotInMotion)
+            .with_homing(AzimuthHomingState::Homing);
+        if let Err(err) = self
+            .controller
+            .write_event("logevent_azimuthState", &azimuth_state)
+            .await
+        {
+            tracing::error!("Failed to write azimuth state: {err:?}");
+        }
+
+        let poll_interval = Duration::from_secs_f32(self.config.status_poll_interval);
+        let homing_timeout = self.homing_timeout(status);
+        let ack_timeout = homing_timeout.as_secs_f64();
+        let sal_info = sal_info()?;
+        let mut azimuth_state_writer =
+            WriteTopic::new("logevent_azimuthState", &sal_info, &self.domain);
+        let ack_release = command.as_ref().map(|_| {
+            let (ack_release, ack_released) = oneshot::channel::<()>();
+            self.ack_release = Some(ack_release);
+            ack_released
+        });
+        if let Some(homing_task) = self.homing_task.take() {
+            homing_task.abort();
+        }
+        let (homing_cancel, cancel_receiver) = oneshot::channel::<String>();
+        self.homing_cancel = Some(homing_cancel);
+        self.homing.send_replace(true);
+        self.homing_deadline = Some(Instant::now() + homing_timeout);
+        let homing = self.homing.clone();
+        self.homing_task = Some(task::spawn(async move {
+            let poll_loop = async {
+                loop {
+                    sleep(poll_interval).await;
+                    match model.get_status().await {
+                        Ok(status) if status.homed && !MoveCode::is_homing(status.move_code) => {
+                            tracing::info!(az_pos = status.az_pos, "Homing completed.");
+                            break Ok(());
+                        }
+                        Ok(status) => {
+                            tracing::info!(az_pos = status.az_pos, "Homing in progress.");
+                            if let Some((make_ack, ack_channel)) = &command {
+                                send_progress_ack(
+                                    ack_channel,
+                                    make_ack(MotionOutcome::InProgress(
+                                        ack_timeout,
+                                        format!("Homing; azimuth = {:.2} deg.", status.az_pos),
+                                    )),
+                                );
+                            }
+                        }
+                        Err(error) => {
+                            tracing::error!("Lost controller status while homing: {error}");
+                            break Err(error);
+                        }
+                    }
+                }
+            };
+            let homing_monitor = async {
+                if let Some(ack_released) = ack_release {
+                    // Dropped unsent if the control loop stopped first.
+                    let _ = ack_released.await;
+                }
+                match timeout(homing_timeout, poll_loop).await {
+                    Ok(Ok(())) => MotionOutcome::Complete,
+                    Ok(Err(error)) => MotionOutcome::from_error_while("Homing failed", &error),
+                    Err(_) => {
+                        let message = format!(
+                            "Homing did not complete in {:.1}s.",
+                            homing_timeout.as_secs_f32()
+                        );
+                        tracing::error!("{message}");
+                        if let Err(error) = model.run_command(ATDomeCmd::StopMotion).await {
+                            tracing::error!("Failed to stop the dome: {error}");
+                        }
+                        MotionOutcome::from_error(&ATDomeError::Timeout(message))
+                    }
+                }
+            };
+            let outcome = tokio::select! {
+                reason = cancel_receiver => {
+                    let reason = reason.unwrap_or_else(|_| "Homing cancelled.".to_owned());
+                    tracing::info!("Homing cancelled: {reason}");
+                    MotionOutcome::Failed(CMD_ABORTED, reason)
+                }
+                outcome = homing_monitor => outcome,
+            };
+            homing.send_replace(false);
+            let azimuth_state = AzimuthState::default()
+                .with_timestamps()
+                .with_sal_index(azimuth_state_writer.get_index())
+                .with_private_origin(azimuth_state_writer.get_origin())
+                .with_private_identity(&azimuth_state_writer.get_identity())
+                .with_private_seq_num(azimuth_state_writer.get_seq_num())
+                .with_state(AzimuthStateEnum::NotInMotion)
+                .with_homing(AzimuthHomingState::NotHoming);
+            if let Err(err) = azimuth_state_writer
+                .write_typed::<AzimuthState>(&azimuth_state)
+                .await
+            {
+                tracing::error!("Failed to write azimuth state: {err:?}");
+            }
+            if let Some((make_ack, ack_channel)) = command {
+                send_ack(&ack_channel, make_ack(outcome)).await;
+            }
+        }));
+        Ok(())
+    }
+
+    /// Respond to the homeAzimuth command.
+    ///
+    /// The command is acknowledged as in progress; the final ack is sent
+    /// by the homing monitor once the controller reports it is homed, or
+    /// homing fails.
+    #[tracing::instrument(skip_all, fields(command = %data.name))]
+    async fn do_home_azimuth(
+        &mut self,
+        data: &CmdData,
+        ack_channel: mpsc::Sender<CommandAck>,
+    ) -> ATDomeResult<CommandAckResult> {
+        let home_azimuth = from_value::<EmptyTopic>(&data.data).unwrap();
+        let current_state = self.get_current_state();
+        if current_state != State::Enabled {
+            return Ok((
+                CommandAck::from_error(
+                    home_azimuth,
+                    &ATDomeError::InvalidState(format!(
+                        "homeAzimuth not allowed in {current_state:?}."
+                    )),
+                ),
+                ack_channel,
+            ));
+        }
+        if self.is_homing() {
+            return Ok((
+                CommandAck::from_error(
+                    home_azimuth,
+                    &ATDomeError::CommandRejected("Already homing the azimuth.".to_owned()),
+                ),
+                ack_channel,
+            ));
+        }
+        let Some(model) = self.model.clone() else {
+            return Ok((
+                CommandAck::from_error(
+                    home_azimuth,
+                    &ATDomeError::Connection("Not connected to the controller.".to_owned()),
+                ),
+                ack_channel,
+            ));
+        };
+
+        let result = async {
+            let status = model.get_status().await?;
+            self.start_homing(
+                model,
+                &status,
+                Some((make_motion_ack!(EmptyTopic, data.data), ack_channel.clone())),
+            )
+            .await?;
+            Ok::<Status, ATDomeError>(status)
+        }
+        .await;
+
+        match result {
+            Ok(status) => Ok((
+                CommandAck::make_in_progress(
+                    home_azimuth,
+                    self.homing_timeout(&status).as_secs_f64(),
+                    &format!("Homing; azimuth = {:.2} deg.", status.az_pos),
+                ),
+                ack_channel,
+            )),
+            Err(error) => Ok((
+                CommandAck::from_error_while(home_azimuth, "Failed to home azimuth", &error),
+                ack_channel,
+            )),
+        }
+    }
+
+    /// Stop the azimuth and the doors if the controller reports motion,
+    /// including homing.
+    async fn stop_motion_if_moving(&self) -> ATDomeResult<()> {
+        if let Some(model) = &self.model {
+            let status = model.get_status().await?;
+            if MoveCode::is_azimuth_moving(status.move_code)
+                || MoveCode::is_any_door_moving(status.move_code)
+                || MoveCode::is_homing(status.move_code)
+            {
+                tracing::info!(move_code = status.move_code, "Stopping dome motion.");
+                model.run_command(ATDomeCmd::StopMotion).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Respond to the stopMotion command.
+    ///
+    /// Stops the azimuth and the doors and fails the acks of any motion
+    /// command still waiting to complete.
+    #[tracing::instrument(skip_all, fields(command = %data.name))]
+    async fn do_stop_motion(
+        &mut self,
+        data: &CmdData,
+        ack_channel: mpsc::Sender<CommandAck>,
+    ) -> ATDomeResult<CommandAckResult> {
+        let stop_motion = from_value::<EmptyTopic>(&data.data).unwrap();
+        let current_state = self.get_current_state();
+        if current_state != State::Enabled {
+            return Ok((
+                CommandAck::from_error(
+                    stop_motion,
+                    &ATDomeError::InvalidState(format!(
+                        "stopMotion not allowed in {current_state:?}."
+                    )),
+                ),
+                ack_channel,
+            ));
+        }
+        let Some(model) = self.model.clone() else {
+            return Ok((
+                CommandAck::from_error(
+                    stop_motion,
+                    &ATDomeError::Connection("Not connected to the controller.".to_owned()),
+                ),
+                ack_channel,
+            ));
+        };
+
+        if let Err(error) = model.run_command(ATDomeCmd::StopMotion).await {
+            return Ok((
+                CommandAck::from_error_while(stop_motion, "Failed to stop motion", &error),
+                ack_channel,
+            ));
+        }
+
+        for (axis, pending_motion) in self.pending_motions.drain() {
+            tracing::info!(?axis, "Aborting pending motion command.");
+            pending_motion.cancel("Aborted by stopMotion.");
+        }
+        self.cancel_homing("Aborted by stopMotion.");
+
+        let azimuth_commanded_state = self
+            .controller
+            .get_event_to_write::<AzimuthCommandedState>("logevent_azimuthCommandedState")
+            .map_err(sal_error)?
+            .with_commanded_state(AzimuthCommandedStateEnum::Stop);
+        if let Err(err) = self
+            .controller
+            .write_event("logevent_azimuthCommandedState", &azimuth_commanded_state)
+            .await
+        {
+            tracing::error!("Failed to write azimuth commanded state: {err:?}");
+        }
+
+        Ok((CommandAck::make_complete(stop_motion), ack_channel))
+    }
+
+    /// Respond to the openShutter command.
+    #[tracing::instrument(skip_all, fields(command = %data.name))]
+    async fn do_open_shutter(
+        &mut self,
+        data: &CmdData,
+        ack_channel: mpsc::Sender<CommandAck>,
+    ) -> ATDomeResult<CommandAckResult> {
+        let make_ack = make_motion_ack!(EmptyTopic, data.data);
+        Ok(self
+            .move_doors("openShutter", Some(true), Some(true), make_ack, ack_channel)
+            .await)
+    }
+
+    /// Respond to the closeShutter command.
+    #[tracing::instrument(skip_all, fields(command = %data.name))]
+    async fn do_close_shutter(
+        &mut self,
+        data: &CmdData,
+        ack_channel: mpsc::Sender<CommandAck>,
+    ) -> ATDomeResult<CommandAckResult> {
+        let make_ack = make_motion_ack!(EmptyTopic, data.data);
+        Ok(self
+            .move_doors(
+                "closeShutter",
+                Some(false),
+                Some(false),
+                make_ack,
+                ack_channel,
+            )
+            .await)
+    }
+
+    /// Respond to the moveShutterMainDoor command.
+    #[tracing::instrument(skip_all, fields(command = %data.name))]
+    async fn do_move_shutter_main_door(
+        &mut self,
+        data: &CmdData,
+        ack_channel: mpsc::Sender<CommandAck>,
+    ) -> ATDomeResult<CommandAckResult> {
+        let open = from_value::<MoveShutterMainDoor>(&data.data)
+            .unwrap()
+            .get_open();
+        let make_ack = make_motion_ack!(MoveShutterMainDoor, data.data);
+        Ok(self
+            .move_doors(
+                "moveShutterMainDoor",
+                Some(open),
+                None,
+                make_ack,
+                ack_channel,
+            )
+            .await)
+    }
+
+    /// Respond to the moveShutterDropoutDoor command.
+    #[tracing::instrument(skip_all, fields(command = %data.name))]
+    async fn do_move_shutter_dropout_door(
+        &mut self,
+        data: &CmdData,
+        ack_channel: mpsc::Sender<CommandAck>,
+    ) -> ATDomeResult<CommandAckResult> {
+        let open = from_value::<MoveShutterDropoutDoor>(&data.data)
+            .unwrap()
+            .get_open();
+        let make_ack = make_motion_ack!(MoveShutterDropoutDoor, data.data);
+        Ok(self
+            .move_doors(
+                "moveShutterDropoutDoor",
+                None,
+                Some(open),
+                make_ack,
+                ack_channel,
+            )
+            .await)
+    }
+
+    /// Open or close the doors and monitor them until they get there.
+    ///
+    /// `main_door` and `dropout_door` are `Some(true)` to open the door,
+    /// `Some(false)` to close it and `None` to leave it alone. The command
+    /// is rejected without reaching the controller if the status shows the
+    /// emergency stop active or the shutter control board link down.
+    /// Otherwise it is acknowledged as in progress; the final ack is sent
+    /// when the status shows the doors at the commanded end state, or a
+    /// failed ack after the controller door move timeout.
+    async fn move_doors(
+        &mut self,
+        command: &str,
+        main_door: Option<bool>,
+        dropout_door: Option<bool>,
+        make_ack: MakeAck,
+        ack_channel: mpsc::Sender<CommandAck>,
+    ) -> CommandAckResult {
+        let current_state = self.get_current_state();
+        if current_state != State::Enabled {
+            return (
+                make_ack(MotionOutcome::from_error(&ATDomeError::InvalidState(
+                    format!("{command} not allowed in {current_state:?}."),
+                ))),
+                ack_channel,
+            );
+        }
+        if let Some(reason) = self.check_homing_exclusivity(command) {
+            return (
+                make_ack(MotionOutcome::from_error(&ATDomeError::CommandRejected(
+                    reason,
+                ))),
+                ack_channel,
+            );
+        }
+        let ack = self
+            .send_door_commands(
+                command,
+                main_door,
+                dropout_door,
+                make_ack,
+                Some(ack_channel.clone()),
+            )
+            .await;
+        (ack, ack_channel)
+    }
+
+    /// Send the door commands of `move_doors` and monitor the doors,
+    /// without checking the summary state or homing.
+    ///
+    /// Returns the first ack; the monitor sends the others on
+    /// `ack_channel`, if any.
+    async fn send_door_commands(
+        &mut self,
+        command: &str,
+        main_door: Option<bool>,
+        dropout_door: Option<bool>,
+        make_ack: MakeAck,
+        ack_channel: Option<mpsc::Sender<CommandAck>>,
+    ) -> CommandAck {
+        let Some(model) = self.model.clone() else {
+            return make_ack(MotionOutcome::from_error(&ATDomeError::Connection(
+                "Not connected to the controller.".to_owned(),
+            )));
+        };
+
+        let door_cmds = match (main_door, dropout_door) {
+            (Some(true), Some(true)) => vec![ATDomeCmd::OpenShutter],
+            (Some(false), Some(false)) => vec![ATDomeCmd::CloseShutter],
+            _ => [
+                main_door.map(|open| {
+                    if open {
+                        ATDomeCmd::OpenShutterMainDoor
+                    } else {
+                        ATDomeCmd::CloseShutterMainDoor
+                    }
+                }),
+                dropout_door.map(|open| {
+                    if open {
+                        ATDomeCmd::OpenShutterDropoutDoor
+                    } else {
+                        ATDomeCmd::CloseShutterDropoutDoor
+                    }
+                }),
+            ]
+            .into_iter()
+            .flatten()
+            .collect(),
+        };
+        let result = async {
+            let status = model.get_status().await?;
+            if let Err(error) = status.check_shutter_interlock() {
+                tracing::warn!("Refusing {command}: {error}");
+                return Err(error);
+            }
+            for door_cmd in door_cmds {
+                model.run_command(door_cmd).await?;
+            }
+            Ok::<Status, ATDomeError>(status)
+        }
+        .await;
+        let status = match result {
+            Ok(status) => status,
+            Err(error) => {
+                return make_ack(MotionOutcome::from_error_while(
+                    "Failed to move the doors",
+                    &error,
+                ))
+            }
+        };
+
+        let door_target = |open: bool| if open { 100.0 } else { 0.0 };
+        let main_door_target = main_door.map(door_target);
+        let dropout_door_target = dropout_door.map(door_target);
+        self.commanded_targets.send_modify(|targets| {
+            if main_door_target.is_some() {
+                targets.main_door = main_door_target;
+            }
+            if dropout_door_target.is_some() {
+                targets.dropout_door = dropout_door_target;
+            }
+        });
+        self.publish_door_commanded_states(main_door, dropout_door)
+            .await;
+
+        if let Some(pending_motion) = self.pending_motions.remove(&MotionAxis::Shutter) {
+            pending_motion.cancel(&format!("Superseded by {command}."));
+        }
+
+        let config = MotionMonitorConfig {
+            poll_interval: Duration::from_secs_f32(self.config.status_poll_interval),
+            timeout: controller_timeout(status.door_move_timeout),
+            timeout_margin: Duration::from_secs_f32(self.config.motion_timeout_margin),
+            warning_sender: Some(self.warning_sender.clone()),
+        };
+        let in_progress = make_ack(MotionOutcome::InProgress(
+            (config.timeout + config.timeout_margin).as_secs_f64(),
+            format!("{command} in progress."),
+        ));
+        let pending_motion = PendingMotion::start(
+            model,
+            ack_channel,
+            config,
+            move |status| {
+                let at_target = |pct: f32, target: Option<f32>| {
+                    target.is_none_or(|target| (pct - target).abs() <= DOOR_TOLERANCE)
+                };
+                !MoveCode::is_any_door_moving(status.move_code)
+                    && at_target(status.main_door_pct, main_door_target)
+                    && at_target(status.dropout_door_pct, dropout_door_target)
+            },
+            |status| {
+                format!(
+                    "Main door at {:.1}%, dropout door at {:.1}%.",
+                    status.main_door_pct, status.dropout_door_pct
+                )
+            },
+            make_ack,
+        );
+        self.pending_motions
+            .insert(MotionAxis::Shutter, pending_motion);
+        in_progress
+    }
+
+    /// Go to Fault because the controller status went stale, unless it
+    /// recovered since or the CSC is not connected or already in Fault.
+    async fn fault_on_stale_status(&mut self, reason: &str) -> ATDomeResult<()> {
+        let stale = self.model.as_ref().is_some_and(|model| !model.is_healthy());
+        if !stale || self.get_current_state() == State::Fault {
+            tracing::info!("{reason}; not going to Fault.");
+            return Ok(());
+        }
+        self.fault(&ATDomeError::Connection(format!("{reason}.")))
+            .await
+    }
+
+    /// Close both doors because a weather sensor tripped, publishing the
+    /// reason as a warning.
+    ///
+    /// Sends the same door commands as closeShutter, even while homing or
+    /// moving; the outcome is only logged since no remote is waiting for an
+    /// ack. If the close fails the status poll requests it again after
+    /// `WEATHER_CLOSE_RETRY_DELAY`. Nothing is done unless the CSC is
+    /// enabled; enabling it lets the status poll request the close again.
+    async fn close_for_weather(&mut self, reason: &str) {
+        let current_state = self.get_current_state();
+        if current_state != State::Enabled {
+            tracing::info!("{reason}; not closing the shutter in {current_state:?}.");
+            return;
+        }
+        let message = format!("{reason}; closing the shutter.");
+        tracing::warn!("{message}");
+        if let Err(error) = self.warning_sender.try_send(message) {
+            tracing::warn!("Failed to queue the warning: {}", ATDomeError::from(error));
+        }
+
+        let weather_close = self.weather_close.clone();
+        let make_ack: MakeAck = Box::new(move |outcome| {
+            match &outcome {
+                // Superseded by another door command or stopMotion.
+                MotionOutcome::Failed(CMD_ABORTED, message) => {
+                    tracing::warn!("Weather close aborted: {message}");
+                }
+                MotionOutcome::Failed(_, message) => {
+                    tracing::error!("Failed to close the shutter for the weather: {message}");
+                    *weather_close.lock().unwrap() =
+                        WeatherClose::RetryAt(Instant::now() + WEATHER_CLOSE_RETRY_DELAY);
+                }
+                _ => {}
+            }
+            CommandAck::make_complete(EmptyTopic::default())
+        });
+        self.send_door_commands("closeShutter", Some(false), Some(false), make_ack, None)
+            .await;
+    }
+
+    /// Publish the commanded state of the doors that were commanded.
+    async fn publish_door_commanded_states(
+        &mut self,
+        main_door: Option<bool>,
+        dropout_door: Option<bool>,
+    ) {
+        let commanded_state = |open: bool| {
+            if open {
+                ShutterDoorCommandedState::Opened
+            } else {
+                ShutterDoorCommandedState::Closed
+            }
+        };
+        if let Some(open) = main_door {
+            match self
+                .controller
+                .get_event_to_write::<MainDoorCommandedState>("logevent_mainDoorCommandedState")
+            {
+                Ok(main_door_commanded_state) => {
+                    let main_door_commanded_state =
+                        main_door_commanded_state.with_commanded_state(commanded_state(open));
+                    if let Err(err) = self
+                        .controller
+                        .write_event(
+                            "logevent_mainDoorCommandedState",
+                            &main_door_commanded_state,
+                        )
+                        .await
+                    {
+                        tracing::error!("Failed to write main door commanded state: {err:?}");
+                    }
+                }
+                Err(err) => tracing::error!("Failed to get main door commanded state: {err:?}"),
+            }
+        }
+        if let Some(open) = dropout_door {
+            match self
+                .controller
+                .get_event_to_write::<DropoutDoorCommandedState>(
+                    "logevent_dropoutDoorCommandedState",
+                ) {
+                Ok(dropout_door_commanded_state) => {
+                    let dropout_door_commanded_state =
+                        dropout_door_commanded_state.with_commanded_state(commanded_state(open));
+                    if let Err(err) = self
+                        .controller
+                        .write_event(
+                            "logevent_dropoutDoorCommandedState",
+                            &dropout_door_commanded_state,
+                        )
+                        .await
+                    {
+                        tracing::error!("Failed to write dropout door commanded state: {err:?}");
+                    }
+                }
+                Err(err) => {
+                    tracing::error!("Failed to get dropout door commanded state: {err:?}")
+                }
+            }
+        }
+    }
+
+    /// Respond to the moveAzimuth command.
+    ///
+    /// The target is wrapped to [0, 360) deg and the commanded state
+    /// reports the shortest path the controller will take. The command is
+    /// acknowledged as in progress during the slew and completes once the
+    /// azimuth is within the controller tolerance and no longer moving.
+    #[tracing::instrument(skip_all, fields(command = %data.name))]
+    async fn do_move_azimuth(
+        &mut self,
+        data: &CmdData,
+        ack_channel: mpsc::Sender<CommandAck>,
+    ) -> ATDomeResult<CommandAckResult> {
+        let move_azimuth = from_value::<MoveAzimuth>(&data.data).unwrap();
+        let current_state = self.get_current_state();
+        if current_state != State::Enabled {
+            return Ok((
+                CommandAck::from_error(
+                    move_azimuth,
+                    &ATDomeError::InvalidState(format!(
+                        "moveAzimuth not allowed in {current_state:?}."
+                    )),
+                ),
+                ack_channel,
+            ));
+        }
+        if let Some(reason) = self.check_homing_exclusivity("moveAzimuth") {
+            return Ok((
+                CommandAck::from_error(move_azimuth, &ATDomeError::CommandRejected(reason)),
+                ack_channel,
+            ));
+        }
+        let Some(model) = self.model.clone() else {
+            return Ok((
+                CommandAck::from_error(
+                    move_azimuth,
+                    &ATDomeError::Connection("Not connected to the controller.".to_owned()),
+                ),
+                ack_channel,
+            ));
+        };
+
+        let result = async {
+            let status = model.get_status().await?;
+            if self.config.require_homed_to_move && !status.homed {
+                return Err(ATDomeError::CommandRejected(
+                    "The azimuth is not homed; run homeAzimuth first.".to_owned(),
+                ));
+            }
+            let path = AzimuthPath::new(status.az_pos, move_azimuth.get_azimuth() as f32);
+            tracing::info!(
+                target = path.target,
+                distance = path.distance,
+                estimated_duration = ?path.estimated_duration(self.config.azimuth_speed),
+                "Moving azimuth."
+            );
+            model.run_command(ATDomeCmd::MoveAz(path.target)).await?;
+            self.commanded_targets
+                .send_modify(|targets| targets.azimuth = Some(path.target));
+            Ok::<(Status, AzimuthPath), ATDomeError>((status, path))
+        }
+        .await;
+
+        let (status, path) = match result {
+            Ok(result) => result,
+            Err(error) => {
+                return Ok((
+                    CommandAck::from_error_while(move_azimuth, "Failed to move azimuth", &error),
+                    ack_channel,
+                ))
+            }
+        };
+
+        let azimuth_commanded_state = self
+            .controller
+            .get_event_to_write::<AzimuthCommandedState>("logevent_azimuthCommandedState")
+            .map_err(sal_error)?
+            .with_commanded_state(AzimuthCommandedStateEnum::GoToPosition)
+            .with_azimuth(path.target as f64);
+        if let Err(err) = self
+            .controller
+            .write_event("logevent_azimuthCommandedState", &azimuth_commanded_state)
+            .await
+        {
+            tracing::error!("Failed to write azimuth commanded state: {err:?}");
+        }
+
+        if let Some(pending_motion) = self.pending_motions.remove(&MotionAxis::Azimuth) {
+            pending_motion.cancel("Superseded by moveAzimuth.");
+        }
+
+        let config = MotionMonitorConfig {
+            poll_interval: Duration::from_secs_f32(self.config.status_poll_interval),
+            timeout: controller_timeout(status.azimuth_move_timeout),
+            timeout_margin: Duration::from_secs_f32(self.config.motion_timeout_margin),
+            warning_sender: Some(self.warning_sender.clone()),
+        };
+        let ack_timeout = (config.timeout + config.timeout_margin).as_secs_f64();
+        let target = path.target;
+        let pending_motion = PendingMotion::start(
+            model,
+            Some(ack_channel.clone()),
+            config,
+            move |status| {
+                !MoveCode::is_azimuth_moving(status.move_code)
+                    && azimuth_distance(status.az_pos, target).abs() <= status.tolerance
+            },
+            move |status| {
+                format!(
+                    "Azimuth at {:.2} deg, {:.2} deg to go.",
+                    status.az_pos,
+                    azimuth_distance(status.az_pos, target)
+                )
+            },
+            make_motion_ack!(MoveAzimuth, data.data),
+        );
+        self.pending_motions
+            .insert(MotionAxis::Azimuth, pending_motion);
+
+        Ok((
+            CommandAck::make_in_progress(
+                move_azimuth,
+                ack_timeout,
+                &match path.estimated_duration(self.config.azimuth_speed) {
+                    Some(duration) => format!(
+                        "Moving to {:.2} deg; estimated duration {duration:.1}s.",
+                        path.target
+                    ),
+                    None => format!("Moving to {:.2} deg.", path.target),
+                },
+            ),
+            ack_channel,
+        ))
+    }
+
+    /// Send the CSC to Fault.
+    ///
+    /// Publishes the errorCode event with the code of the error category
+    /// before transitioning to Fault.
+    pub async fn fault(&mut self, error: &ATDomeError) -> ATDomeResult<()> {
+        tracing::error!("Going to Fault: {error}");
+        self.cancel_homing("Aborted by fault.");
+        let error_code = self
+            .controller
+            .get_event_to_write::<ErrorCode>("logevent_errorCode")
+            .map_err(sal_error)?
+            .with_error_code(error.error_code())
+            .with_error_report(&error.get_error_message());
+
+        if let Err(err) = self
+            .controller
+            .write_event("logevent_errorCode", &error_code)
+            .await
+        {
+            tracing::error!("Failed to write error code: {err:?}");
+        }
+        self.fault_error_code = Some(error.error_code());
+        self.set_summary_state(State::Fault);
+        self.update_summary_state().await
+    }
+
+    /// Reset the error state left by a fault and publish a cleared errorCode.
+    async fn clear_fault(&mut self) -> ATDomeResult<()> {
+        if let Some(fault_error_code) = self.fault_error_code.take() {
+            tracing::info!("Clearing fault with error code {fault_error_code}.");
+        }
+        let error_code = self
+            .controller
+            .get_event_to_write::<ErrorCode>("logevent_errorCode")
+            .map_err(sal_error)?
+            .with_error_code(0)
+            .with_error_report("");
+
+        if let Err(err) = self
+            .controller
+            .write_event("logevent_errorCode", &error_code)
+            .await
+        {
+            return Err(ATDomeError::Connection(format!(
+                "Failed to write error code: {err:?}"
+            )));
+        }
+        Ok(())
+    }
+
+    /// Publish the version of the CSC.
+    async fn publish_software_versions(&mut self) -> ATDomeResult<()> {
+        let software_versions = self
+            .controller
+            .get_event_to_write::<SoftwareVersions>("logevent_softwareVersions")
+            .map_err(sal_error)?
+            .with_csc_version(LONG_VERSION);
+
+        if let Err(err) = self
+            .controller
+            .write_event("logevent_softwareVersions", &software_versions)
+            .await
+        {
+            return Err(ATDomeError::Connection(format!(
+                "Failed to write software versions: {err:?}"
+            )));
+        }
+        Ok(())
+    }
+
+    /// Publish the current state of the component.
+    #[tracing::instrument(skip(self), fields(state = ?self.summary_state))]
+    async fn update_summary_state(&mut self) -> ATDomeResult<()> {
+        self.metrics.summary_state.set(self.summary_state as i64);
+        let summary_state_name = format!("{:?}", self.summary_state);
+        self.engineering_status.send_modify(|engineering_status| {
+            engineering_status.summary_state = summary_state_name
+        });
+
+        let summary_state = self
+            .controller
+            .get_event_to_write::<SummaryState>("logevent_summaryState")
+            .map_err(sal_error)?
+            .with_summary_state(self.summary_state);
+
+        if let Err(err) = self
+            .controller
+            .write_event("logevent_summaryState", &summary_state)
+            .await
+        {
+            return Err(ATDomeError::Connection(format!(
+                "Failed to write summary state: {err:?}"
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl<'a> BaseCSC for ATDome<'a> {
+    fn get_current_state(&self) -> State {
+        self.summary_state
+    }
+
+    fn set_summary_state(&mut self, new_state: State) {
+        self.summary_state = new_state;
+    }
+
+    fn configure(&mut self, data: &Start) -> SalObjResult<()> {
+        tracing::info!(
+            "Received {} configuration override.",
+            data.get_configuration_override()
+        );
+        Ok(())
+    }
+}
+
+/// Ack of a state command from the result of its transition.
+fn transition_ack<T: BaseSALTopic>(
+    data: T,
+    transition: StateTransition,
+    result: ATDomeResult<()>,
+) -> CommandAck {
+    match result {
+        Ok(()) => CommandAck::make_complete(data),
+        Err(error @ ATDomeError::InvalidState(_)) => CommandAck::from_error(data, &error),
+        Err(error) => CommandAck::from_error_while(
+            data,
+            &format!("Transition to {:?} failed", transition.target()),
+            &error,
+        ),
+    }
+}
+
+/// Run one of `CONCURRENT_COMMANDS` and return its ack.
+#[tracing::instrument(skip_all, fields(command = %data.name))]
+fn run_concurrent_command(data: &CmdData) -> CommandAck {
+    match data.name.as_str() {
+        "setLogLevel" => set_log_level_ack(data),
+        name => {
+            let reason = format!("Command {name} cannot run concurrently.");
+            let command = from_value::<EmptyTopic>(&data.data).unwrap();
+            CommandAck::from_error(command, &ATDomeError::Internal(reason))
+        }
+    }
+}
+
+/// Respond to the setLogLevel command.
+///
+/// Allowed in every state, so the logs can be made more verbose to
+/// investigate a problem without disturbing the CSC.
+fn set_log_level_ack(data: &CmdData) -> CommandAck {
+    let set_log_level_data = from_value::<SetLogLevel>(&data.data).unwrap();
+    let subsystem = set_log_level_data.get_subsystem().trim();
+    let target = (!subsystem.is_empty()).then_some(subsystem);
+    let level = level_from_python(set_log_level_data.get_level());
+    match set_log_level(target, level) {
+        Ok(()) => CommandAck::make_complete(set_log_level_data),
+        Err(error) => {
+            CommandAck::from_error_while(set_log_level_data, "Failed to set the log level", &error)
+        }
+    }
+}
+
+/// Ack a command that will not run as failed, so the remote does not wait
+/// for an ack that never comes.
+async fn reject_command(
+    data: &CmdData,
+    ack_channel: &mpsc::Sender<CommandAck>,
+    error: &ATDomeError,
+) {
+    tracing::warn!("Rejecting command {}: {error}", data.name);
+    match from_value::<EmptyTopic>(&data.data) {
+        Ok(command) => {
+            let ack = CommandAck::from_error(command, error);
+            send_ack(ack_channel, ack).await;
+        }
+        Err(error) => tracing::error!("Failed to decode {} to ack it: {error}", data.name),
+    }
+}
+
+/// SAL interface of the CSC, for its readers and writers.
+fn sal_info() -> ATDomeResult<SalInfo> {
+    SalInfo::new(SAL_NAME, SAL_INDEX).map_err(sal_error)
+}
+
+/// Wrap an error of the SAL layer, which is internal to the CSC.
+fn sal_error(error: impl fmt::Display) -> ATDomeError {
+    ATDomeError::Internal(format!("SAL error: {error}"))
+}
+
+/// Ask the control loop to run the internal command `name`, e.g. to close
+/// the shutter, because of `reason`.
+///
+/// Returns false if the command queue is full.
+fn request_internal_command(
+    command_sender: &mpsc::Sender<CmdPayload>,
+    name: &str,
+    reason: String,
+) -> bool {
+    let internal_command = CmdData {
+        name: name.to_owned(),
+        data: Value::String(reason),
+    };
+    // Nobody waits for the ack of an internal command.
+    match command_sender.try_send((internal_command, mpsc::channel(1).0)) {
+        Ok(()) => true,
+        Err(error) => {
+            tracing::error!("Failed to queue the internal command {name}: {error}");
+            false
+        }
+    }
+}
+
+/// Build the acks of a motion command held back by homing, one of
+/// `HOMING_EXCLUSIVE_COMMANDS`, from the topic of the command.
+fn held_command_ack(data: &CmdData) -> MakeAck {
+    match data.name.as_str() {
+        "moveAzimuth" => make_motion_ack!(MoveAzimuth, data.data),
+        "moveShutterMainDoor" => make_motion_ack!(MoveShutterMainDoor, data.data),
+        "moveShutterDropoutDoor" => make_motion_ack!(MoveShutterDropoutDoor, data.data),
+        _ => make_motion_ack!(EmptyTopic, data.data),
+    }
+}
+
+/// Duration of a move timeout reported by the controller (sec); zero, so
+/// only the configured margin applies, if the value is not usable.
+fn controller_timeout(seconds: f32) -> Duration {
+    Duration::try_from_secs_f32(seconds).unwrap_or_else(|_| {
+        tracing::warn!("Invalid controller move timeout {seconds}; using only the margin.");
+        Duration::ZERO
+    })
+}
+
+/// Wait for `task` to publish its queued messages, aborting it after
+/// `FLUSH_TIMEOUT`.
+async fn flush_task(task: task::JoinHandle<()>, messages: &str) {
+    let task_abort = task.abort_handle();
+    if timeout(FLUSH_TIMEOUT, task).await.is_err() {
+        tracing::warn!("Timed out publishing the queued {messages}.");
+        task_abort.abort();
+    }
+}
+
+/// Run the CSC until it exits or the process receives SIGINT or SIGTERM.
+///
+/// See [`run_atdome_csc_with_shutdown`].
+pub async fn run_atdome_csc(
+    config: ATDomeConfig,
+    simulation_mode: bool,
+    initial_state: State,
+) -> ATDomeResult<()> {
+    run_atdome_csc_with_shutdown(
+        config,
+        simulation_mode,
+        initial_state,
+        wait_for_shutdown_signal(),
+    )
+    .await
+}
+
+/// Run the CSC until it exits (exitControl) or `shutdown` completes.
+///
+/// Creates and starts the CSC, runs the command loop and shuts the CSC
+/// down before returning.
+pub async fn run_atdome_csc_with_shutdown(
+    config: ATDomeConfig,
+    simulation_mode: bool,
+    initial_state: State,
+    shutdown: impl Future<Output = ()>,
+) -> ATDomeResult<()> {
+    let mut atdome = ATDome::new(config, simulation_mode, initial_state)?;
+
+    // Starting can wait a long time for the broker, so it is interrupted
+    // by `shutdown` too.
+    tokio::pin!(shutdown);
+    let started = tokio::select! {
+        result = atdome.start() => Some(result),
+        _ = &mut shutdown => {
+            tracing::info!("Shutdown requested while starting the CSC.");
+            None
+        }
+    };
+
+    let result = match started {
+        Some(Ok(())) => tokio::select! {
+            result = atdome.run() => result,
+            _ = shutdown => Ok(()),
+        },
+        Some(Err(error)) => Err(error),
+        None => Ok(()),
+    };
+
+    // Shut down however the CSC stopped, so the tasks a partial start
+    // spawned are stopped as well.
+    let shutdown_result = atdome.shutdown().await;
+    result.and(shutdown_result)
+}
@@ -0,0 +1,98 @@
+//! Backpressure policy of the internal channels of the CSC.
+//!
+//! Each channel between the tasks of the CSC has an explicit policy for
+//! when its consumer falls behind:
+//!
+//! * Commands (mpsc, `COMMAND_QUEUE_CAPACITY`): reject with busy. A command
+//!   reader that finds the queue full acks the command with an
+//!   `ATDomeError::Busy` (CMD_NOPERM) right away instead of waiting, so the
+//!   remote gets an answer instead of a timeout.
+//! * Telemetry (watch): drop oldest. Only the latest sample is kept; the
+//!   samples overwritten before the telemetry loop publishes them are
+//!   counted.
+//! * Command acks (`spawn_forwarder`): block. Final acks are never dropped,
+//!   since the remote would wait for them until it times out: once
+//!   `ACK_QUEUE_CAPACITY` acks wait for the broker, the senders wait too.
+//!   The periodic in-progress acks of the motion and homing monitors are
+//!   sent with `send_progress_ack` instead, which skips them when the queue
+//!   is full, so they never hold up the final acks.
+//!
+//! Fill levels and drops are exported as the `channel_fill` and
+//! `channel_dropped` metrics, labelled by channel name.
+
+use prometheus::IntGauge;
+use tokio::{
+    sync::mpsc,
+    task::{self, JoinHandle},
+};
+
+/// Commands that can wait for the control loop before new ones are rejected.
+pub const COMMAND_QUEUE_CAPACITY: usize = 32;
+/// Acks that can wait for the broker before their senders wait as well.
+pub const ACK_QUEUE_CAPACITY: usize = 256;
+
+/// Channel names used as the `channel` label of the metrics.
+pub const COMMAND_CHANNEL: &str = "command";
+pub const TELEMETRY_CHANNEL: &str = "telemetry";
+pub const ACK_CHANNEL: &str = "ack";
+
+/// Number of messages queued in the channel of `sender`.
+pub fn queued<T>(sender: &mpsc::Sender<T>) -> usize {
+    sender.max_capacity() - sender.capacity()
+}
+
+/// Forward messages to `output`, in order, through a queue.
+///
+/// Up to `capacity` messages wait for `output` to have room; beyond that
+/// the senders wait, so no message is ever dropped. `fill` tracks the
+/// number of waiting messages. Returns the sender to use instead of
+/// `output` and the forwarder task, which exits after flushing the queue
+/// once every returned sender is dropped.
+pub fn spawn_forwarder<T: Send + 'static>(
+    output: mpsc::Sender<T>,
+    capacity: usize,
+    fill: IntGauge,
+) -> (mpsc::Sender<T>, JoinHandle<()>) {
+    let (input, mut input_receiver) = mpsc::channel::<T>(capacity);
+    let forwarder_task = task::spawn(async move {
+        while let Some(message) = input_receiver.recv().await {
+            fill.set(input_receiver.len() as i64);
+            if output.send(message).await.is_err() {
+                return;
+            }
+        }
+        fill.set(0);
+    });
+    (input, forwarder_task)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_forwarder_keeps_every_message() {
+        let fill = IntGauge::new("fill", "fill").unwrap();
+        let (output, mut output_receiver) = mpsc::channel::<u32>(1);
+        let (input, forwarder_task) = spawn_forwarder(output, 2, fill.clone());
+
+        // More messages than the queue and the output hold: the sender
+        // waits for the consumer instead of dropping any.
+        let sender_task = task::spawn(async move {
+            for message in 1..=10 {
+                input.send(message).await.unwrap();
+            }
+        });
+
+        let mut received = Vec::new();
+        while let Some(message) = output_receiver.recv().await {
+            received.push(message);
+            tokio::task::yield_now().await;
+        }
+        sender_task.await.unwrap();
+        forwarder_task.await.unwrap();
+
+        assert_eq!(received, (1..=10).collect::<Vec<_>>());
+        assert_eq!(fill.get(), 0);
+    }
+}
@@ -0,0 +1,173 @@
+//! Run the mock controller and the CSC connected to it in one process.
+//!
+//! Meant for demos and to develop components that talk to the CSC without
+//! the dome hardware: the CSC is a regular (non-simulation) CSC pointed at
+//! the embedded mock, so it behaves as it would with the real controller.
+
+use clap::{Parser, ValueEnum};
+use rubin_ts_atdome_csc::{
+    atdome_config::ATDomeConfig,
+    atdome_csc::run_atdome_csc_with_shutdown,
+    logging::{init_logging, parse_module_level, LogFormat},
+    mock_controller::mock_controller::{run_mock_controller_on, MockControllerConfig},
+    shutdown_signal::wait_for_shutdown_signal,
+    version::LONG_VERSION,
+};
+use salobj::sal_enums::State;
+use tokio::{net::TcpListener, sync::oneshot};
+
+use tracing::level_filters::LevelFilter;
+
+use std::{error::Error, path::PathBuf};
+
+/// Summary states the CSC can be started in.
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum InitialState {
+    Standby,
+    Disabled,
+    Enabled,
+}
+
+impl From<InitialState> for State {
+    fn from(item: InitialState) -> State {
+        match item {
+            InitialState::Standby => State::Standby,
+            InitialState::Disabled => State::Disabled,
+            InitialState::Enabled => State::Enabled,
+        }
+    }
+}
+
+/// Run the ATDome CSC against an embedded mock controller.
+#[derive(Parser, Debug)]
+#[command(about, version = LONG_VERSION)]
+struct Args {
+    /// Path to the CSC configuration file; use the default configuration
+    /// if not given. The host and port of the controller are replaced by
+    /// the ones of the mock.
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// Summary state to transition to at startup.
+    #[arg(long, value_enum, default_value_t = InitialState::Enabled)]
+    state: InitialState,
+
+    /// Address the mock controller listens on.
+    #[arg(long, default_value = "127.0.0.1")]
+    host: String,
+
+    /// Port the mock controller listens on; 0 picks a free port.
+    #[arg(long, default_value_t = 0)]
+    port: u16,
+
+    /// Azimuth speed of the mock (deg/s).
+    #[arg(long, default_value_t = 2.4)]
+    az_speed: f32,
+
+    /// Half width of the jitter added to the azimuth reported by the mock
+    /// (deg).
+    #[arg(long, default_value_t = 0.0)]
+    az_jitter: f32,
+
+    /// Rate the azimuth reported by the mock drifts away from the
+    /// simulated one (deg/s).
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    az_drift_rate: f32,
+
+    /// Address to stream the simulated state over a WebSocket, e.g.
+    /// "127.0.0.1:8888".
+    #[arg(long)]
+    websocket_address: Option<String>,
+
+    /// Address of the side-channel control port of the mock, e.g.
+    /// "127.0.0.1:8889".
+    #[arg(long)]
+    control_address: Option<String>,
+
+    /// JSON file to keep the simulated state in across restarts.
+    #[arg(long)]
+    state_file: Option<PathBuf>,
+
+    /// Log level (error, warn, info, debug or trace).
+    #[arg(long, default_value = "info")]
+    log_level: LevelFilter,
+
+    /// Log level of a module, as target=level, e.g.
+    /// "rubin_ts_atdome::atdome_model=debug"; overrides the configuration.
+    /// Can be repeated.
+    #[arg(long, value_parser = parse_module_level)]
+    log_module: Vec<(String, LevelFilter)>,
+
+    /// Log output format.
+    #[arg(long, value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse();
+
+    let config = match &args.config {
+        Some(path) => ATDomeConfig::from_file(path)?,
+        None => ATDomeConfig::default(),
+    };
+
+    let mut log_levels = config.log_levels(args.log_level)?;
+    for (target, level) in &args.log_module {
+        log_levels.set(Some(target), *level)?;
+    }
+    init_logging(log_levels, args.log_format);
+
+    // Before the runtime starts its threads.
+    config.apply_kafka_environment();
+    run(args, config)
+}
+
+#[tokio::main]
+async fn run(args: Args, mut config: ATDomeConfig) -> Result<(), Box<dyn Error>> {
+    // Bind before starting the CSC so it never races the mock.
+    let listener = TcpListener::bind((args.host.as_str(), args.port)).await?;
+    let address = listener.local_addr()?;
+    tracing::info!("Mock controller listening on {address}.");
+
+    let mock_controller_config = MockControllerConfig {
+        host: args.host,
+        port: address.port() as usize,
+        az_speed: args.az_speed,
+        az_jitter: args.az_jitter,
+        az_drift_rate: args.az_drift_rate,
+        websocket_address: args.websocket_address,
+        control_address: args.control_address,
+        state_file: args.state_file,
+        ..Default::default()
+    };
+    let (mock_shutdown, mock_shutdown_receiver) = oneshot::channel::<()>();
+    let (mock_exited, mock_exited_receiver) = oneshot::channel::<()>();
+    let mock_task = tokio::spawn(async move {
+        let result = run_mock_controller_on(listener, mock_controller_config, async move {
+            let _ = mock_shutdown_receiver.await;
+        })
+        .await;
+        let _ = mock_exited.send(());
+        result
+    });
+
+    config.host = address.ip().to_string();
+    config.port = address.port() as usize;
+
+    let shutdown = async move {
+        tokio::select! {
+            _ = wait_for_shutdown_signal() => (),
+            _ = mock_exited_receiver => {
+                tracing::error!("The mock controller exited; stopping the CSC.");
+            }
+        }
+    };
+    let csc_result = run_atdome_csc_with_shutdown(config, false, args.state.into(), shutdown).await;
+
+    // Stop the mock after the CSC so it saves the state of a quiet dome.
+    let _ = mock_shutdown.send(());
+    mock_task.await??;
+    csc_result?;
+
+    Ok(())
+}
@@ -0,0 +1,89 @@
+use clap::{Parser, ValueEnum};
+use rubin_ts_atdome_csc::{
+    atdome_config::ATDomeConfig,
+    atdome_csc::run_atdome_csc,
+    logging::{init_logging, parse_module_level, LogFormat},
+    version::LONG_VERSION,
+};
+use salobj::sal_enums::State;
+
+use tracing::level_filters::LevelFilter;
+
+use std::{error::Error, path::PathBuf};
+
+/// Summary states the CSC can be started in.
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum InitialState {
+    Standby,
+    Disabled,
+    Enabled,
+}
+
+impl From<InitialState> for State {
+    fn from(item: InitialState) -> State {
+        match item {
+            InitialState::Standby => State::Standby,
+            InitialState::Disabled => State::Disabled,
+            InitialState::Enabled => State::Enabled,
+        }
+    }
+}
+
+/// Run the ATDome CSC.
+#[derive(Parser, Debug)]
+#[command(about, version = LONG_VERSION)]
+struct Args {
+    /// Path to the configuration file; use the default configuration if
+    /// not given.
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// Run in simulation mode, connecting to a mock controller.
+    #[arg(long)]
+    simulate: bool,
+
+    /// Summary state to transition to at startup; Disabled and Enabled run
+    /// the start (and enable) sequence without waiting for commands.
+    #[arg(long, value_enum, default_value_t = InitialState::Standby)]
+    state: InitialState,
+
+    /// Log level (error, warn, info, debug or trace).
+    #[arg(long, default_value = "info")]
+    log_level: LevelFilter,
+
+    /// Log level of a module, as target=level, e.g.
+    /// "rubin_ts_atdome::atdome_model=debug"; overrides the configuration.
+    /// Can be repeated.
+    #[arg(long, value_parser = parse_module_level)]
+    log_module: Vec<(String, LevelFilter)>,
+
+    /// Log output format.
+    #[arg(long, value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse();
+
+    let config = match &args.config {
+        Some(path) => ATDomeConfig::from_file(path)?,
+        None => ATDomeConfig::default(),
+    };
+
+    let mut log_levels = config.log_levels(args.log_level)?;
+    for (target, level) in &args.log_module {
+        log_levels.set(Some(target), *level)?;
+    }
+    init_logging(log_levels, args.log_format);
+
+    // Before the runtime starts its threads.
+    config.apply_kafka_environment();
+    run(args, config)
+}
+
+#[tokio::main]
+async fn run(args: Args, config: ATDomeConfig) -> Result<(), Box<dyn Error>> {
+    run_atdome_csc(config, args.simulate, args.state.into()).await?;
+
+    Ok(())
+}
@@ -0,0 +1,72 @@
+//! Build command acknowledgements from ATDome errors.
+//!
+//! Command handlers report failures through these helpers so the ack code
+//! is always the standard SAL code of the error category (see `ack_codes`)
+//! and the messages have the same shape for every command.
+
+use salobj::{topics::base_sal_topic::BaseSALTopic, utils::command_ack::CommandAck};
+use tokio::sync::mpsc;
+
+use crate::error::ATDomeError;
+
+/// Message of a failed ack: the action that failed followed by the error
+/// message, e.g. "Failed to move azimuth: Connection closed.".
+pub fn failed_message(action: &str, error: &ATDomeError) -> String {
+    format!("{action}: {}", error.get_error_message())
+}
+
+/// Send `ack` on `ack_channel`; if the command reader is gone the ack is
+/// logged and dropped since nobody can receive it.
+pub async fn send_ack(ack_channel: &mpsc::Sender<CommandAck>, ack: CommandAck) {
+    if let Err(error) = ack_channel.send(ack).await {
+        tracing::warn!("Failed to send command ack: {}", ATDomeError::from(error));
+    }
+}
+
+/// Send the in-progress `ack` of a long command, skipping it if the ack
+/// queue is full: progress acks are periodic and must never hold up the
+/// final ack of a command (see `backpressure`).
+pub fn send_progress_ack(ack_channel: &mpsc::Sender<CommandAck>, ack: CommandAck) {
+    match ack_channel.try_send(ack) {
+        Ok(()) => {}
+        Err(mpsc::error::TrySendError::Full(_)) => {
+            tracing::debug!("The ack queue is full; skipping a progress ack.");
+        }
+        Err(error) => tracing::warn!("Failed to send command ack: {}", ATDomeError::from(error)),
+    }
+}
+
+/// Conversions from `ATDomeError` into failed acks.
+pub trait CommandAckExt {
+    /// Failed ack with the ack code and message of `error`.
+    fn from_error<T: BaseSALTopic>(cmd: T, error: &ATDomeError) -> CommandAck;
+
+    /// Failed ack with the ack code of `error` and a message prefixed by
+    /// the action that failed.
+    fn from_error_while<T: BaseSALTopic>(cmd: T, action: &str, error: &ATDomeError) -> CommandAck;
+}
+
+impl CommandAckExt for CommandAck {
+    fn from_error<T: BaseSALTopic>(cmd: T, error: &ATDomeError) -> CommandAck {
+        CommandAck::make_failed(cmd, error.ack_code(), &error.get_error_message())
+    }
+
+    fn from_error_while<T: BaseSALTopic>(cmd: T, action: &str, error: &ATDomeError) -> CommandAck {
+        CommandAck::make_failed(cmd, error.ack_code(), &failed_message(action, error))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_failed_message() {
+        let error = ATDomeError::Timeout("No reply in 10.0s.".to_owned());
+
+        assert_eq!(
+            failed_message("Failed to stop motion", &error),
+            "Failed to stop motion: No reply in 10.0s."
+        );
+    }
+}
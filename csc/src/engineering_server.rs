@@ -0,0 +1,60 @@
+//! Read-only HTTP endpoint with the engineering status of the CSC.
+//!
+//! Lets engineers check the dome without a SAL client.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use axum::{routing::get, Json, Router};
+use serde::Serialize;
+use tokio::{net::TcpListener, sync::watch};
+
+use crate::{atdome_model::ConnectionStats, error::ATDomeResult, status::Status};
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct EngineeringStatus {
+    /// Current summary state of the CSC.
+    pub summary_state: String,
+    /// Is the CSC connected to the controller?
+    pub connected: bool,
+    /// Latest status received from the controller.
+    pub status: Option<Status>,
+    /// Time the latest status was received (unix seconds).
+    pub status_timestamp: Option<f64>,
+    /// Text of the latest status reply, kept even if it failed to parse.
+    pub raw_status: Option<String>,
+    /// Statistics of the link with the controller.
+    pub connection_stats: Option<ConnectionStats>,
+}
+
+impl EngineeringStatus {
+    /// Store a new controller status, timestamped with the current time.
+    pub fn update_status(&mut self, status: Status) {
+        self.status = Some(status);
+        self.status_timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .ok()
+            .map(|timestamp| timestamp.as_secs_f64());
+    }
+}
+
+/// Serve the engineering status as json on `address` under `/status`,
+/// and the text of the latest status reply under `/raw_status`.
+pub async fn serve_engineering_status(
+    engineering_status: watch::Receiver<EngineeringStatus>,
+    address: &str,
+) -> ATDomeResult<()> {
+    let raw_status = engineering_status.clone();
+    let app = Router::new()
+        .route(
+            "/status",
+            get(move || async move { Json(engineering_status.borrow().clone()) }),
+        )
+        .route(
+            "/raw_status",
+            get(move || async move { raw_status.borrow().raw_status.clone().unwrap_or_default() }),
+        );
+    let listener = TcpListener::bind(address).await?;
+    tracing::info!("Serving engineering status on {address}.");
+    axum::serve(listener, app).await?;
+    Ok(())
+}
@@ -0,0 +1,17 @@
+// The controller client, parser, status types and mock, re-exported so the
+// CSC and its users reach everything through this crate.
+pub use rubin_ts_atdome::{
+    ack_codes, atdome_cmd_regex, atdome_config, atdome_enums, atdome_model, azimuth,
+    controller_client, error, in_position, logging, mock_controller, move_code, shutdown_signal,
+    status, status_parser, transcript, version,
+};
+
+pub mod atdome_csc;
+pub mod backpressure;
+pub mod command_ack;
+pub mod engineering_server;
+pub mod metrics;
+pub mod motion_monitor;
+pub mod summary_state;
+pub mod task_supervisor;
+pub mod topics;
@@ -0,0 +1,200 @@
+//! Prometheus metrics of the ATDome CSC.
+//!
+//! The metrics are always collected and can optionally be served over HTTP
+//! so the monitoring stack can scrape them.
+
+use std::{sync::Arc, time::Duration};
+
+use axum::{routing::get, Router};
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts,
+    Registry, TextEncoder,
+};
+use tokio::net::TcpListener;
+
+use crate::{
+    atdome_model::ConnectionStats,
+    error::{ATDomeError, ATDomeResult},
+};
+
+pub struct ATDomeMetrics {
+    registry: Registry,
+    /// Number of commands processed, by command name.
+    pub command_count: IntCounterVec,
+    /// Time taken to process commands, by command name.
+    pub command_latency: HistogramVec,
+    /// Number of failed attempts to get the controller status.
+    pub status_poll_failures: IntCounter,
+    /// Current summary state of the CSC.
+    pub summary_state: IntGauge,
+    /// Number of messages waiting in an internal channel, by channel.
+    pub channel_fill: IntGaugeVec,
+    /// Number of messages dropped or rejected because the consumer of an
+    /// internal channel fell behind, by channel.
+    pub channel_dropped: IntCounterVec,
+    /// Statistics of the link with the controller, by statistic; the
+    /// "reconnects" statistic counts the reconnections.
+    pub connection_stats: IntGaugeVec,
+}
+
+impl ATDomeMetrics {
+    pub fn new() -> ATDomeResult<ATDomeMetrics> {
+        let registry = Registry::new_custom(Some("atdome".to_owned()), None)
+            .map_err(ATDomeMetrics::to_error)?;
+        let command_count = IntCounterVec::new(
+            Opts::new("command_count", "Number of commands processed."),
+            &["command"],
+        )
+        .map_err(ATDomeMetrics::to_error)?;
+        let command_latency = HistogramVec::new(
+            HistogramOpts::new("command_latency_seconds", "Time taken to process commands."),
+            &["command"],
+        )
+        .map_err(ATDomeMetrics::to_error)?;
+        let status_poll_failures = IntCounter::new(
+            "status_poll_failures",
+            "Number of failed attempts to get the controller status.",
+        )
+        .map_err(ATDomeMetrics::to_error)?;
+        let summary_state = IntGauge::new("summary_state", "Current summary state of the CSC.")
+            .map_err(ATDomeMetrics::to_error)?;
+        let channel_fill = IntGaugeVec::new(
+            Opts::new(
+                "channel_fill",
+                "Number of messages waiting in an internal channel.",
+            ),
+            &["channel"],
+        )
+        .map_err(ATDomeMetrics::to_error)?;
+        let channel_dropped = IntCounterVec::new(
+            Opts::new(
+                "channel_dropped",
+                "Number of messages dropped or rejected because the consumer of an internal channel fell behind.",
+            ),
+            &["channel"],
+        )
+        .map_err(ATDomeMetrics::to_error)?;
+        let connection_stats = IntGaugeVec::new(
+            Opts::new(
+                "connection_stats",
+                "Statistics of the link with the controller.",
+            ),
+            &["stat"],
+        )
+        .map_err(ATDomeMetrics::to_error)?;
+
+        registry
+            .register(Box::new(command_count.clone()))
+            .map_err(ATDomeMetrics::to_error)?;
+        registry
+            .register(Box::new(command_latency.clone()))
+            .map_err(ATDomeMetrics::to_error)?;
+        registry
+            .register(Box::new(status_poll_failures.clone()))
+            .map_err(ATDomeMetrics::to_error)?;
+        registry
+            .register(Box::new(summary_state.clone()))
+            .map_err(ATDomeMetrics::to_error)?;
+        registry
+            .register(Box::new(channel_fill.clone()))
+            .map_err(ATDomeMetrics::to_error)?;
+        registry
+            .register(Box::new(channel_dropped.clone()))
+            .map_err(ATDomeMetrics::to_error)?;
+        registry
+            .register(Box::new(connection_stats.clone()))
+            .map_err(ATDomeMetrics::to_error)?;
+
+        Ok(ATDomeMetrics {
+            registry,
+            command_count,
+            command_latency,
+            status_poll_failures,
+            summary_state,
+            channel_fill,
+            channel_dropped,
+            connection_stats,
+        })
+    }
+
+    /// Record that a command was processed.
+    pub fn observe_command(&self, command: &str, latency: Duration) {
+        self.command_count.with_label_values(&[command]).inc();
+        self.command_latency
+            .with_label_values(&[command])
+            .observe(latency.as_secs_f64());
+    }
+
+    /// Record the statistics of the link with the controller.
+    pub fn set_connection_stats(&self, stats: &ConnectionStats) {
+        for (stat, value) in [
+            ("bytes_sent", stats.bytes_sent),
+            ("bytes_received", stats.bytes_received),
+            ("commands_sent", stats.commands_sent),
+            ("parse_failures", stats.parse_failures),
+            ("reconnects", stats.reconnects),
+        ] {
+            self.connection_stats
+                .with_label_values(&[stat])
+                .set(value as i64);
+        }
+    }
+
+    /// Render the metrics in the Prometheus text format.
+    pub fn encode(&self) -> ATDomeResult<String> {
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .map_err(ATDomeMetrics::to_error)?;
+        String::from_utf8(buffer).map_err(ATDomeError::from_error)
+    }
+
+    fn to_error(error: prometheus::Error) -> ATDomeError {
+        ATDomeError::Internal(format!("Metrics error: {error}"))
+    }
+}
+
+/// Serve the metrics on `address` under the `/metrics` path.
+pub async fn serve_metrics(metrics: Arc<ATDomeMetrics>, address: &str) -> ATDomeResult<()> {
+    let app = Router::new().route(
+        "/metrics",
+        get(move || async move {
+            metrics.encode().unwrap_or_else(|error| {
+                tracing::error!("Failed to encode metrics: {error}");
+                String::new()
+            })
+        }),
+    );
+    let listener = TcpListener::bind(address).await?;
+    tracing::info!("Serving metrics on {address}.");
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        let metrics = ATDomeMetrics::new().unwrap();
+
+        metrics.observe_command("start", Duration::from_millis(10));
+        metrics
+            .channel_dropped
+            .with_label_values(&["command"])
+            .inc();
+        metrics.set_connection_stats(&ConnectionStats {
+            commands_sent: 3,
+            reconnects: 1,
+            ..Default::default()
+        });
+
+        let text = metrics.encode().unwrap();
+
+        assert!(text.contains("atdome_command_count{command=\"start\"} 1"));
+        assert!(text.contains("atdome_channel_dropped{channel=\"command\"} 1"));
+        assert!(text.contains("atdome_connection_stats{stat=\"commands_sent\"} 3"));
+        assert!(text.contains("atdome_connection_stats{stat=\"reconnects\"} 1"));
+    }
+}
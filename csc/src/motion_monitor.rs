@@ -0,0 +1,467 @@
+//! Monitor motion commands until the dome reaches the commanded target.
+//!
+//! Motion commands are acknowledged as in progress when they are sent to
+//! the controller; a monitor task then polls the controller status and
+//! sends the final ack once the target is reached, the motion times out or
+//! the command is cancelled (e.g. by stopMotion).
+
+use std::{sync::Arc, time::Instant};
+
+use salobj::utils::command_ack::CommandAck;
+use tokio::{
+    sync::{mpsc, oneshot},
+    task,
+    time::{sleep, timeout, Duration},
+};
+
+use crate::{
+    ack_codes::CMD_ABORTED,
+    atdome_model::{ATDomeCmd, ATDomeModel},
+    command_ack::{failed_message, send_ack, send_progress_ack},
+    error::ATDomeError,
+    status::Status,
+};
+
+/// Consecutive failed status polls tolerated before the command fails.
+pub const MAX_CONSECUTIVE_POLL_ERRORS: usize = 3;
+
+/// Time allowed for the controller to accept the stop sent when the
+/// watchdog fires.
+const WATCHDOG_STOP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Axis a motion command acts on.
+///
+/// Only one command per axis is monitored at a time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MotionAxis {
+    Azimuth,
+    Shutter,
+}
+
+/// Outcome of a motion command, used to build the acks.
+#[derive(Debug)]
+pub enum MotionOutcome {
+    /// Still moving; carries the time left before the motion times out
+    /// (sec) and a message describing the progress.
+    InProgress(f64, String),
+    /// The target was reached.
+    Complete,
+    /// The command failed with a SAL ack code and message.
+    Failed(i32, String),
+}
+
+impl MotionOutcome {
+    /// Failure with the ack code and message of `error`.
+    pub fn from_error(error: &ATDomeError) -> MotionOutcome {
+        MotionOutcome::Failed(error.ack_code(), error.get_error_message())
+    }
+
+    /// Failure with the ack code of `error` and a message prefixed by the
+    /// action that failed.
+    pub fn from_error_while(action: &str, error: &ATDomeError) -> MotionOutcome {
+        MotionOutcome::Failed(error.ack_code(), failed_message(action, error))
+    }
+}
+
+/// Build the ack for `outcome` from the command data.
+pub type MakeAck = Box<dyn Fn(MotionOutcome) -> CommandAck + Send + Sync>;
+
+/// Create a [`MakeAck`] that decodes the command data as `$topic`.
+macro_rules! make_motion_ack {
+    ($topic:ty, $data:expr) => {{
+        let data = $data.clone();
+        Box::new(move |outcome: $crate::motion_monitor::MotionOutcome| {
+            let topic = from_value::<$topic>(&data).unwrap();
+            match outcome {
+                $crate::motion_monitor::MotionOutcome::InProgress(timeout, message) => {
+                    CommandAck::make_in_progress(topic, timeout, &message)
+                }
+                $crate::motion_monitor::MotionOutcome::Complete => CommandAck::make_complete(topic),
+                $crate::motion_monitor::MotionOutcome::Failed(code, message) => {
+                    CommandAck::make_failed(topic, code, &message)
+                }
+            }
+        }) as $crate::motion_monitor::MakeAck
+    }};
+}
+pub(crate) use make_motion_ack;
+
+/// Parameters of a motion monitor.
+pub struct MotionMonitorConfig {
+    /// Interval between status polls.
+    pub poll_interval: Duration,
+    /// Time the controller allows for the motion to complete.
+    pub timeout: Duration,
+    /// Extra time allowed on top of `timeout` before the watchdog fails
+    /// the command.
+    pub timeout_margin: Duration,
+    /// Channel to report watchdog timeouts on, published as warnings.
+    pub warning_sender: Option<mpsc::Sender<String>>,
+}
+
+/// A motion command waiting for the dome to reach its target.
+#[derive(Debug)]
+pub struct PendingMotion {
+    cancel: oneshot::Sender<String>,
+    task: task::JoinHandle<()>,
+}
+
+impl PendingMotion {
+    /// Start monitoring a motion command.
+    ///
+    /// * `is_done` - Has the motion reached its target?
+    /// * `progress` - Describe the progress for the in-progress acks.
+    /// * `make_ack` - Build the acks sent on `ack_channel`; it is still
+    ///   called with every outcome if there is no channel, e.g. for an
+    ///   internal motion no remote waits for.
+    ///
+    /// A few failed status polls in a row are tolerated, up to
+    /// [`MAX_CONSECUTIVE_POLL_ERRORS`]. A watchdog stops the dome and fails
+    /// the command if it does not complete within the controller timeout
+    /// plus the margin, even if the controller stops answering status
+    /// requests.
+    pub fn start(
+        model: Arc<ATDomeModel>,
+        ack_channel: Option<mpsc::Sender<CommandAck>>,
+        config: MotionMonitorConfig,
+        is_done: impl Fn(&Status) -> bool + Send + Sync + 'static,
+        progress: impl Fn(&Status) -> String + Send + Sync + 'static,
+        make_ack: MakeAck,
+    ) -> PendingMotion {
+        let (cancel, cancel_receiver) = oneshot::channel::<String>();
+        let task = task::spawn(async move {
+            let start = Instant::now();
+            let deadline = config.timeout + config.timeout_margin;
+            let poll_loop = async {
+                let mut poll_errors = 0;
+                loop {
+                    sleep(config.poll_interval).await;
+                    match model.get_status().await {
+                        Ok(status) if is_done(&status) => break MotionOutcome::Complete,
+                        Ok(status) => {
+                            poll_errors = 0;
+                            let time_left = deadline.saturating_sub(start.elapsed());
+                            let ack = make_ack(MotionOutcome::InProgress(
+                                time_left.as_secs_f64(),
+                                progress(&status),
+                            ));
+                            if let Some(ack_channel) = &ack_channel {
+                                send_progress_ack(ack_channel, ack);
+                            }
+                        }
+                        Err(error) => {
+                            poll_errors += 1;
+                            if poll_errors >= MAX_CONSECUTIVE_POLL_ERRORS {
+                                break MotionOutcome::from_error_while(
+                                    "Lost controller status",
+                                    &error,
+                                );
+                            }
+                            tracing::warn!(poll_errors, "Failed to poll the status: {error}");
+                        }
+                    }
+                }
+            };
+            let outcome = tokio::select! {
+                reason = cancel_receiver => {
+                    let reason = reason.unwrap_or_else(|_| "Command cancelled.".to_owned());
+                    MotionOutcome::Failed(CMD_ABORTED, reason)
+                }
+                _ = sleep(deadline) => {
+                    let message = format!(
+                        "Motion did not complete in {:.1}s (controller timeout {:.1}s).",
+                        deadline.as_secs_f32(),
+                        config.timeout.as_secs_f32()
+                    );
+                    tracing::warn!("{message}");
+                    if let Some(warning_sender) = &config.warning_sender {
+                        // Never wait on the broker before sending the ack.
+                        if let Err(error) = warning_sender.try_send(message.clone()) {
+                            tracing::warn!(
+                                "Failed to queue the warning: {}",
+                                ATDomeError::from(error)
+                            );
+                        }
+                    }
+                    stop_motion(&model).await;
+                    MotionOutcome::from_error(&ATDomeError::Timeout(message))
+                }
+                outcome = poll_loop => outcome,
+            };
+            tracing::info!(?outcome, "Motion command finished.");
+            let ack = make_ack(outcome);
+            if let Some(ack_channel) = &ack_channel {
+                send_ack(ack_channel, ack).await;
+            }
+        });
+        PendingMotion { cancel, task }
+    }
+
+    /// Has the monitor sent the final ack?
+    pub fn is_finished(&self) -> bool {
+        self.task.is_finished()
+    }
+
+    /// Fail the command with `reason`, unless it already finished.
+    pub fn cancel(self, reason: &str) {
+        if !self.task.is_finished() && self.cancel.send(reason.to_owned()).is_err() {
+            tracing::debug!("Motion command finished before it was cancelled.");
+        }
+    }
+
+    /// Stop monitoring without sending a final ack.
+    pub fn abort(self) {
+        self.task.abort();
+    }
+}
+
+/// Tell the controller to stop, after the watchdog gave up on a motion.
+async fn stop_motion(model: &ATDomeModel) {
+    match timeout(
+        WATCHDOG_STOP_TIMEOUT,
+        model.run_command(ATDomeCmd::StopMotion),
+    )
+    .await
+    {
+        Ok(Ok(_)) => tracing::info!("Stopped the dome after the motion timed out."),
+        Ok(Err(error)) => tracing::error!("Failed to stop the dome: {error}"),
+        Err(_) => tracing::error!("Timed out stopping the dome."),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_controller::mock_controller::{run_mock_controller_on, MockControllerConfig};
+    use crate::move_code::MoveCode;
+    use salobj::generics::empty_topic::EmptyTopic;
+    use std::sync::Mutex;
+    use tokio::net::TcpListener;
+
+    /// Start a mock controller with `config` on a free port and connect a
+    /// model to it.
+    async fn start_mock_and_model(
+        config: MockControllerConfig,
+    ) -> (Arc<ATDomeModel>, oneshot::Sender<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port() as usize;
+        let (shutdown_sender, shutdown_receiver) = oneshot::channel::<()>();
+        tokio::spawn(run_mock_controller_on(listener, config, async {
+            let _ = shutdown_receiver.await;
+        }));
+        let atdome_model = ATDomeModel::create_and_start("127.0.0.1", port, 10, Duration::ZERO)
+            .await
+            .unwrap();
+        (Arc::new(atdome_model), shutdown_sender)
+    }
+
+    fn monitor_config(poll_interval: Duration, timeout: Duration) -> MotionMonitorConfig {
+        MotionMonitorConfig {
+            poll_interval,
+            timeout,
+            timeout_margin: Duration::from_millis(100),
+            warning_sender: None,
+        }
+    }
+
+    /// A [`MakeAck`] recording the outcomes it is given.
+    fn recording_make_ack() -> (MakeAck, Arc<Mutex<Vec<String>>>) {
+        let outcomes = Arc::new(Mutex::new(Vec::new()));
+        let recorded = outcomes.clone();
+        let make_ack = Box::new(move |outcome: MotionOutcome| {
+            recorded.lock().unwrap().push(match outcome {
+                MotionOutcome::InProgress(..) => "InProgress".to_owned(),
+                MotionOutcome::Complete => "Complete".to_owned(),
+                MotionOutcome::Failed(code, message) => format!("Failed {code}: {message}"),
+            });
+            CommandAck::make_complete(EmptyTopic::default())
+        }) as MakeAck;
+        (make_ack, outcomes)
+    }
+
+    /// Wait for the monitor to send the final ack and return it.
+    async fn final_outcome(
+        pending_motion: &PendingMotion,
+        outcomes: &Mutex<Vec<String>>,
+    ) -> String {
+        timeout(Duration::from_secs(10), async {
+            while !pending_motion.is_finished() {
+                sleep(Duration::from_millis(20)).await;
+            }
+        })
+        .await
+        .expect("The motion monitor did not finish.");
+        outcomes.lock().unwrap().last().cloned().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_door_motion_completes() {
+        let (atdome_model, _shutdown_sender) = start_mock_and_model(MockControllerConfig {
+            main_door_speed: 200.0,
+            ..Default::default()
+        })
+        .await;
+        let (ack_sender, _ack_receiver) = mpsc::channel(100);
+        let (make_ack, outcomes) = recording_make_ack();
+
+        atdome_model
+            .run_command(ATDomeCmd::OpenShutterMainDoor)
+            .await
+            .unwrap();
+        let pending_motion = PendingMotion::start(
+            atdome_model.clone(),
+            Some(ack_sender),
+            monitor_config(Duration::from_millis(50), Duration::from_secs(5)),
+            |status| {
+                status.main_door_pct == 100.0 && !MoveCode::is_any_door_moving(status.move_code)
+            },
+            |status| format!("Main door at {:.0}%.", status.main_door_pct),
+            make_ack,
+        );
+
+        assert_eq!(final_outcome(&pending_motion, &outcomes).await, "Complete");
+        assert!(outcomes
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|outcome| outcome == "InProgress"));
+    }
+
+    #[tokio::test]
+    async fn test_homing_completes() {
+        let (atdome_model, _shutdown_sender) = start_mock_and_model(MockControllerConfig {
+            az_speed: 20.0,
+            ..Default::default()
+        })
+        .await;
+        let (ack_sender, _ack_receiver) = mpsc::channel(100);
+        let (make_ack, outcomes) = recording_make_ack();
+        assert!(!atdome_model.get_status().await.unwrap().homed);
+
+        atdome_model
+            .run_command(ATDomeCmd::HomeAzimuth)
+            .await
+            .unwrap();
+        let pending_motion = PendingMotion::start(
+            atdome_model.clone(),
+            Some(ack_sender),
+            monitor_config(Duration::from_millis(50), Duration::from_secs(5)),
+            |status| status.homed && !MoveCode::is_homing(status.move_code),
+            |status| format!("Azimuth at {:.1}.", status.az_pos),
+            make_ack,
+        );
+
+        assert_eq!(final_outcome(&pending_motion, &outcomes).await, "Complete");
+        let status = atdome_model.get_status().await.unwrap();
+        assert_eq!(status.az_pos, status.home_azimuth);
+    }
+
+    #[tokio::test]
+    async fn test_timeout_stops_the_dome() {
+        let (atdome_model, _shutdown_sender) =
+            start_mock_and_model(MockControllerConfig::default()).await;
+        let (ack_sender, _ack_receiver) = mpsc::channel(100);
+        let (make_ack, outcomes) = recording_make_ack();
+
+        // 90 deg at 2.4 deg/s takes far longer than the timeout.
+        atdome_model
+            .run_command(ATDomeCmd::MoveAz(90.0))
+            .await
+            .unwrap();
+        let pending_motion = PendingMotion::start(
+            atdome_model.clone(),
+            Some(ack_sender),
+            monitor_config(Duration::from_millis(50), Duration::from_millis(200)),
+            |status| status.az_pos == 90.0,
+            |status| format!("Azimuth at {:.1}.", status.az_pos),
+            make_ack,
+        );
+
+        let outcome = final_outcome(&pending_motion, &outcomes).await;
+        assert!(
+            outcome.contains("Motion did not complete in 0.3s"),
+            "{outcome}"
+        );
+        let status = atdome_model.get_status().await.unwrap();
+        assert_eq!(status.last_azimuth_goto, status.az_pos);
+        assert!(!MoveCode::is_azimuth_moving(status.move_code));
+    }
+
+    #[tokio::test]
+    async fn test_transient_poll_errors_are_tolerated() {
+        let (atdome_model, _shutdown_sender) = start_mock_and_model(MockControllerConfig {
+            main_door_speed: 20.0,
+            ..Default::default()
+        })
+        .await;
+        let (ack_sender, _ack_receiver) = mpsc::channel(100);
+        let (make_ack, outcomes) = recording_make_ack();
+
+        atdome_model
+            .run_command(ATDomeCmd::OpenShutterMainDoor)
+            .await
+            .unwrap();
+        let pending_motion = PendingMotion::start(
+            atdome_model.clone(),
+            Some(ack_sender),
+            monitor_config(Duration::from_millis(200), Duration::from_secs(10)),
+            |status| status.main_door_pct == 100.0,
+            |status| format!("Main door at {:.0}%.", status.main_door_pct),
+            make_ack,
+        );
+
+        // One poll fails while disconnected.
+        atdome_model.disconnect();
+        sleep(Duration::from_millis(250)).await;
+        let (host, port) = (atdome_model.host(), atdome_model.port());
+        atdome_model.reconnect(&host, port).await.unwrap();
+
+        assert_eq!(final_outcome(&pending_motion, &outcomes).await, "Complete");
+    }
+
+    #[tokio::test]
+    async fn test_lost_status_fails_the_command() {
+        let (atdome_model, _shutdown_sender) =
+            start_mock_and_model(MockControllerConfig::default()).await;
+        let (ack_sender, _ack_receiver) = mpsc::channel(100);
+        let (make_ack, outcomes) = recording_make_ack();
+
+        let pending_motion = PendingMotion::start(
+            atdome_model.clone(),
+            Some(ack_sender),
+            monitor_config(Duration::from_millis(50), Duration::from_secs(10)),
+            |_| false,
+            |_| String::new(),
+            make_ack,
+        );
+        atdome_model.disconnect();
+
+        let outcome = final_outcome(&pending_motion, &outcomes).await;
+        assert!(outcome.contains("Lost controller status"), "{outcome}");
+    }
+
+    #[tokio::test]
+    async fn test_cancel() {
+        let (atdome_model, _shutdown_sender) =
+            start_mock_and_model(MockControllerConfig::default()).await;
+        let (ack_sender, mut ack_receiver) = mpsc::channel(100);
+        let (make_ack, outcomes) = recording_make_ack();
+
+        let pending_motion = PendingMotion::start(
+            atdome_model,
+            Some(ack_sender),
+            monitor_config(Duration::from_millis(50), Duration::from_secs(10)),
+            |_| false,
+            |_| String::new(),
+            make_ack,
+        );
+        pending_motion.cancel("Aborted by stopMotion.");
+
+        // The final ack is the last one sent before the channel closes.
+        while ack_receiver.recv().await.is_some() {}
+        assert_eq!(
+            outcomes.lock().unwrap().last().unwrap(),
+            &format!("Failed {CMD_ABORTED}: Aborted by stopMotion.")
+        );
+    }
+}
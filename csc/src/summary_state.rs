@@ -0,0 +1,84 @@
+//! Summary state machine of the CSC.
+//!
+//! Each state command requests a transition; this module decides whether
+//! the transition is allowed from the current state and which state it
+//! leads to. What the CSC does on each transition lives in
+//! `ATDome::handle_summary_state`.
+
+use salobj::sal_enums::State;
+
+use crate::error::{ATDomeError, ATDomeResult};
+
+/// Transition requested by a state command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateTransition {
+    Start,
+    Enable,
+    Disable,
+    Standby,
+    ExitControl,
+}
+
+impl StateTransition {
+    /// States the transition is allowed from.
+    pub fn allowed_from(&self) -> &'static [State] {
+        match self {
+            StateTransition::Start => &[State::Standby],
+            StateTransition::Enable => &[State::Disabled],
+            StateTransition::Disable => &[State::Enabled],
+            StateTransition::Standby => &[State::Disabled, State::Fault],
+            StateTransition::ExitControl => &[State::Standby],
+        }
+    }
+
+    /// State the transition leads to.
+    pub fn target(&self) -> State {
+        match self {
+            StateTransition::Start | StateTransition::Disable => State::Disabled,
+            StateTransition::Enable => State::Enabled,
+            StateTransition::Standby => State::Standby,
+            StateTransition::ExitControl => State::Offline,
+        }
+    }
+
+    /// State the transition leads to from `current`, or an `InvalidState`
+    /// error if it is not allowed.
+    pub fn check(&self, current: State) -> ATDomeResult<State> {
+        if self.allowed_from().contains(&current) {
+            Ok(self.target())
+        } else {
+            Err(ATDomeError::InvalidState(format!(
+                "Invalid state transition {current:?} -> {:?}.",
+                self.target()
+            )))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        assert_eq!(
+            StateTransition::Start.check(State::Standby).unwrap(),
+            State::Disabled
+        );
+        assert_eq!(
+            StateTransition::Standby.check(State::Fault).unwrap(),
+            State::Standby
+        );
+        assert_eq!(
+            StateTransition::ExitControl.check(State::Standby).unwrap(),
+            State::Offline
+        );
+
+        let error = StateTransition::Enable.check(State::Standby).unwrap_err();
+        assert_eq!(
+            error.get_error_message(),
+            "Invalid state transition Standby -> Enabled."
+        );
+        assert!(StateTransition::Disable.check(State::Disabled).is_err());
+    }
+}
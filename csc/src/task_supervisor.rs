@@ -0,0 +1,216 @@
+//! Supervise the background tasks of the CSC.
+//!
+//! The heartbeat, telemetry, status polling and command reader tasks are
+//! expected to run for as long as the CSC does. The supervisor owns their
+//! join handles and reports the ones that exited, either returning or
+//! panicking, together with what the CSC should do about it.
+
+use std::{any::Any, collections::HashMap, future::Future};
+
+use tokio::task::{self, JoinHandle};
+
+/// What to do when a supervised task exits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitPolicy {
+    /// Start the task again.
+    Restart,
+    /// Send the CSC to Fault.
+    Fault,
+}
+
+/// A supervised task that exited unexpectedly.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaskExit {
+    pub name: String,
+    pub policy: ExitPolicy,
+    /// Why the task exited, e.g. the panic message.
+    pub reason: String,
+}
+
+struct SupervisedTask {
+    policy: ExitPolicy,
+    handle: JoinHandle<()>,
+}
+
+#[derive(Default)]
+pub struct TaskSupervisor {
+    tasks: HashMap<String, SupervisedTask>,
+}
+
+impl TaskSupervisor {
+    pub fn new() -> TaskSupervisor {
+        TaskSupervisor::default()
+    }
+
+    /// Spawn `future` as the task `name`, aborting any task already running
+    /// with that name.
+    pub fn spawn<F>(&mut self, name: &str, policy: ExitPolicy, future: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let handle = task::spawn(future);
+        if let Some(previous) = self
+            .tasks
+            .insert(name.to_owned(), SupervisedTask { policy, handle })
+        {
+            previous.handle.abort();
+        }
+    }
+
+    /// Is the task `name` supervised and still running?
+    pub fn is_running(&self, name: &str) -> bool {
+        self.tasks
+            .get(name)
+            .is_some_and(|supervised| !supervised.handle.is_finished())
+    }
+
+    /// Stop the task `name`; it is no longer supervised.
+    pub fn abort(&mut self, name: &str) {
+        if let Some(supervised) = self.tasks.remove(name) {
+            supervised.handle.abort();
+        }
+    }
+
+    /// Stop all tasks.
+    pub fn abort_all(&mut self) {
+        for (_, supervised) in self.tasks.drain() {
+            supervised.handle.abort();
+        }
+    }
+
+    /// Stop all tasks and wait for them to finish.
+    pub async fn shutdown(&mut self) {
+        for (name, supervised) in self.tasks.drain() {
+            supervised.handle.abort();
+            if let Err(error) = supervised.handle.await {
+                if error.is_panic() {
+                    tracing::error!(
+                        task = name,
+                        "Background task panicked: {}",
+                        panic_message(error.into_panic())
+                    );
+                }
+            }
+        }
+    }
+
+    /// Remove the tasks that exited and report why, sorted by name.
+    pub async fn check(&mut self) -> Vec<TaskExit> {
+        let mut finished: Vec<String> = self
+            .tasks
+            .iter()
+            .filter(|(_, supervised)| supervised.handle.is_finished())
+            .map(|(name, _)| name.to_owned())
+            .collect();
+        finished.sort();
+
+        let mut exits = Vec::with_capacity(finished.len());
+        for name in finished {
+            let Some(supervised) = self.tasks.remove(&name) else {
+                continue;
+            };
+            let reason = match supervised.handle.await {
+                Ok(()) => "returned".to_owned(),
+                Err(error) if error.is_panic() => {
+                    format!("panicked: {}", panic_message(error.into_panic()))
+                }
+                Err(error) => error.to_string(),
+            };
+            tracing::error!(task = name, reason, "Background task exited unexpectedly.");
+            exits.push(TaskExit {
+                name,
+                policy: supervised.policy,
+                reason,
+            });
+        }
+        exits
+    }
+}
+
+impl Drop for TaskSupervisor {
+    fn drop(&mut self) {
+        self.abort_all();
+    }
+}
+
+/// Text of a panic payload.
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.to_owned()
+    } else {
+        "unknown panic".to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::time::sleep;
+
+    #[tokio::test]
+    async fn test_check_reports_exits() {
+        let mut supervisor = TaskSupervisor::new();
+        supervisor.spawn("heartbeat", ExitPolicy::Restart, async {
+            panic!("lost the writer");
+        });
+        supervisor.spawn("status_poll", ExitPolicy::Fault, async {});
+        supervisor.spawn("telemetry", ExitPolicy::Restart, async {
+            sleep(Duration::from_secs(60)).await;
+        });
+        sleep(Duration::from_millis(50)).await;
+
+        let exits = supervisor.check().await;
+
+        assert_eq!(
+            exits,
+            vec![
+                TaskExit {
+                    name: "heartbeat".to_owned(),
+                    policy: ExitPolicy::Restart,
+                    reason: "panicked: lost the writer".to_owned(),
+                },
+                TaskExit {
+                    name: "status_poll".to_owned(),
+                    policy: ExitPolicy::Fault,
+                    reason: "returned".to_owned(),
+                },
+            ]
+        );
+        assert!(supervisor.is_running("telemetry"));
+        assert!(!supervisor.is_running("heartbeat"));
+        assert!(supervisor.check().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_aborted_task_is_not_reported() {
+        let mut supervisor = TaskSupervisor::new();
+        supervisor.spawn("status_poll", ExitPolicy::Fault, async {
+            sleep(Duration::from_secs(60)).await;
+        });
+
+        supervisor.abort("status_poll");
+        sleep(Duration::from_millis(50)).await;
+
+        assert!(!supervisor.is_running("status_poll"));
+        assert!(supervisor.check().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_waits_for_tasks() {
+        let (sender, mut receiver) = tokio::sync::mpsc::channel::<()>(1);
+        let mut supervisor = TaskSupervisor::new();
+        supervisor.spawn("heartbeat", ExitPolicy::Restart, async move {
+            let _sender = sender;
+            sleep(Duration::from_secs(60)).await;
+        });
+
+        supervisor.shutdown().await;
+
+        // The task, and the sender it owned, were dropped.
+        assert!(receiver.recv().await.is_none());
+        assert!(!supervisor.is_running("heartbeat"));
+    }
+}
@@ -0,0 +1,32 @@
+//! Event with the commanded state of the azimuth axis.
+
+use base_topic_derive::{add_sal_topic_fields, BaseSALTopic};
+use salobj::topics::base_sal_topic::BaseSALTopic;
+use serde::{Deserialize, Serialize};
+
+use crate::atdome_enums::AzimuthCommandedState as AzimuthCommandedStateEnum;
+
+#[add_sal_topic_fields]
+#[derive(Debug, Default, Clone, Deserialize, Serialize, BaseSALTopic)]
+#[allow(non_snake_case)]
+pub struct AzimuthCommandedState {
+    /// Commanded state.
+    commandedState: i32,
+    /// Commanded azimuth (deg).
+    azimuth: f64,
+}
+
+impl AzimuthCommandedState {
+    pub fn with_commanded_state(
+        mut self,
+        commanded_state: AzimuthCommandedStateEnum,
+    ) -> AzimuthCommandedState {
+        self.commandedState = commanded_state as i32;
+        self
+    }
+
+    pub fn with_azimuth(mut self, azimuth: f64) -> AzimuthCommandedState {
+        self.azimuth = azimuth;
+        self
+    }
+}
@@ -0,0 +1,24 @@
+//! Event reporting whether the azimuth is in position.
+
+use base_topic_derive::{add_sal_topic_fields, BaseSALTopic};
+use salobj::topics::base_sal_topic::BaseSALTopic;
+use serde::{Deserialize, Serialize};
+
+#[add_sal_topic_fields]
+#[derive(Debug, Default, Clone, Deserialize, Serialize, BaseSALTopic)]
+#[allow(non_snake_case)]
+pub struct AzimuthInPosition {
+    /// Is the azimuth in position?
+    inPosition: bool,
+}
+
+impl AzimuthInPosition {
+    pub fn with_in_position(mut self, in_position: bool) -> AzimuthInPosition {
+        self.inPosition = in_position;
+        self
+    }
+
+    pub fn get_in_position(&self) -> bool {
+        self.inPosition
+    }
+}
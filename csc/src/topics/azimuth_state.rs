@@ -0,0 +1,29 @@
+//! Event with the motion state of the azimuth axis.
+
+use base_topic_derive::{add_sal_topic_fields, BaseSALTopic};
+use salobj::topics::base_sal_topic::BaseSALTopic;
+use serde::{Deserialize, Serialize};
+
+use crate::atdome_enums::{AzimuthHomingState, AzimuthState as AzimuthStateEnum};
+
+#[add_sal_topic_fields]
+#[derive(Debug, Default, Clone, Deserialize, Serialize, BaseSALTopic)]
+#[allow(non_snake_case)]
+pub struct AzimuthState {
+    /// Motion state.
+    state: i32,
+    /// Homing state.
+    homing: i32,
+}
+
+impl AzimuthState {
+    pub fn with_state(mut self, state: AzimuthStateEnum) -> AzimuthState {
+        self.state = state as i32;
+        self
+    }
+
+    pub fn with_homing(mut self, homing: AzimuthHomingState) -> AzimuthState {
+        self.homing = homing as i32;
+        self
+    }
+}
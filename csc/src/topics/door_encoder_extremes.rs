@@ -0,0 +1,41 @@
+//! Event with the encoder values at the door extremes.
+
+use base_topic_derive::{add_sal_topic_fields, BaseSALTopic};
+use salobj::topics::base_sal_topic::BaseSALTopic;
+use serde::{Deserialize, Serialize};
+
+#[add_sal_topic_fields]
+#[derive(Debug, Default, Clone, Deserialize, Serialize, BaseSALTopic)]
+#[allow(non_snake_case)]
+pub struct DoorEncoderExtremes {
+    /// Main door encoder when closed.
+    mainClosed: i64,
+    /// Main door encoder when opened.
+    mainOpened: i64,
+    /// Dropout door encoder when closed.
+    dropoutClosed: i64,
+    /// Dropout door encoder when opened.
+    dropoutOpened: i64,
+}
+
+impl DoorEncoderExtremes {
+    pub fn with_main_closed(mut self, main_closed: i64) -> DoorEncoderExtremes {
+        self.mainClosed = main_closed;
+        self
+    }
+
+    pub fn with_main_opened(mut self, main_opened: i64) -> DoorEncoderExtremes {
+        self.mainOpened = main_opened;
+        self
+    }
+
+    pub fn with_dropout_closed(mut self, dropout_closed: i64) -> DoorEncoderExtremes {
+        self.dropoutClosed = dropout_closed;
+        self
+    }
+
+    pub fn with_dropout_opened(mut self, dropout_opened: i64) -> DoorEncoderExtremes {
+        self.dropoutOpened = dropout_opened;
+        self
+    }
+}
@@ -0,0 +1,25 @@
+//! Event with the commanded state of the dropout door.
+
+use base_topic_derive::{add_sal_topic_fields, BaseSALTopic};
+use salobj::topics::base_sal_topic::BaseSALTopic;
+use serde::{Deserialize, Serialize};
+
+use crate::atdome_enums::ShutterDoorCommandedState;
+
+#[add_sal_topic_fields]
+#[derive(Debug, Default, Clone, Deserialize, Serialize, BaseSALTopic)]
+#[allow(non_snake_case)]
+pub struct DropoutDoorCommandedState {
+    /// Commanded state.
+    commandedState: i32,
+}
+
+impl DropoutDoorCommandedState {
+    pub fn with_commanded_state(
+        mut self,
+        commanded_state: ShutterDoorCommandedState,
+    ) -> DropoutDoorCommandedState {
+        self.commandedState = commanded_state as i32;
+        self
+    }
+}
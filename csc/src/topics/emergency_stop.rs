@@ -0,0 +1,20 @@
+//! Event reporting the emergency stop state.
+
+use base_topic_derive::{add_sal_topic_fields, BaseSALTopic};
+use salobj::topics::base_sal_topic::BaseSALTopic;
+use serde::{Deserialize, Serialize};
+
+#[add_sal_topic_fields]
+#[derive(Debug, Default, Clone, Deserialize, Serialize, BaseSALTopic)]
+#[allow(non_snake_case)]
+pub struct EmergencyStop {
+    /// Is the emergency stop active?
+    active: bool,
+}
+
+impl EmergencyStop {
+    pub fn with_active(mut self, active: bool) -> EmergencyStop {
+        self.active = active;
+        self
+    }
+}
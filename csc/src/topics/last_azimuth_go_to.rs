@@ -0,0 +1,20 @@
+//! Event with the last azimuth commanded to the controller.
+
+use base_topic_derive::{add_sal_topic_fields, BaseSALTopic};
+use salobj::topics::base_sal_topic::BaseSALTopic;
+use serde::{Deserialize, Serialize};
+
+#[add_sal_topic_fields]
+#[derive(Debug, Default, Clone, Deserialize, Serialize, BaseSALTopic)]
+#[allow(non_snake_case)]
+pub struct LastAzimuthGoTo {
+    /// Azimuth (deg).
+    position: f64,
+}
+
+impl LastAzimuthGoTo {
+    pub fn with_position(mut self, position: f64) -> LastAzimuthGoTo {
+        self.position = position;
+        self
+    }
+}
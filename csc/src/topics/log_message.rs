@@ -0,0 +1,52 @@
+//! Event with a log message published by the CSC.
+
+use base_topic_derive::{add_sal_topic_fields, BaseSALTopic};
+use salobj::topics::base_sal_topic::BaseSALTopic;
+use serde::{Deserialize, Serialize};
+
+/// Level of warning messages, following the Python logging levels.
+pub const WARNING_LEVEL: i32 = 30;
+
+#[add_sal_topic_fields]
+#[derive(Debug, Default, Clone, Deserialize, Serialize, BaseSALTopic)]
+#[allow(non_snake_case)]
+pub struct LogMessage {
+    /// Name of the logger.
+    name: String,
+    /// Message level.
+    level: i32,
+    /// Message text.
+    message: String,
+    /// Traceback, if any.
+    traceback: String,
+    /// Source file that emitted the message.
+    filePath: String,
+    /// Function that emitted the message.
+    functionName: String,
+    /// Line that emitted the message.
+    lineNumber: i32,
+    /// Process id.
+    process: i32,
+}
+
+impl LogMessage {
+    pub fn with_name(mut self, name: &str) -> LogMessage {
+        self.name = name.to_owned();
+        self
+    }
+
+    pub fn with_level(mut self, level: i32) -> LogMessage {
+        self.level = level;
+        self
+    }
+
+    pub fn with_message(mut self, message: &str) -> LogMessage {
+        self.message = message.to_owned();
+        self
+    }
+
+    pub fn with_process(mut self, process: i32) -> LogMessage {
+        self.process = process;
+        self
+    }
+}
@@ -0,0 +1,22 @@
+//! Event with the state of the main door.
+
+use base_topic_derive::{add_sal_topic_fields, BaseSALTopic};
+use salobj::topics::base_sal_topic::BaseSALTopic;
+use serde::{Deserialize, Serialize};
+
+use crate::atdome_enums::ShutterDoorState;
+
+#[add_sal_topic_fields]
+#[derive(Debug, Default, Clone, Deserialize, Serialize, BaseSALTopic)]
+#[allow(non_snake_case)]
+pub struct MainDoorState {
+    /// Door state.
+    state: i32,
+}
+
+impl MainDoorState {
+    pub fn with_state(mut self, state: ShutterDoorState) -> MainDoorState {
+        self.state = state as i32;
+        self
+    }
+}
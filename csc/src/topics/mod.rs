@@ -0,0 +1,21 @@
+pub mod azimuth_commanded_state;
+pub mod azimuth_in_position;
+pub mod azimuth_state;
+pub mod door_encoder_extremes;
+pub mod dropout_door_commanded_state;
+pub mod dropout_door_state;
+pub mod emergency_stop;
+pub mod last_azimuth_go_to;
+pub mod log_message;
+pub mod main_door_commanded_state;
+pub mod main_door_state;
+pub mod move_azimuth;
+pub mod move_shutter_dropout_door;
+pub mod move_shutter_main_door;
+pub mod position;
+pub mod scb_link;
+pub mod set_log_level;
+pub mod settings_applied_dome_controller;
+pub mod shutter_in_position;
+pub mod telemetry;
+pub mod weather_sensors;
@@ -0,0 +1,24 @@
+//! Command to move the dome to an azimuth.
+
+use base_topic_derive::{add_sal_topic_fields, BaseSALTopic};
+use salobj::topics::base_sal_topic::BaseSALTopic;
+use serde::{Deserialize, Serialize};
+
+#[add_sal_topic_fields]
+#[derive(Debug, Default, Clone, Deserialize, Serialize, BaseSALTopic)]
+#[allow(non_snake_case)]
+pub struct MoveAzimuth {
+    /// Target azimuth (deg).
+    azimuth: f64,
+}
+
+impl MoveAzimuth {
+    pub fn with_azimuth(mut self, azimuth: f64) -> MoveAzimuth {
+        self.azimuth = azimuth;
+        self
+    }
+
+    pub fn get_azimuth(&self) -> f64 {
+        self.azimuth
+    }
+}
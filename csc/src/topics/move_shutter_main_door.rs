@@ -0,0 +1,19 @@
+//! Command to open or close the main door.
+
+use base_topic_derive::{add_sal_topic_fields, BaseSALTopic};
+use salobj::topics::base_sal_topic::BaseSALTopic;
+use serde::{Deserialize, Serialize};
+
+#[add_sal_topic_fields]
+#[derive(Debug, Default, Clone, Deserialize, Serialize, BaseSALTopic)]
+#[allow(non_snake_case)]
+pub struct MoveShutterMainDoor {
+    /// Open the door if true, close it otherwise.
+    open: bool,
+}
+
+impl MoveShutterMainDoor {
+    pub fn get_open(&self) -> bool {
+        self.open
+    }
+}
@@ -0,0 +1,71 @@
+//! Telemetry with the azimuth and door positions.
+
+use base_topic_derive::{add_sal_topic_fields, BaseSALTopic};
+use salobj::topics::base_sal_topic::BaseSALTopic;
+use serde::{Deserialize, Serialize};
+
+use crate::status::Status;
+
+#[add_sal_topic_fields]
+#[derive(Debug, Default, Clone, Deserialize, Serialize, BaseSALTopic)]
+#[allow(non_snake_case)]
+pub struct Position {
+    /// Dropout door opening (%).
+    dropoutDoorOpeningPercentage: f64,
+    /// Main door opening (%).
+    mainDoorOpeningPercentage: f64,
+    /// Current azimuth (deg).
+    azimuthPosition: f64,
+    /// Commanded azimuth (deg).
+    azimuthPositionSet: f64,
+}
+
+impl Position {
+    pub fn with_dropout_door_opening_percentage(
+        mut self,
+        dropout_door_opening_percentage: f64,
+    ) -> Position {
+        self.dropoutDoorOpeningPercentage = dropout_door_opening_percentage;
+        self
+    }
+
+    pub fn with_main_door_opening_percentage(
+        mut self,
+        main_door_opening_percentage: f64,
+    ) -> Position {
+        self.mainDoorOpeningPercentage = main_door_opening_percentage;
+        self
+    }
+
+    pub fn with_azimuth_position(mut self, azimuth_position: f64) -> Position {
+        self.azimuthPosition = azimuth_position;
+        self
+    }
+
+    pub fn with_azimuth_position_set(mut self, azimuth_position_set: f64) -> Position {
+        self.azimuthPositionSet = azimuth_position_set;
+        self
+    }
+
+    pub fn get_azimuth_position(&self) -> f64 {
+        self.azimuthPosition
+    }
+
+    pub fn get_main_door_opening_percentage(&self) -> f64 {
+        self.mainDoorOpeningPercentage
+    }
+
+    pub fn get_dropout_door_opening_percentage(&self) -> f64 {
+        self.dropoutDoorOpeningPercentage
+    }
+}
+
+impl From<&Status> for Position {
+    fn from(status: &Status) -> Position {
+        Position::default()
+            .with_dropout_door_opening_percentage(status.dropout_door_pct as f64)
+            .with_main_door_opening_percentage(status.main_door_pct as f64)
+            .with_azimuth_position(status.az_pos as f64)
+            .with_azimuth_position_set(status.last_azimuth_goto as f64)
+    }
+}
@@ -0,0 +1,20 @@
+//! Event reporting the state of the shutter control board link.
+
+use base_topic_derive::{add_sal_topic_fields, BaseSALTopic};
+use salobj::topics::base_sal_topic::BaseSALTopic;
+use serde::{Deserialize, Serialize};
+
+#[add_sal_topic_fields]
+#[derive(Debug, Default, Clone, Deserialize, Serialize, BaseSALTopic)]
+#[allow(non_snake_case)]
+pub struct ScbLink {
+    /// Is the link working?
+    active: bool,
+}
+
+impl ScbLink {
+    pub fn with_active(mut self, active: bool) -> ScbLink {
+        self.active = active;
+        self
+    }
+}
@@ -0,0 +1,36 @@
+//! Command to change the log level of the CSC.
+
+use base_topic_derive::{add_sal_topic_fields, BaseSALTopic};
+use salobj::topics::base_sal_topic::BaseSALTopic;
+use serde::{Deserialize, Serialize};
+
+#[add_sal_topic_fields]
+#[derive(Debug, Default, Clone, Deserialize, Serialize, BaseSALTopic)]
+#[allow(non_snake_case)]
+pub struct SetLogLevel {
+    /// Level, following the Python logging levels.
+    level: i32,
+    /// Module to set the level of, as a tracing target; the level of the
+    /// whole CSC is set if empty.
+    subsystem: String,
+}
+
+impl SetLogLevel {
+    pub fn with_level(mut self, level: i32) -> SetLogLevel {
+        self.level = level;
+        self
+    }
+
+    pub fn with_subsystem(mut self, subsystem: &str) -> SetLogLevel {
+        self.subsystem = subsystem.to_owned();
+        self
+    }
+
+    pub fn get_level(&self) -> i32 {
+        self.level
+    }
+
+    pub fn get_subsystem(&self) -> &str {
+        &self.subsystem
+    }
+}
@@ -0,0 +1,67 @@
+//! Event with the settings of the dome controller.
+
+use base_topic_derive::{add_sal_topic_fields, BaseSALTopic};
+use salobj::topics::base_sal_topic::BaseSALTopic;
+use serde::{Deserialize, Serialize};
+
+use crate::status::Status;
+
+#[add_sal_topic_fields]
+#[derive(Debug, Default, Clone, Deserialize, Serialize, BaseSALTopic)]
+#[allow(non_snake_case)]
+pub struct SettingsAppliedDomeController {
+    /// Is the rain-snow sensor interlock enabled?
+    rainSensorEnabled: bool,
+    /// Is the cloud sensor interlock enabled?
+    cloudSensorEnabled: bool,
+    /// Azimuth tolerance of a move (deg).
+    tolerance: f64,
+    /// Azimuth of the home switch (deg).
+    homeAzimuth: f64,
+    /// Distance beyond which the dome moves at high speed (deg).
+    highSpeedDistance: f64,
+    /// Watchdog reset time (sec).
+    watchdogTimer: f64,
+    /// Delay before reversing the azimuth drive (sec).
+    reversalDelay: f64,
+    /// Does the controller close the shutter on bad weather or loss of
+    /// the watchdog?
+    autoShutdownEnabled: bool,
+    /// Distance the dome coasts after the drive stops (deg).
+    coast: f64,
+    /// Encoder counts per azimuth revolution.
+    encoderCountsPer360: i32,
+    /// Time limit for door moves (sec).
+    doorMoveTimeout: f64,
+    /// Time limit for azimuth moves (sec).
+    azimuthMoveTimeout: f64,
+    /// Interval between heartbeat events of the CSC (sec).
+    heartbeatInterval: f64,
+}
+
+impl SettingsAppliedDomeController {
+    /// Set the fields from the settings reported in `status`.
+    pub fn with_status(mut self, status: &Status) -> SettingsAppliedDomeController {
+        self.rainSensorEnabled = status.rain_sensor_enabled;
+        self.cloudSensorEnabled = status.cloud_sensor_enabled;
+        self.tolerance = status.tolerance as f64;
+        self.homeAzimuth = status.home_azimuth as f64;
+        self.highSpeedDistance = status.high_speed as f64;
+        self.watchdogTimer = status.watchdog_timer as f64;
+        self.reversalDelay = status.reversal_delay as f64;
+        self.autoShutdownEnabled = status.auto_shutdown_enabled;
+        self.coast = status.coast as f64;
+        self.encoderCountsPer360 = status.encoder_counts_per_360 as i32;
+        self.doorMoveTimeout = status.door_move_timeout as f64;
+        self.azimuthMoveTimeout = status.azimuth_move_timeout as f64;
+        self
+    }
+
+    pub fn with_heartbeat_interval(
+        mut self,
+        heartbeat_interval: f64,
+    ) -> SettingsAppliedDomeController {
+        self.heartbeatInterval = heartbeat_interval;
+        self
+    }
+}
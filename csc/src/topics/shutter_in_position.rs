@@ -0,0 +1,20 @@
+//! Event reporting whether the shutter is in position.
+
+use base_topic_derive::{add_sal_topic_fields, BaseSALTopic};
+use salobj::topics::base_sal_topic::BaseSALTopic;
+use serde::{Deserialize, Serialize};
+
+#[add_sal_topic_fields]
+#[derive(Debug, Default, Clone, Deserialize, Serialize, BaseSALTopic)]
+#[allow(non_snake_case)]
+pub struct ShutterInPosition {
+    /// Are the doors in the commanded position?
+    inPosition: bool,
+}
+
+impl ShutterInPosition {
+    pub fn with_in_position(mut self, in_position: bool) -> ShutterInPosition {
+        self.inPosition = in_position;
+        self
+    }
+}
@@ -0,0 +1,10 @@
+//! Define the telemetry published by the ATDome CSC.
+
+use crate::topics::position::Position;
+
+#[derive(Debug, Default, Clone)]
+pub enum ATDomeTelemetry {
+    #[default]
+    None,
+    Position(Position),
+}
@@ -0,0 +1,28 @@
+//! Event with the state of the weather sensors of the dome controller.
+
+use base_topic_derive::{add_sal_topic_fields, BaseSALTopic};
+use salobj::topics::base_sal_topic::BaseSALTopic;
+use serde::{Deserialize, Serialize};
+
+use crate::status::SensorFlags;
+
+#[add_sal_topic_fields]
+#[derive(Debug, Default, Clone, Deserialize, Serialize, BaseSALTopic)]
+#[allow(non_snake_case)]
+pub struct WeatherSensors {
+    /// Is the rain-snow sensor tripped?
+    rainActive: bool,
+    /// Is the cloud sensor tripped?
+    cloudsActive: bool,
+    /// Does the controller close the shutter on bad weather?
+    autoShutdownEnabled: bool,
+}
+
+impl WeatherSensors {
+    pub fn with_flags(mut self, flags: &SensorFlags) -> WeatherSensors {
+        self.rainActive = flags.rain_active;
+        self.cloudsActive = flags.clouds_active;
+        self.autoShutdownEnabled = flags.auto_shutdown_enabled;
+        self
+    }
+}
@@ -0,0 +1,189 @@
+//! End-to-end tests of the CSC against the mock controller.
+//!
+//! These tests only run when `LSST_KAFKA_BROKER_ADDR` is set, e.g.:
+//!
+//! ```text
+//! LSST_KAFKA_BROKER_ADDR=localhost:9092 \
+//! LSST_SCHEMA_REGISTRY_URL=http://localhost:8081 \
+//! cargo test --test csc_end_to_end
+//! ```
+
+mod harness;
+
+use harness::CscHarness;
+use rubin_ts_atdome_csc::{
+    atdome_config::ATDomeConfig,
+    mock_controller::mock_controller::{run_mock_controller_on, MockControllerConfig},
+    status::Status,
+    topics::{
+        azimuth_in_position::AzimuthInPosition, move_azimuth::MoveAzimuth, position::Position,
+    },
+};
+use salobj::{
+    generics::{
+        disable::Disable, empty_topic::EmptyTopic, exit_control::ExitControl, standby::Standby,
+    },
+    sal_enums::State,
+};
+use tokio::{net::TcpListener, sync::oneshot};
+
+/// Assert both doors are reported at `pct` (%) within a few position
+/// samples, allowing for samples taken before the command completed.
+async fn assert_doors_reach(harness: &mut CscHarness, pct: f64) {
+    for _ in 0..3 {
+        let position = harness.next_telemetry::<Position>("position").await;
+        if position.get_main_door_opening_percentage() == pct
+            && position.get_dropout_door_opening_percentage() == pct
+        {
+            return;
+        }
+    }
+    panic!("The doors were not reported at {pct}%.");
+}
+
+#[tokio::test]
+async fn test_move_azimuth() {
+    let Some(mut harness) = CscHarness::start().await else {
+        return;
+    };
+    harness.enable().await;
+
+    harness
+        .run_command("moveAzimuth", &MoveAzimuth::default().with_azimuth(10.0))
+        .await;
+
+    let azimuth_in_position = harness
+        .next_event::<AzimuthInPosition>("logevent_azimuthInPosition")
+        .await;
+    assert!(azimuth_in_position.get_in_position());
+    let position = harness.next_telemetry::<Position>("position").await;
+    assert!((position.get_azimuth_position() - 10.0).abs() < 0.5);
+
+    harness.run_command("disable", &Disable::default()).await;
+    harness.assert_summary_state(State::Disabled).await;
+    harness.stop().await.expect("The CSC failed.");
+}
+
+#[tokio::test]
+async fn test_open_and_close_shutter() {
+    let Some(mut harness) = CscHarness::start().await else {
+        return;
+    };
+    harness.enable().await;
+
+    // The commands complete only once the doors reach the target.
+    harness
+        .run_command("openShutter", &EmptyTopic::default())
+        .await;
+    assert_doors_reach(&mut harness, 100.0).await;
+
+    harness
+        .run_command("closeShutter", &EmptyTopic::default())
+        .await;
+    assert_doors_reach(&mut harness, 0.0).await;
+
+    harness.run_command("disable", &Disable::default()).await;
+    harness.assert_summary_state(State::Disabled).await;
+    harness.stop().await.expect("The CSC failed.");
+}
+
+#[tokio::test]
+async fn test_require_homed_to_move() {
+    let config = ATDomeConfig {
+        require_homed_to_move: true,
+        ..Default::default()
+    };
+    let Some(mut harness) = CscHarness::start_with(config).await else {
+        return;
+    };
+    harness.enable().await;
+
+    // The simulated controller starts unhomed.
+    let move_azimuth = MoveAzimuth::default().with_azimuth(20.0);
+    harness
+        .assert_command_fails("moveAzimuth", &move_azimuth)
+        .await;
+
+    harness
+        .run_command("homeAzimuth", &EmptyTopic::default())
+        .await;
+    harness.run_command("moveAzimuth", &move_azimuth).await;
+
+    harness.run_command("disable", &Disable::default()).await;
+    harness.assert_summary_state(State::Disabled).await;
+    harness.stop().await.expect("The CSC failed.");
+}
+
+#[tokio::test]
+async fn test_shutdown_stops_the_dome() {
+    // Run the controller here, saving its state on exit, to see what the
+    // CSC left it doing.
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port() as usize;
+    let state_file =
+        std::env::temp_dir().join(format!("atdome-shutdown-{}.json", std::process::id()));
+    let _ = std::fs::remove_file(&state_file);
+    let (mock_shutdown_sender, mock_shutdown_receiver) = oneshot::channel::<()>();
+    let mock = tokio::spawn(run_mock_controller_on(
+        listener,
+        MockControllerConfig {
+            state_file: Some(state_file.clone()),
+            ..Default::default()
+        },
+        async {
+            let _ = mock_shutdown_receiver.await;
+        },
+    ));
+    let config = ATDomeConfig {
+        port,
+        ..Default::default()
+    };
+    let Some(mut harness) = CscHarness::start_against(config).await else {
+        return;
+    };
+    harness.enable().await;
+
+    harness
+        .start_command("moveAzimuth", &MoveAzimuth::default().with_azimuth(90.0))
+        .await;
+    let mut moving = false;
+    for _ in 0..5 {
+        let position = harness.next_telemetry::<Position>("position").await;
+        if position.get_azimuth_position() > 1.0 {
+            moving = true;
+            break;
+        }
+    }
+    assert!(moving, "The dome did not start moving.");
+
+    // Shut down while enabled, as on SIGTERM.
+    harness.stop().await.expect("The CSC failed.");
+    mock_shutdown_sender.send(()).unwrap();
+    mock.await.unwrap().unwrap();
+
+    let saved = std::fs::read_to_string(&state_file).unwrap();
+    std::fs::remove_file(&state_file).unwrap();
+    let status: Status = serde_json::from_str(&saved).unwrap();
+    assert!(status.az_pos < 90.0);
+    assert_eq!(status.last_azimuth_goto, status.az_pos);
+    assert_eq!(status.move_code, 0);
+}
+
+#[tokio::test]
+async fn test_exit_control() {
+    let Some(mut harness) = CscHarness::start().await else {
+        return;
+    };
+    harness.enable().await;
+
+    harness.run_command("disable", &Disable::default()).await;
+    harness.assert_summary_state(State::Disabled).await;
+    harness.run_command("standby", &Standby::default()).await;
+    harness.assert_summary_state(State::Standby).await;
+    harness
+        .run_command("exitControl", &ExitControl::default())
+        .await;
+    harness.assert_summary_state(State::Offline).await;
+
+    harness.stop().await.expect("The CSC failed.");
+}
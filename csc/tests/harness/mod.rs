@@ -0,0 +1,205 @@
+//! Harness to run the CSC against the mock controller and drive it over SAL.
+//!
+//! The CSC talks to Kafka, so the harness only starts when
+//! `LSST_KAFKA_BROKER_ADDR` points to a broker (and the schema registry and
+//! topic subname are configured the same way as for the CSC itself).
+//!
+//! ATDome is not indexed and salobj takes the topic subname from the
+//! environment, so every CSC of a test run uses the same topics: harnesses
+//! run one at a time, so a remote only sees its own CSC.
+
+use std::net::TcpListener;
+
+use apache_avro::from_value;
+use rubin_ts_atdome_csc::{
+    atdome_config::{ATDomeConfig, KAFKA_BROKER_ADDRESS_ENV},
+    atdome_csc::run_atdome_csc_with_shutdown,
+    error::ATDomeResult,
+};
+use salobj::{
+    domain::Domain,
+    generics::{enable::Enable, start::Start, summary_state::SummaryState},
+    remote::Remote,
+    sal_enums::State,
+    sal_info::SalInfo,
+    topics::base_sal_topic::BaseSALTopic,
+};
+use serde::de::DeserializeOwned;
+use tokio::{
+    sync::{oneshot, Mutex, MutexGuard},
+    task::JoinHandle,
+    time::{timeout, Duration},
+};
+
+/// Time limit for a command to be acknowledged as complete.
+pub const COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+/// Time limit to receive an event or telemetry sample.
+pub const TOPIC_TIMEOUT: Duration = Duration::from_secs(30);
+/// Time limit for the CSC to shut down.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+/// Time given to a command sent without waiting for it to complete.
+const COMMAND_START_TIME: Duration = Duration::from_secs(2);
+
+/// Held by the running harness; the tests of a binary run in parallel.
+static CSC_LOCK: Mutex<()> = Mutex::const_new(());
+
+/// CSC running in simulation mode with a remote to command it.
+pub struct CscHarness {
+    pub remote: Remote,
+    shutdown: Option<oneshot::Sender<()>>,
+    csc_task: Option<JoinHandle<ATDomeResult<()>>>,
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl CscHarness {
+    /// Start the CSC in Standby, or return None if no broker is configured.
+    pub async fn start() -> Option<CscHarness> {
+        CscHarness::start_with(ATDomeConfig::default()).await
+    }
+
+    /// Start the CSC in Standby with `config`, or return None if no broker
+    /// is configured; the port and timeouts are overridden for the test.
+    pub async fn start_with(config: ATDomeConfig) -> Option<CscHarness> {
+        let config = ATDomeConfig {
+            port: ephemeral_port(),
+            ..config
+        };
+        CscHarness::launch(config, true).await
+    }
+
+    /// Start the CSC in Standby connected to a controller the test runs on
+    /// `config.port`, instead of the CSC's own mock, or return None if no
+    /// broker is configured.
+    pub async fn start_against(config: ATDomeConfig) -> Option<CscHarness> {
+        CscHarness::launch(config, false).await
+    }
+
+    async fn launch(config: ATDomeConfig, simulation_mode: bool) -> Option<CscHarness> {
+        if std::env::var(KAFKA_BROKER_ADDRESS_ENV).is_err() {
+            println!("{KAFKA_BROKER_ADDRESS_ENV} not set; skipping.");
+            return None;
+        }
+        let lock = CSC_LOCK.lock().await;
+        let config = ATDomeConfig {
+            connection_timeout: 5.0,
+            status_poll_interval: 0.2,
+            // The heartbeats of the previous test's CSC may still be read.
+            duplicate_check_time: 0.0,
+            ..config
+        };
+
+        let (shutdown_sender, shutdown_receiver) = oneshot::channel::<()>();
+        let csc_task = tokio::spawn(run_atdome_csc_with_shutdown(
+            config,
+            simulation_mode,
+            State::Standby,
+            async move {
+                let _ = shutdown_receiver.await;
+            },
+        ));
+
+        let mut domain = Domain::new();
+        let sal_info = SalInfo::new("ATDome", 0).expect("Failed to load the ATDome interface.");
+        let mut remote = Remote::new(&mut domain, &sal_info);
+        remote.start().await;
+
+        let mut harness = CscHarness {
+            remote,
+            shutdown: Some(shutdown_sender),
+            csc_task: Some(csc_task),
+            _lock: lock,
+        };
+        harness.assert_summary_state(State::Standby).await;
+        Some(harness)
+    }
+
+    /// Run `command` and wait for it to complete.
+    pub async fn run_command<T: BaseSALTopic>(&mut self, command: &str, data: &T) {
+        self.remote
+            .run_command(command, data, COMMAND_TIMEOUT)
+            .await
+            .unwrap_or_else(|error| panic!("Command {command} failed: {error:?}"));
+    }
+
+    /// Send `command` and give it time to start, without waiting for it to
+    /// complete.
+    pub async fn start_command<T: BaseSALTopic>(&mut self, command: &str, data: &T) {
+        let _ = timeout(
+            COMMAND_START_TIME,
+            self.remote.run_command(command, data, COMMAND_TIMEOUT),
+        )
+        .await;
+    }
+
+    /// Run `command` and assert it is rejected or fails.
+    pub async fn assert_command_fails<T: BaseSALTopic>(&mut self, command: &str, data: &T) {
+        let result = self
+            .remote
+            .run_command(command, data, COMMAND_TIMEOUT)
+            .await;
+        assert!(result.is_err(), "Command {command} unexpectedly succeeded.");
+    }
+
+    /// Wait for the next sample of the event `name`.
+    pub async fn next_event<T: DeserializeOwned>(&mut self, name: &str) -> T {
+        let value = timeout(TOPIC_TIMEOUT, self.remote.next_event(name))
+            .await
+            .unwrap_or_else(|_| panic!("Timed out waiting for {name}."))
+            .unwrap_or_else(|error| panic!("Failed to read {name}: {error:?}"));
+        from_value::<T>(&value).unwrap_or_else(|error| panic!("Invalid {name}: {error}"))
+    }
+
+    /// Wait for the next sample of the telemetry topic `name`.
+    pub async fn next_telemetry<T: DeserializeOwned>(&mut self, name: &str) -> T {
+        let value = timeout(TOPIC_TIMEOUT, self.remote.next_telemetry(name))
+            .await
+            .unwrap_or_else(|_| panic!("Timed out waiting for {name}."))
+            .unwrap_or_else(|error| panic!("Failed to read {name}: {error:?}"));
+        from_value::<T>(&value).unwrap_or_else(|error| panic!("Invalid {name}: {error}"))
+    }
+
+    /// Assert the next summaryState event reports `state`.
+    pub async fn assert_summary_state(&mut self, state: State) {
+        let summary_state = self
+            .next_event::<SummaryState>("logevent_summaryState")
+            .await;
+        assert_eq!(summary_state.get_summary_state(), state);
+    }
+
+    /// Take the CSC from Standby to Enabled.
+    pub async fn enable(&mut self) {
+        self.run_command("start", &Start::default()).await;
+        self.assert_summary_state(State::Disabled).await;
+        self.run_command("enable", &Enable::default()).await;
+        self.assert_summary_state(State::Enabled).await;
+    }
+
+    /// Shut the CSC down, the way a SIGTERM does, and return how it exited.
+    pub async fn stop(mut self) -> ATDomeResult<()> {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+        let csc_task = self.csc_task.take().expect("The CSC was already stopped.");
+        timeout(SHUTDOWN_TIMEOUT, csc_task)
+            .await
+            .expect("Timed out waiting for the CSC to shut down.")
+            .expect("The CSC task panicked.")
+    }
+}
+
+impl Drop for CscHarness {
+    fn drop(&mut self) {
+        if let Some(csc_task) = &self.csc_task {
+            csc_task.abort();
+        }
+    }
+}
+
+/// A port nobody listens on, for the mock controller.
+pub fn ephemeral_port() -> usize {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind an ephemeral port.");
+    listener
+        .local_addr()
+        .expect("Failed to read the ephemeral port.")
+        .port() as usize
+}
@@ -0,0 +1,37 @@
+//! Feed arbitrary client input through the command path of the mock
+//! controller: decoding, command parsing and execution must never panic.
+//!
+//! ```text
+//! cargo +nightly fuzz run mock_command
+//! ```
+
+#![no_main]
+
+use std::sync::OnceLock;
+
+use libfuzzer_sys::fuzz_target;
+use rubin_ts_atdome::{
+    atdome_cmd_regex::ATDomeCmdRegex,
+    mock_controller::mock_controller::{
+        decode_client_input, execute_command, update_azimuth, ClientInput,
+    },
+    status::Status,
+};
+
+fn atdome_cmd_regex() -> &'static ATDomeCmdRegex {
+    static ATDOME_CMD_REGEX: OnceLock<ATDomeCmdRegex> = OnceLock::new();
+    ATDOME_CMD_REGEX.get_or_init(ATDomeCmdRegex::new)
+}
+
+fuzz_target!(|data: &[u8]| {
+    let mut status = Status::default();
+    // The client may send several lines at once; run each as the mock
+    // would if they arrived in separate reads.
+    for chunk in data.split_inclusive(|byte| *byte == b'\n') {
+        if let ClientInput::Command(atdome_cmd) = decode_client_input(atdome_cmd_regex(), chunk) {
+            execute_command(&mut status, atdome_cmd);
+            update_azimuth(&mut status, 2.0);
+            let _ = status.as_string();
+        }
+    }
+});
@@ -0,0 +1,19 @@
+//! Standard SAL command ack codes.
+//!
+//! These are the values of `SalRetCode` used by every CSC to report the
+//! outcome of a command, so clients can tell a command that was not allowed
+//! from one that failed or timed out without parsing the message.
+
+pub const CMD_ACK: i32 = 300;
+pub const CMD_INPROGRESS: i32 = 301;
+pub const CMD_STALLED: i32 = 302;
+pub const CMD_COMPLETE: i32 = 303;
+/// The command is not allowed in the current state.
+pub const CMD_NOPERM: i32 = -300;
+pub const CMD_NOACK: i32 = -301;
+/// The command was accepted but failed to execute.
+pub const CMD_FAILED: i32 = -302;
+/// The command was superseded or cancelled, e.g. by stopMotion.
+pub const CMD_ABORTED: i32 = -303;
+/// The command did not complete in the expected time.
+pub const CMD_TIMEOUT: i32 = -304;
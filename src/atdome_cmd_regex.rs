@@ -1,9 +1,14 @@
 //! Parse dome commands into the ATDomeCmd struct.
-use regex::{Regex, RegexSet};
+//!
+//! Commands are matched case-insensitively against the whole text, with
+//! any whitespace around the command and between a value and its code, as
+//! engineers type them at a telnet prompt; anything else is `Unknown`.
+use regex::{Captures, Regex};
+use thiserror::Error as ThisError;
 
-use crate::atdome_model::ATDomeCmd;
+use crate::{atdome_model::ATDomeCmd, error::ATDomeError};
 
-const MOVE_AZ_REGEX: &str = r"(?P<az>[0-9]*) MV";
+const MOVE_AZ_REGEX: &str = r"(?P<az>[0-9]+\.?[0-9]*|\.[0-9]+)\s*MV";
 const CLOSE_SHUTTER_REGEX: &str = r"SC";
 const OPEN_SHUTTER_REGEX: &str = r"SO";
 const STOP_MOTION_REGEX: &str = r"ST";
@@ -13,64 +18,257 @@ const CLOSE_SHUTTHER_DROPOUT_REGEX: &str = r"UP";
 const OPEN_SHUTTHER_MAIN_DOOR_REGEX: &str = r"OP";
 const CLOSE_SHUTTHER_MAIN_DOOR_REGEX: &str = r"CL";
 const GET_STATUS_REGEX: &str = r"\+";
+const SET_HOME_AZIMUTH_REGEX: &str = r"(?P<value>[0-9]+\.?[0-9]*|\.[0-9]+)\s*HZ";
+const SET_TOLERANCE_REGEX: &str = r"(?P<value>[0-9]+\.?[0-9]*|\.[0-9]+)\s*TL";
+const SET_COAST_REGEX: &str = r"(?P<value>[0-9]+\.?[0-9]*|\.[0-9]+)\s*CO";
+const SET_HIGH_SPEED_REGEX: &str = r"(?P<value>[0-9]+\.?[0-9]*|\.[0-9]+)\s*HS";
+const SET_WATCHDOG_TIMER_REGEX: &str = r"(?P<value>[0-9]+\.?[0-9]*|\.[0-9]+)\s*WD";
+const SET_AZIMUTH_MOVE_TIMEOUT_REGEX: &str = r"(?P<value>[0-9]+\.?[0-9]*|\.[0-9]+)\s*AT";
+const SET_DOOR_MOVE_TIMEOUT_REGEX: &str = r"(?P<value>[0-9]+\.?[0-9]*|\.[0-9]+)\s*DT";
+const SET_RAIN_SENSOR_ENABLED_REGEX: &str = r"(?P<enabled>[01])\s*RS";
+const SET_CLOUD_SENSOR_ENABLED_REGEX: &str = r"(?P<enabled>[01])\s*CS";
+const SET_AUTO_SHUTDOWN_ENABLED_REGEX: &str = r"(?P<enabled>[01])\s*AS";
+
+/// Build a command from the captures of its pattern; returns None if a
+/// captured value is invalid.
+type Constructor = fn(&Captures) -> Option<ATDomeCmd>;
+
+/// Entry of the command table.
+struct CommandSpec {
+    /// Pattern matching the command, without anchors or flags.
+    pattern: &'static str,
+    /// Usage reported when a text is close to this command.
+    usage: &'static str,
+    constructor: Constructor,
+}
+
+/// Commands recognized by the parser, tried in order.
+///
+/// This is the only place that pairs a pattern with a command, so the
+/// order of the entries does not affect which command a pattern maps to.
+const COMMANDS: [CommandSpec; 20] = [
+    CommandSpec {
+        pattern: MOVE_AZ_REGEX,
+        usage: "<az> MV",
+        constructor: |captures| parse_value(&captures["az"]).map(ATDomeCmd::MoveAz),
+    },
+    CommandSpec {
+        pattern: CLOSE_SHUTTER_REGEX,
+        usage: "SC",
+        constructor: |_| Some(ATDomeCmd::CloseShutter),
+    },
+    CommandSpec {
+        pattern: OPEN_SHUTTER_REGEX,
+        usage: "SO",
+        constructor: |_| Some(ATDomeCmd::OpenShutter),
+    },
+    CommandSpec {
+        pattern: STOP_MOTION_REGEX,
+        usage: "ST",
+        constructor: |_| Some(ATDomeCmd::StopMotion),
+    },
+    CommandSpec {
+        pattern: HOME_AZIMUTH_REGEX,
+        usage: "HM",
+        constructor: |_| Some(ATDomeCmd::HomeAzimuth),
+    },
+    CommandSpec {
+        pattern: OPEN_SHUTTHER_DROPOUT_REGEX,
+        usage: "DN",
+        constructor: |_| Some(ATDomeCmd::OpenShutterDropoutDoor),
+    },
+    CommandSpec {
+        pattern: CLOSE_SHUTTHER_DROPOUT_REGEX,
+        usage: "UP",
+        constructor: |_| Some(ATDomeCmd::CloseShutterDropoutDoor),
+    },
+    CommandSpec {
+        pattern: OPEN_SHUTTHER_MAIN_DOOR_REGEX,
+        usage: "OP",
+        constructor: |_| Some(ATDomeCmd::OpenShutterMainDoor),
+    },
+    CommandSpec {
+        pattern: CLOSE_SHUTTHER_MAIN_DOOR_REGEX,
+        usage: "CL",
+        constructor: |_| Some(ATDomeCmd::CloseShutterMainDoor),
+    },
+    CommandSpec {
+        pattern: GET_STATUS_REGEX,
+        usage: "+",
+        constructor: |_| Some(ATDomeCmd::GetStatus),
+    },
+    CommandSpec {
+        pattern: SET_HOME_AZIMUTH_REGEX,
+        usage: "<az> HZ",
+        constructor: |captures| parse_value(&captures["value"]).map(ATDomeCmd::SetHomeAzimuth),
+    },
+    CommandSpec {
+        pattern: SET_TOLERANCE_REGEX,
+        usage: "<deg> TL",
+        constructor: |captures| parse_value(&captures["value"]).map(ATDomeCmd::SetTolerance),
+    },
+    CommandSpec {
+        pattern: SET_COAST_REGEX,
+        usage: "<deg> CO",
+        constructor: |captures| parse_value(&captures["value"]).map(ATDomeCmd::SetCoast),
+    },
+    CommandSpec {
+        pattern: SET_HIGH_SPEED_REGEX,
+        usage: "<deg> HS",
+        constructor: |captures| parse_value(&captures["value"]).map(ATDomeCmd::SetHighSpeed),
+    },
+    CommandSpec {
+        pattern: SET_WATCHDOG_TIMER_REGEX,
+        usage: "<sec> WD",
+        constructor: |captures| parse_value(&captures["value"]).map(ATDomeCmd::SetWatchdogTimer),
+    },
+    CommandSpec {
+        pattern: SET_AZIMUTH_MOVE_TIMEOUT_REGEX,
+        usage: "<sec> AT",
+        constructor: |captures| {
+            parse_value(&captures["value"]).map(ATDomeCmd::SetAzimuthMoveTimeout)
+        },
+    },
+    CommandSpec {
+        pattern: SET_DOOR_MOVE_TIMEOUT_REGEX,
+        usage: "<sec> DT",
+        constructor: |captures| parse_value(&captures["value"]).map(ATDomeCmd::SetDoorMoveTimeout),
+    },
+    CommandSpec {
+        pattern: SET_RAIN_SENSOR_ENABLED_REGEX,
+        usage: "<0|1> RS",
+        constructor: |captures| Some(ATDomeCmd::SetRainSensorEnabled(&captures["enabled"] == "1")),
+    },
+    CommandSpec {
+        pattern: SET_CLOUD_SENSOR_ENABLED_REGEX,
+        usage: "<0|1> CS",
+        constructor: |captures| {
+            Some(ATDomeCmd::SetCloudSensorEnabled(
+                &captures["enabled"] == "1",
+            ))
+        },
+    },
+    CommandSpec {
+        pattern: SET_AUTO_SHUTDOWN_ENABLED_REGEX,
+        usage: "<0|1> AS",
+        constructor: |captures| {
+            Some(ATDomeCmd::SetAutoShutdownEnabled(
+                &captures["enabled"] == "1",
+            ))
+        },
+    },
+];
+
+/// Failure to parse a command string.
+#[derive(Debug, Clone, PartialEq, ThisError)]
+#[error("Unrecognized command {text:?}{}", expected_usage(.closest_usage))]
+pub struct CmdParseError {
+    /// Text that failed to parse.
+    pub text: String,
+    /// Pattern of the command closest to the text, if any is close.
+    pub closest_pattern: Option<&'static str>,
+    /// Usage of the command closest to the text, if any is close.
+    pub closest_usage: Option<&'static str>,
+}
+
+impl From<CmdParseError> for ATDomeError {
+    fn from(error: CmdParseError) -> ATDomeError {
+        ATDomeError::Parse(error.to_string())
+    }
+}
 
 pub struct ATDomeCmdRegex {
-    regex_set: RegexSet,
+    /// Compiled pattern of each entry of `COMMANDS`.
     regex: Vec<Regex>,
 }
 
 impl ATDomeCmdRegex {
     pub fn new() -> ATDomeCmdRegex {
-        let regex_set = RegexSet::new([
-            MOVE_AZ_REGEX,
-            CLOSE_SHUTTER_REGEX,
-            OPEN_SHUTTER_REGEX,
-            STOP_MOTION_REGEX,
-            HOME_AZIMUTH_REGEX,
-            OPEN_SHUTTHER_DROPOUT_REGEX,
-            CLOSE_SHUTTHER_DROPOUT_REGEX,
-            OPEN_SHUTTHER_MAIN_DOOR_REGEX,
-            CLOSE_SHUTTHER_MAIN_DOOR_REGEX,
-            GET_STATUS_REGEX,
-        ])
-        .unwrap();
-
-        let regex = regex_set
-            .patterns()
-            .into_iter()
-            .map(|pattern| Regex::new(pattern).unwrap())
+        let regex = COMMANDS
+            .iter()
+            .map(|command| Regex::new(&format!(r"(?i)^\s*(?:{})\s*$", command.pattern)).unwrap())
             .collect();
 
-        ATDomeCmdRegex { regex_set, regex }
-    }
-
-    fn get_match_index(&self, text: &str) -> Option<usize> {
-        self.regex_set.matches(text).into_iter().next()
-    }
-
-    pub fn into_atdome_cmd(&self, text: &str) -> ATDomeCmd {
-        if let Some(match_index) = self.get_match_index(text) {
-            match match_index {
-                0 => {
-                    let capture = self.regex[match_index].captures(text).unwrap();
-                    let az_value: f32 = capture["az"].parse().unwrap();
-                    ATDomeCmd::MoveAz(az_value)
-                }
-                9 => ATDomeCmd::GetStatus,
-                1 => ATDomeCmd::CloseShutter,
-                2 => ATDomeCmd::OpenShutter,
-                3 => ATDomeCmd::StopMotion,
-                4 => ATDomeCmd::HomeAzimuth,
-                5 => ATDomeCmd::OpenShutterDropoutDoor,
-                6 => ATDomeCmd::CloseShutterDropoutDoor,
-                7 => ATDomeCmd::OpenShutterMainDoor,
-                8 => ATDomeCmd::CloseShutterMainDoor,
-                _ => ATDomeCmd::Unknown,
-            }
+        ATDomeCmdRegex { regex }
+    }
+
+    pub fn into_atdome_cmd(&self, text: &str) -> Result<ATDomeCmd, CmdParseError> {
+        COMMANDS
+            .iter()
+            .zip(&self.regex)
+            .find_map(|(command, regex)| regex.captures(text).map(|captures| (command, captures)))
+            .and_then(|(command, captures)| (command.constructor)(&captures))
+            .ok_or_else(|| CmdParseError::new(text))
+    }
+}
+
+impl Default for ATDomeCmdRegex {
+    fn default() -> ATDomeCmdRegex {
+        ATDomeCmdRegex::new()
+    }
+}
+
+impl CmdParseError {
+    /// Describe why `text` failed to parse, finding the command whose code
+    /// is closest to the code in the text.
+    fn new(text: &str) -> CmdParseError {
+        let code: String = text
+            .chars()
+            .filter(|c| !c.is_ascii_digit() && *c != '.' && !c.is_whitespace())
+            .collect::<String>()
+            .to_uppercase();
+        let closest_index = if code.is_empty() {
+            None
         } else {
-            ATDomeCmd::Unknown
+            COMMANDS
+                .iter()
+                .map(|command| command.usage.rsplit(' ').next().unwrap_or(command.usage))
+                .enumerate()
+                .filter_map(|(index, command_code)| {
+                    let distance = edit_distance(&code, command_code);
+                    // Also accept words that start with the code, e.g. "OPEN".
+                    if distance <= 1 || code.starts_with(command_code) {
+                        Some((distance, index))
+                    } else {
+                        None
+                    }
+                })
+                .min()
+                .map(|(_, index)| index)
+        };
+        CmdParseError {
+            text: text.to_owned(),
+            closest_pattern: closest_index.map(|index| COMMANDS[index].pattern),
+            closest_usage: closest_index.map(|index| COMMANDS[index].usage),
+        }
+    }
+}
+
+/// Suggestion appended to the parse error message.
+fn expected_usage(closest_usage: &Option<&str>) -> String {
+    closest_usage
+        .map(|usage| format!("; expected \"{usage}\""))
+        .unwrap_or_default()
+}
+
+/// Number of single character edits needed to turn `a` into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
         }
+        previous = current;
     }
+    previous[b.len()]
+}
+
+/// Parse the value of a command, rejecting values that overflow.
+fn parse_value(text: &str) -> Option<f32> {
+    text.parse::<f32>().ok().filter(|value| value.is_finite())
 }
 
 #[cfg(test)]
@@ -81,16 +279,113 @@ mod test {
     fn test_into_atdome_cmd_move_az() {
         let atdome_cmd_regex = ATDomeCmdRegex::new();
 
-        let atdome_cmd = atdome_cmd_regex.into_atdome_cmd("101 MV");
+        let atdome_cmd = atdome_cmd_regex.into_atdome_cmd("101 MV").unwrap();
 
         assert!(matches!(atdome_cmd, ATDomeCmd::MoveAz(101.0)))
     }
 
+    #[test]
+    fn test_into_atdome_cmd_move_az_fractional() {
+        let atdome_cmd_regex = ATDomeCmdRegex::new();
+
+        let atdome_cmd = atdome_cmd_regex.into_atdome_cmd("12.5 MV").unwrap();
+
+        assert!(matches!(atdome_cmd, ATDomeCmd::MoveAz(12.5)))
+    }
+
+    #[test]
+    fn test_into_atdome_cmd_move_az_trailing_point() {
+        let atdome_cmd_regex = ATDomeCmdRegex::new();
+
+        let atdome_cmd = atdome_cmd_regex.into_atdome_cmd("123. MV").unwrap();
+
+        assert!(matches!(atdome_cmd, ATDomeCmd::MoveAz(123.0)))
+    }
+
+    #[test]
+    fn test_into_atdome_cmd_move_az_overflow() {
+        let atdome_cmd_regex = ATDomeCmdRegex::new();
+
+        let error = atdome_cmd_regex
+            .into_atdome_cmd(&format!("{} MV", "9".repeat(50)))
+            .unwrap_err();
+
+        assert_eq!(error.closest_pattern, Some(MOVE_AZ_REGEX));
+    }
+
+    #[test]
+    fn test_into_atdome_cmd_move_az_lowercase_whitespace() {
+        let atdome_cmd_regex = ATDomeCmdRegex::new();
+
+        let atdome_cmd = atdome_cmd_regex.into_atdome_cmd("  123   mv ").unwrap();
+
+        assert!(matches!(atdome_cmd, ATDomeCmd::MoveAz(123.0)))
+    }
+
+    #[test]
+    fn test_into_atdome_cmd_lowercase() {
+        let atdome_cmd_regex = ATDomeCmdRegex::new();
+
+        let atdome_cmd = atdome_cmd_regex.into_atdome_cmd("sc").unwrap();
+
+        assert!(matches!(atdome_cmd, ATDomeCmd::CloseShutter))
+    }
+
+    #[test]
+    fn test_into_atdome_cmd_malformed() {
+        let atdome_cmd_regex = ATDomeCmdRegex::new();
+
+        for (text, closest_pattern) in [
+            ("SCX", Some(CLOSE_SHUTTER_REGEX)),
+            ("1.2.3 MV", Some(MOVE_AZ_REGEX)),
+            ("MV", Some(MOVE_AZ_REGEX)),
+            ("12 M V", Some(MOVE_AZ_REGEX)),
+            ("OPEN", Some(OPEN_SHUTTHER_MAIN_DOOR_REGEX)),
+            ("hello", None),
+            ("", None),
+        ] {
+            let error = atdome_cmd_regex.into_atdome_cmd(text).unwrap_err();
+
+            assert_eq!(error.text, text);
+            assert_eq!(error.closest_pattern, closest_pattern, "{text:?}");
+        }
+    }
+
+    #[test]
+    fn test_cmd_parse_error_message() {
+        let atdome_cmd_regex = ATDomeCmdRegex::new();
+
+        let error = atdome_cmd_regex.into_atdome_cmd("12.x MV").unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "Unrecognized command \"12.x MV\"; expected \"<az> MV\""
+        );
+    }
+
+    #[test]
+    fn test_into_atdome_cmd_set_tolerance() {
+        let atdome_cmd_regex = ATDomeCmdRegex::new();
+
+        let atdome_cmd = atdome_cmd_regex.into_atdome_cmd("1.50 TL").unwrap();
+
+        assert!(matches!(atdome_cmd, ATDomeCmd::SetTolerance(1.5)))
+    }
+
+    #[test]
+    fn test_into_atdome_cmd_set_rain_sensor_enabled() {
+        let atdome_cmd_regex = ATDomeCmdRegex::new();
+
+        let atdome_cmd = atdome_cmd_regex.into_atdome_cmd("0 RS").unwrap();
+
+        assert!(matches!(atdome_cmd, ATDomeCmd::SetRainSensorEnabled(false)))
+    }
+
     #[test]
     fn test_into_atdome_cmd_get_status() {
         let atdome_cmd_regex = ATDomeCmdRegex::new();
 
-        let atdome_cmd = atdome_cmd_regex.into_atdome_cmd("+");
+        let atdome_cmd = atdome_cmd_regex.into_atdome_cmd("+").unwrap();
 
         assert!(matches!(atdome_cmd, ATDomeCmd::GetStatus))
     }
@@ -99,7 +394,7 @@ mod test {
     fn test_into_atdome_cmd_close_shutter() {
         let atdome_cmd_regex = ATDomeCmdRegex::new();
 
-        let atdome_cmd = atdome_cmd_regex.into_atdome_cmd("SC");
+        let atdome_cmd = atdome_cmd_regex.into_atdome_cmd("SC").unwrap();
 
         assert!(matches!(atdome_cmd, ATDomeCmd::CloseShutter))
     }
@@ -108,7 +403,7 @@ mod test {
     fn test_into_atdome_cmd_open_shutter() {
         let atdome_cmd_regex = ATDomeCmdRegex::new();
 
-        let atdome_cmd = atdome_cmd_regex.into_atdome_cmd("SO");
+        let atdome_cmd = atdome_cmd_regex.into_atdome_cmd("SO").unwrap();
 
         assert!(matches!(atdome_cmd, ATDomeCmd::OpenShutter))
     }
@@ -117,7 +412,7 @@ mod test {
     fn test_into_atdome_cmd_stop_motion() {
         let atdome_cmd_regex = ATDomeCmdRegex::new();
 
-        let atdome_cmd = atdome_cmd_regex.into_atdome_cmd("ST");
+        let atdome_cmd = atdome_cmd_regex.into_atdome_cmd("ST").unwrap();
 
         assert!(matches!(atdome_cmd, ATDomeCmd::StopMotion))
     }
@@ -126,7 +421,7 @@ mod test {
     fn test_into_atdome_cmd_home_az() {
         let atdome_cmd_regex = ATDomeCmdRegex::new();
 
-        let atdome_cmd = atdome_cmd_regex.into_atdome_cmd("HM");
+        let atdome_cmd = atdome_cmd_regex.into_atdome_cmd("HM").unwrap();
 
         assert!(matches!(atdome_cmd, ATDomeCmd::HomeAzimuth))
     }
@@ -135,7 +430,7 @@ mod test {
     fn test_into_atdome_cmd_open_shutter_dropout() {
         let atdome_cmd_regex = ATDomeCmdRegex::new();
 
-        let atdome_cmd = atdome_cmd_regex.into_atdome_cmd("DN");
+        let atdome_cmd = atdome_cmd_regex.into_atdome_cmd("DN").unwrap();
 
         assert!(matches!(atdome_cmd, ATDomeCmd::OpenShutterDropoutDoor))
     }
@@ -144,7 +439,7 @@ mod test {
     fn test_into_atdome_cmd_close_shutter_dropout() {
         let atdome_cmd_regex = ATDomeCmdRegex::new();
 
-        let atdome_cmd = atdome_cmd_regex.into_atdome_cmd("UP");
+        let atdome_cmd = atdome_cmd_regex.into_atdome_cmd("UP").unwrap();
 
         assert!(matches!(atdome_cmd, ATDomeCmd::CloseShutterDropoutDoor))
     }
@@ -153,7 +448,7 @@ mod test {
     fn test_into_atdome_cmd_open_shutter_main() {
         let atdome_cmd_regex = ATDomeCmdRegex::new();
 
-        let atdome_cmd = atdome_cmd_regex.into_atdome_cmd("OP");
+        let atdome_cmd = atdome_cmd_regex.into_atdome_cmd("OP").unwrap();
 
         assert!(matches!(atdome_cmd, ATDomeCmd::OpenShutterMainDoor))
     }
@@ -162,7 +457,7 @@ mod test {
     fn test_into_atdome_cmd_close_shutter_main() {
         let atdome_cmd_regex = ATDomeCmdRegex::new();
 
-        let atdome_cmd = atdome_cmd_regex.into_atdome_cmd("CL");
+        let atdome_cmd = atdome_cmd_regex.into_atdome_cmd("CL").unwrap();
 
         assert!(matches!(atdome_cmd, ATDomeCmd::CloseShutterMainDoor))
     }
@@ -0,0 +1,305 @@
+//! Define the ATDomeConfig struct.
+//!
+//! This struct contains the configuration of the ATDome CSC and can be
+//! loaded from a yaml file.
+
+use std::{collections::BTreeMap, env, fs, path::Path};
+
+use serde::Deserialize;
+use tracing::level_filters::LevelFilter;
+
+use crate::{
+    error::{ATDomeError, ATDomeResult},
+    logging::LogLevels,
+};
+
+/// Environment variables salobj reads the Kafka connection from.
+pub const KAFKA_BROKER_ADDRESS_ENV: &str = "LSST_KAFKA_BROKER_ADDR";
+pub const SCHEMA_REGISTRY_URL_ENV: &str = "LSST_SCHEMA_REGISTRY_URL";
+pub const TOPIC_SUBNAME_ENV: &str = "LSST_TOPIC_SUBNAME";
+pub const KAFKA_CLIENT_ID_ENV: &str = "LSST_KAFKA_CLIENT_ID";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ATDomeConfig {
+    /// Address of the dome controller.
+    pub host: String,
+    /// Port of the dome controller.
+    pub port: usize,
+    /// Time limit for connecting to the controller (sec).
+    pub connection_timeout: f32,
+    /// Time limit for reading data from the controller (sec).
+    pub read_timeout: f32,
+    /// Interval between status requests to the controller (sec).
+    pub status_poll_interval: f32,
+    /// Time without a valid status from the controller, e.g. because it
+    /// stopped answering but kept the connection open, before the CSC goes
+    /// to Fault (sec).
+    pub stale_status_timeout: f32,
+    /// Home the azimuth when the CSC is enabled if the controller reports
+    /// it is not homed.
+    pub home_on_enable: bool,
+    /// Wait for homing to finish before executing motion commands instead
+    /// of rejecting them.
+    pub queue_motion_while_homing: bool,
+    /// Reject moveAzimuth while the controller reports the azimuth is not
+    /// homed, since its absolute position is wrong until it is.
+    pub require_homed_to_move: bool,
+    /// Nominal azimuth speed used to estimate slew durations (deg/s).
+    pub azimuth_speed: f32,
+    /// Margin beyond the controller tolerance the azimuth must move before
+    /// it is reported out of position again (deg).
+    pub azimuth_hysteresis: f32,
+    /// Time allowed beyond the controller move timeouts before a motion
+    /// command is failed by the watchdog (sec).
+    pub motion_timeout_margin: f32,
+    /// Enable or disable the auto-shutdown of the controller, which closes
+    /// the shutter on bad weather or loss of the watchdog, when the CSC is
+    /// enabled; the controller setting is left as is if not set. Disabling
+    /// it lets maintenance open the shutter during daytime work.
+    pub auto_shutdown_enabled: Option<bool>,
+    /// Close both doors when the status shows a weather sensor tripped, as
+    /// a backstop for the auto-shutdown of the controller. Only done while
+    /// the CSC is enabled.
+    pub close_on_weather_trip: bool,
+    /// Longest delay between attempts to reach the Kafka broker when the
+    /// CSC starts (sec); the CSC keeps retrying, with an exponential
+    /// backoff up to this delay, until the broker is reachable.
+    pub broker_retry_max_delay: f32,
+    /// Time to listen for the heartbeat of another ATDome CSC before
+    /// starting, to refuse to run two CSCs against the same controller
+    /// (sec); not checked if zero.
+    pub duplicate_check_time: f32,
+    /// Interval between heartbeat events (sec); the salobj default is
+    /// used if not set.
+    pub heartbeat_interval: Option<f32>,
+    /// Minimum interval between commands written to the controller (sec);
+    /// commands sent in a burst are queued.
+    pub min_command_interval: f32,
+    /// Address to serve the Prometheus metrics on, e.g. "0.0.0.0:9100";
+    /// metrics are not served if not set.
+    pub metrics_address: Option<String>,
+    /// Address to serve the engineering status on, e.g. "0.0.0.0:8080";
+    /// the endpoint is not served if not set.
+    pub engineering_address: Option<String>,
+    /// Log level of some modules, by tracing target, e.g.
+    /// "rubin_ts_atdome::atdome_model: debug"; the other modules log at
+    /// the level given on the command line.
+    pub log_levels: BTreeMap<String, String>,
+    /// Kafka broker address, e.g. "kafka:9092".
+    pub kafka_broker_address: Option<String>,
+    /// URL of the Avro schema registry.
+    pub schema_registry_url: Option<String>,
+    /// Suffix of the topic names, to isolate test stands and CI runs.
+    pub topic_subname: Option<String>,
+    /// Identity of the Kafka clients.
+    pub kafka_client_id: Option<String>,
+}
+
+impl Default for ATDomeConfig {
+    fn default() -> ATDomeConfig {
+        ATDomeConfig {
+            host: "127.0.0.1".to_owned(),
+            port: 8887,
+            connection_timeout: 10.0,
+            read_timeout: 10.0,
+            status_poll_interval: 1.0,
+            stale_status_timeout: 30.0,
+            home_on_enable: false,
+            queue_motion_while_homing: false,
+            require_homed_to_move: false,
+            azimuth_speed: 2.4,
+            azimuth_hysteresis: 0.5,
+            motion_timeout_margin: 10.0,
+            auto_shutdown_enabled: None,
+            close_on_weather_trip: false,
+            broker_retry_max_delay: 30.0,
+            duplicate_check_time: 3.0,
+            heartbeat_interval: None,
+            min_command_interval: 0.0,
+            metrics_address: None,
+            engineering_address: None,
+            log_levels: BTreeMap::new(),
+            kafka_broker_address: None,
+            schema_registry_url: None,
+            topic_subname: None,
+            kafka_client_id: None,
+        }
+    }
+}
+
+impl ATDomeConfig {
+    /// Parse the configuration from a yaml string and validate it.
+    pub fn from_yaml(yaml: &str) -> ATDomeResult<ATDomeConfig> {
+        let config: ATDomeConfig = serde_yaml::from_str(yaml)
+            .map_err(|error| ATDomeError::Internal(format!("Invalid configuration: {error}")))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Check that the durations and speeds can be used as such: finite,
+    /// and positive or non-negative depending on the setting.
+    pub fn validate(&self) -> ATDomeResult<()> {
+        let positive = [
+            ("connection_timeout", self.connection_timeout),
+            ("read_timeout", self.read_timeout),
+            ("status_poll_interval", self.status_poll_interval),
+            ("stale_status_timeout", self.stale_status_timeout),
+            ("azimuth_speed", self.azimuth_speed),
+        ]
+        .into_iter()
+        .chain(
+            self.heartbeat_interval
+                .map(|interval| ("heartbeat_interval", interval)),
+        );
+        for (name, value) in positive {
+            if !(value.is_finite() && value > 0.0) {
+                return Err(invalid_setting(name, value, "positive"));
+            }
+        }
+        for (name, value) in [
+            ("azimuth_hysteresis", self.azimuth_hysteresis),
+            ("motion_timeout_margin", self.motion_timeout_margin),
+            ("broker_retry_max_delay", self.broker_retry_max_delay),
+            ("duplicate_check_time", self.duplicate_check_time),
+            ("min_command_interval", self.min_command_interval),
+        ] {
+            if !(value.is_finite() && value >= 0.0) {
+                return Err(invalid_setting(name, value, "zero or positive"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Read the configuration from a yaml file.
+    pub fn from_file(path: &Path) -> ATDomeResult<ATDomeConfig> {
+        let yaml = fs::read_to_string(path)?;
+        ATDomeConfig::from_yaml(&yaml)
+    }
+
+    /// Log levels with `default` for the modules without a level in the
+    /// configuration.
+    pub fn log_levels(&self, default: LevelFilter) -> ATDomeResult<LogLevels> {
+        self.log_levels
+            .iter()
+            .try_fold(LogLevels::new(default), |levels, (target, level)| {
+                let level = level.parse::<LevelFilter>().map_err(|error| {
+                    ATDomeError::Internal(format!(
+                        "Invalid log level {level:?} for {target}: {error}"
+                    ))
+                })?;
+                levels.with_module(target, level)
+            })
+    }
+
+    /// Kafka connection settings to export, as (variable, value): the
+    /// configured settings whose variable `is_set` reports as unset.
+    pub fn kafka_environment(&self, is_set: impl Fn(&str) -> bool) -> Vec<(&'static str, String)> {
+        [
+            (KAFKA_BROKER_ADDRESS_ENV, &self.kafka_broker_address),
+            (SCHEMA_REGISTRY_URL_ENV, &self.schema_registry_url),
+            (TOPIC_SUBNAME_ENV, &self.topic_subname),
+            (KAFKA_CLIENT_ID_ENV, &self.kafka_client_id),
+        ]
+        .into_iter()
+        .filter(|(name, _)| !is_set(name))
+        .filter_map(|(name, value)| value.as_ref().map(|value| (name, value.to_owned())))
+        .collect()
+    }
+
+    /// Export the Kafka connection settings for salobj, which reads them
+    /// from the environment when the domain is created.
+    ///
+    /// Changing the environment is not thread safe, so this must run in
+    /// `main` before the tokio runtime, or any other thread, starts.
+    /// Variables already set in the environment take precedence, so a
+    /// deployment can override the configuration file.
+    pub fn apply_kafka_environment(&self) {
+        for (name, value) in self.kafka_environment(|name| env::var_os(name).is_some()) {
+            tracing::info!("Setting {name}={value} from the configuration.");
+            env::set_var(name, value);
+        }
+    }
+}
+
+/// Error for a setting outside of its allowed range.
+fn invalid_setting(name: &str, value: f32, expected: &str) -> ATDomeError {
+    ATDomeError::Internal(format!(
+        "Invalid configuration: {name} must be a finite {expected} number, got {value}."
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_yaml() {
+        let config = ATDomeConfig::from_yaml("host: 192.168.1.41\nport: 23\n").unwrap();
+
+        assert_eq!(config.host, "192.168.1.41");
+        assert_eq!(config.port, 23);
+        assert_eq!(config.read_timeout, 10.0);
+        assert!(!config.home_on_enable);
+        assert!(!config.close_on_weather_trip);
+        assert_eq!(config.auto_shutdown_enabled, None);
+        assert_eq!(config.heartbeat_interval, None);
+
+        let config = ATDomeConfig::from_yaml("auto_shutdown_enabled: false\n").unwrap();
+        assert_eq!(config.auto_shutdown_enabled, Some(false));
+    }
+
+    #[test]
+    fn test_kafka_environment() {
+        let config = ATDomeConfig::from_yaml(
+            "kafka_broker_address: kafka:9092\nschema_registry_url: http://registry:8081\n",
+        )
+        .unwrap();
+
+        let environment = config.kafka_environment(|name| name == SCHEMA_REGISTRY_URL_ENV);
+
+        assert_eq!(
+            environment,
+            vec![(KAFKA_BROKER_ADDRESS_ENV, "kafka:9092".to_owned())]
+        );
+    }
+
+    #[test]
+    fn test_log_levels() {
+        let config =
+            ATDomeConfig::from_yaml("log_levels:\n  rubin_ts_atdome::atdome_model: debug\n")
+                .unwrap();
+
+        let levels = config.log_levels(LevelFilter::INFO).unwrap();
+
+        assert_eq!(levels.default, LevelFilter::INFO);
+        assert_eq!(
+            levels.modules.get("rubin_ts_atdome::atdome_model"),
+            Some(&LevelFilter::DEBUG)
+        );
+
+        let config = ATDomeConfig::from_yaml("log_levels:\n  atdome_model: loud\n").unwrap();
+        assert!(config.log_levels(LevelFilter::INFO).is_err());
+    }
+
+    #[test]
+    fn test_from_yaml_invalid() {
+        let config = ATDomeConfig::from_yaml("port: not-a-number\n");
+
+        assert!(config.is_err());
+
+        for yaml in [
+            "read_timeout: -1.0\n",
+            "status_poll_interval: 0.0\n",
+            "connection_timeout: .nan\n",
+            "azimuth_speed: 0.0\n",
+            "heartbeat_interval: -2.0\n",
+            "min_command_interval: -0.1\n",
+            "motion_timeout_margin: .inf\n",
+        ] {
+            assert!(ATDomeConfig::from_yaml(yaml).is_err(), "{yaml}");
+        }
+        assert!(ATDomeConfig::from_yaml("min_command_interval: 0.0\n").is_ok());
+        assert!(ATDomeConfig::default().validate().is_ok());
+    }
+}
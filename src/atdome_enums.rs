@@ -0,0 +1,44 @@
+//! Enumerations used in the ATDome SAL interface.
+
+/// Commanded state of the azimuth axis.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AzimuthCommandedState {
+    Unknown = 1,
+    GoToPosition = 2,
+    Stop = 3,
+    Home = 4,
+}
+
+/// Motion state of the azimuth axis.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AzimuthState {
+    NotInMotion = 1,
+    MovingCW = 2,
+    MovingCCW = 3,
+}
+
+/// Homing state of the azimuth axis.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AzimuthHomingState {
+    NotHoming = 1,
+    Homing = 2,
+}
+
+/// State of the main and dropout shutter doors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShutterDoorState {
+    Closed = 1,
+    Opened = 2,
+    PartiallyOpened = 3,
+    Opening = 4,
+    Closing = 5,
+}
+
+/// Commanded state of the main and dropout shutter doors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShutterDoorCommandedState {
+    Unknown = 1,
+    Opened = 2,
+    Closed = 3,
+    Stop = 4,
+}
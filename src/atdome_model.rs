@@ -1,16 +1,33 @@
 //! Provide an interface to the ATDome Controller.
 
-use crate::{error::ATDomeResult, status::Status, status_parser::StatusParser};
-use std::str;
+use crate::{
+    atdome_cmd_regex::ATDomeCmdRegex,
+    azimuth::wrap_azimuth,
+    error::{ATDomeError, ATDomeResult, CommandPhase},
+    status::Status,
+    status_parser::StatusParser,
+};
+use bytes::{Buf, BytesMut};
+use serde::Serialize;
+use std::{
+    str,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+    time::{Duration, Instant},
+};
 
 use tokio::{
     io::{AsyncReadExt, AsyncWriteExt},
     net::TcpStream,
-    sync::{mpsc, oneshot},
+    sync::{mpsc, oneshot, watch},
     task,
+    time::{sleep_until, timeout_at},
 };
+use tracing::Instrument;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum ATDomeCmd {
     MoveAz(f32),
     CloseShutter,
@@ -22,13 +39,55 @@ pub enum ATDomeCmd {
     OpenShutterMainDoor,
     CloseShutterMainDoor,
     GetStatus,
+    /// Set the azimuth of the home switch (deg).
+    SetHomeAzimuth(f32),
+    /// Set the azimuth tolerance of a move (deg).
+    SetTolerance(f32),
+    /// Set the distance the dome coasts after the drive stops (deg).
+    SetCoast(f32),
+    /// Set the distance beyond which the dome moves at high speed (deg).
+    SetHighSpeed(f32),
+    /// Set the watchdog reset time (sec).
+    SetWatchdogTimer(f32),
+    /// Set the time limit for azimuth moves (sec).
+    SetAzimuthMoveTimeout(f32),
+    /// Set the time limit for door moves (sec).
+    SetDoorMoveTimeout(f32),
+    /// Enable or disable the rain-snow sensor interlock.
+    SetRainSensorEnabled(bool),
+    /// Enable or disable the cloud sensor interlock.
+    SetCloudSensorEnabled(bool),
+    /// Enable or disable closing the shutter automatically on bad weather
+    /// or loss of the watchdog.
+    SetAutoShutdownEnabled(bool),
+    /// Command not modeled by the other variants, sent as is.
+    Raw(String),
     Unknown,
 }
 
+/// Terminator the controller expects after every command.
+const COMMAND_TERMINATOR: &str = "\r\n";
+/// Character the controller sends when it is ready for a command.
+const PROMPT: u8 = b'>';
+/// Space reserved in the read buffer before each read (bytes).
+const READ_BUFFER_SIZE: usize = 1024;
+/// Number of decimals of the azimuth sent with the move command.
+const AZIMUTH_PRECISION: usize = 3;
+/// Number of decimals of the angles sent with the settings commands.
+const ANGLE_SETTING_PRECISION: usize = 2;
+
 impl ATDomeCmd {
+    /// Command string as written to the controller, including the
+    /// terminator; empty for `Unknown`.
     pub fn get_command(&self) -> String {
-        match &self {
-            ATDomeCmd::MoveAz(az) => format!("{az} MV\r\n"),
+        let command = match &self {
+            ATDomeCmd::MoveAz(az) => {
+                // Round before wrapping so e.g. 359.9999 is sent as 0.000
+                // rather than 360.000.
+                let scale = 10f32.powi(AZIMUTH_PRECISION as i32);
+                let az = wrap_azimuth((az * scale).round() / scale);
+                format!("{az:.AZIMUTH_PRECISION$} MV")
+            }
             ATDomeCmd::CloseShutter => "SC".to_string(),
             ATDomeCmd::OpenShutter => "SO".to_string(),
             ATDomeCmd::StopMotion => "ST".to_string(),
@@ -37,13 +96,41 @@ impl ATDomeCmd {
             ATDomeCmd::CloseShutterDropoutDoor => "UP".to_string(),
             ATDomeCmd::OpenShutterMainDoor => "OP".to_string(),
             ATDomeCmd::CloseShutterMainDoor => "CL".to_string(),
-            ATDomeCmd::GetStatus => "+\r\n".to_string(),
-            ATDomeCmd::Unknown => "".to_string(),
-        }
+            ATDomeCmd::GetStatus => "+".to_string(),
+            // Settings take the value before the command code, like the
+            // move command; angles are sent with fixed precision and
+            // times in whole seconds.
+            ATDomeCmd::SetHomeAzimuth(az) => {
+                format!("{:.ANGLE_SETTING_PRECISION$} HZ", wrap_azimuth(*az))
+            }
+            ATDomeCmd::SetTolerance(tolerance) => {
+                format!("{tolerance:.ANGLE_SETTING_PRECISION$} TL")
+            }
+            ATDomeCmd::SetCoast(coast) => format!("{coast:.ANGLE_SETTING_PRECISION$} CO"),
+            ATDomeCmd::SetHighSpeed(high_speed) => {
+                format!("{high_speed:.ANGLE_SETTING_PRECISION$} HS")
+            }
+            ATDomeCmd::SetWatchdogTimer(timer) => format!("{timer:.0} WD"),
+            ATDomeCmd::SetAzimuthMoveTimeout(timeout) => format!("{timeout:.0} AT"),
+            ATDomeCmd::SetDoorMoveTimeout(timeout) => format!("{timeout:.0} DT"),
+            ATDomeCmd::SetRainSensorEnabled(enabled) => format!("{} RS", *enabled as u8),
+            ATDomeCmd::SetCloudSensorEnabled(enabled) => format!("{} CS", *enabled as u8),
+            ATDomeCmd::SetAutoShutdownEnabled(enabled) => format!("{} AS", *enabled as u8),
+            ATDomeCmd::Raw(command) => command.to_string(),
+            ATDomeCmd::Unknown => return String::new(),
+        };
+        format!("{command}{COMMAND_TERMINATOR}")
     }
 
+    /// Parse a command string, as sent on the wire, into a command.
+    ///
+    /// Returns `Unknown` if the string is not a recognized command.
     pub fn from_str(atdome_cmd: &str) -> ATDomeCmd {
-        ATDomeCmd::Unknown
+        static ATDOME_CMD_REGEX: OnceLock<ATDomeCmdRegex> = OnceLock::new();
+        ATDOME_CMD_REGEX
+            .get_or_init(ATDomeCmdRegex::new)
+            .into_atdome_cmd(atdome_cmd.trim())
+            .unwrap_or(ATDomeCmd::Unknown)
     }
 }
 
@@ -51,132 +138,1172 @@ impl ATDomeCmd {
 pub enum ATDomeReply {
     None,
     Status(Status),
+    /// Text received before the prompt.
+    Raw(String),
+    /// Error message printed by the controller when it rejects a command.
+    Error(String),
 }
 
+/// Fragments (lowercase) of the controller replies that indicate the
+/// command was rejected, e.g. moving while the emergency stop is active.
+const ERROR_REPLY_PATTERNS: [&str; 6] = [
+    "error",
+    "emergency stop",
+    "not homed",
+    "invalid",
+    "rejected",
+    "ignored",
+];
+
 impl ATDomeReply {
+    /// Classify the text the controller sends in reply to a command that
+    /// is not expected to return data.
+    pub fn from_command_reply(reply: &str) -> ATDomeReply {
+        let message = reply.trim();
+        let lowercase_message = message.to_lowercase();
+        if ERROR_REPLY_PATTERNS
+            .iter()
+            .any(|pattern| lowercase_message.contains(pattern))
+        {
+            ATDomeReply::Error(message.to_owned())
+        } else {
+            ATDomeReply::None
+        }
+    }
+
     pub fn from_buffer(buffer: &[u8]) -> ATDomeReply {
         ATDomeReply::None
     }
 }
 
+/// Command currently being executed by the controller.
+#[derive(Debug, Clone)]
+pub struct ExecutingCommand {
+    /// Description of the command.
+    pub command: String,
+    /// When the command was sent to the controller.
+    pub started: Instant,
+}
+
+impl ExecutingCommand {
+    /// How long the command has been running.
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+}
+
+type CmdChannel = mpsc::Sender<(ATDomeCmd, oneshot::Sender<ATDomeResult<ATDomeReply>>)>;
+
+/// Statistics of the link with the controller since the model was
+/// created.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ConnectionStats {
+    /// Bytes written to the controller.
+    pub bytes_sent: u64,
+    /// Bytes read from the controller.
+    pub bytes_received: u64,
+    /// Commands written to the controller.
+    pub commands_sent: u64,
+    /// Status replies that could not be parsed.
+    pub parse_failures: u64,
+    /// Connections made after the first one.
+    pub reconnects: u64,
+}
+
+/// Counters behind `ConnectionStats`, shared with the command loop.
+#[derive(Debug, Default)]
+struct ConnectionCounters {
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+    commands_sent: AtomicU64,
+    parse_failures: AtomicU64,
+    reconnects: AtomicU64,
+}
+
+impl ConnectionCounters {
+    fn snapshot(&self) -> ConnectionStats {
+        ConnectionStats {
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+            commands_sent: self.commands_sent.load(Ordering::Relaxed),
+            parse_failures: self.parse_failures.load(Ordering::Relaxed),
+            reconnects: self.reconnects.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Callers waiting for the status request in flight; None when there is
+/// none.
+type StatusWaiters = Arc<Mutex<Option<Vec<oneshot::Sender<ATDomeResult<Status>>>>>>;
+
 #[derive(Debug)]
-struct ATDomeModel {
-    pub cmd_channel: mpsc::Sender<(ATDomeCmd, oneshot::Sender<ATDomeReply>)>,
-    cmd_task: Option<task::JoinHandle<ATDomeResult<()>>>,
+pub struct ATDomeModel {
+    /// Host and port of the controller.
+    address: Mutex<(String, usize)>,
+    cmd_channel_size: usize,
+    min_command_interval: Duration,
+    /// None while disconnected.
+    connection: Mutex<Option<Connection>>,
+    status_waiters: StatusWaiters,
+    stale_status_timeout: Option<Duration>,
+    /// False while the status is stale or the model is disconnected.
+    healthy: Arc<watch::Sender<bool>>,
+    counters: Arc<ConnectionCounters>,
 }
 
-impl ATDomeModel {
-    pub async fn create_and_start(
+/// Connection to the controller and the command loop serving it.
+#[derive(Debug)]
+struct Connection {
+    cmd_channel: CmdChannel,
+    cmd_task: task::JoinHandle<ATDomeResult<()>>,
+    executing_command: watch::Receiver<Option<ExecutingCommand>>,
+    raw_status: watch::Receiver<Option<String>>,
+    /// When the latest valid status was received; the time of the
+    /// connection until then.
+    last_status: watch::Receiver<Instant>,
+    watchdog_task: Option<task::JoinHandle<()>>,
+}
+
+impl Connection {
+    /// Connect to the controller on `host`:`port` and start the command
+    /// loop.
+    async fn open(
         host: &str,
         port: usize,
         cmd_channel_size: usize,
-    ) -> ATDomeResult<ATDomeModel> {
+        min_command_interval: Duration,
+        counters: Arc<ConnectionCounters>,
+    ) -> ATDomeResult<Connection> {
         let (cmd_channel, mut cmd_receiver): (
-            mpsc::Sender<(ATDomeCmd, oneshot::Sender<ATDomeReply>)>,
-            mpsc::Receiver<(ATDomeCmd, oneshot::Sender<ATDomeReply>)>,
+            mpsc::Sender<(ATDomeCmd, oneshot::Sender<ATDomeResult<ATDomeReply>>)>,
+            mpsc::Receiver<(ATDomeCmd, oneshot::Sender<ATDomeResult<ATDomeReply>>)>,
         ) = mpsc::channel(cmd_channel_size);
 
         let mut stream = TcpStream::connect(&format!("{host}:{port}")).await?;
+        let (executing_command_sender, executing_command) = watch::channel(None);
+        let (raw_status_sender, raw_status) = watch::channel::<Option<String>>(None);
+        let (last_status_sender, last_status) = watch::channel(Instant::now());
 
-        let cmd_task = Some(task::spawn(async move {
-            let mut buffer = [0; 1024];
+        let cmd_task = task::spawn(async move {
+            // Bytes received but not consumed yet; replies are split off it
+            // without copying and its allocation is reused between commands.
+            let mut buffer = BytesMut::with_capacity(READ_BUFFER_SIZE);
 
             // read welcome message and wait for the prompt character ">"
-            loop {
-                // read any message in the stream;
-                let n_bytes = stream.read(&mut buffer).await?;
-
-                if let Ok(reply) = str::from_utf8(&buffer[..n_bytes]) {
-                    println!("Got {n_bytes} bytes:\n{}", reply);
-                    if reply.contains(">") {
-                        break;
-                    }
-                } else {
-                    break;
-                }
-            }
+            let welcome = read_reply(&mut stream, &mut buffer, &counters.bytes_received).await?;
+            tracing::debug!(
+                "Got welcome message:\n{}",
+                String::from_utf8_lossy(&welcome)
+            );
 
+            let mut last_write: Option<tokio::time::Instant> = None;
             while let Some((atdome_cmd, atdome_reply_sender)) = cmd_receiver.recv().await {
-                let status_parser = StatusParser::new()?;
-                let command = atdome_cmd.get_command();
-                println!("{atdome_cmd:?}::{command}");
-                stream
-                    .write_all(&atdome_cmd.get_command().into_bytes())
-                    .await?;
-                match atdome_cmd {
-                    ATDomeCmd::GetStatus => {
-                        println!("Handling status command");
-                        let mut total_bytes = 0;
-                        let mut status_str = String::with_capacity(1024);
-                        loop {
-                            // read any message in the stream;
-                            let n_bytes = stream.read(&mut buffer).await?;
-                            total_bytes = total_bytes + n_bytes;
-                            println!("Got {n_bytes}: {buffer:?}");
-                            if let Ok(reply) = str::from_utf8(&buffer[..n_bytes]) {
-                                println!("Got {n_bytes} bytes:\n{}", reply);
-                                status_str.push_str(reply);
-                                if reply.contains(">") {
-                                    break;
-                                }
-                            } else {
-                                break;
-                            }
+                if let Some(last_write) = last_write {
+                    sleep_until(last_write + min_command_interval).await;
+                }
+                last_write = Some(tokio::time::Instant::now());
+                let span = tracing::debug_span!("controller_command", command = ?atdome_cmd);
+                let command_start = Instant::now();
+                executing_command_sender.send_replace(Some(ExecutingCommand {
+                    command: format!("{atdome_cmd:?}"),
+                    started: command_start,
+                }));
+                let result = async {
+                    let command = atdome_cmd.get_command();
+                    tracing::debug!("Sending {command:?}.");
+                    stream
+                        .write_all(command.as_bytes())
+                        .await
+                        .map_err(|error| {
+                            ATDomeError::from(error).with_command(&atdome_cmd, CommandPhase::Write)
+                        })?;
+                    counters
+                        .bytes_sent
+                        .fetch_add(command.len() as u64, Ordering::Relaxed);
+                    counters.commands_sent.fetch_add(1, Ordering::Relaxed);
+                    let atdome_reply = match atdome_cmd {
+                        ATDomeCmd::GetStatus => {
+                            let reply =
+                                read_reply(&mut stream, &mut buffer, &counters.bytes_received)
+                                    .await
+                                    .map_err(|error| {
+                                        error.with_command(&atdome_cmd, CommandPhase::AwaitPrompt)
+                                    })?;
+                            tracing::debug!(total_bytes = reply.len(), "Status received.");
+                            // Kept before parsing so a reply that fails to
+                            // parse can be inspected.
+                            raw_status_sender.send_modify(|raw_status| {
+                                let raw_status = raw_status.get_or_insert_with(String::new);
+                                raw_status.clear();
+                                raw_status.push_str(&String::from_utf8_lossy(&reply));
+                            });
+                            split_lines(&reply)
+                                .and_then(|status_lines| {
+                                    StatusParser::new()
+                                        .map_err(ATDomeError::from)?
+                                        .make_status(&status_lines)
+                                })
+                                .map(|status| {
+                                    last_status_sender.send_replace(Instant::now());
+                                    ATDomeReply::Status(status)
+                                })
+                                .map_err(|error| {
+                                    counters.parse_failures.fetch_add(1, Ordering::Relaxed);
+                                    error.with_command(&atdome_cmd, CommandPhase::Parse)
+                                })?
                         }
-                        println!("Total bytes read: {total_bytes}");
-                        let status_vec: Vec<&str> = status_str.split("\n").collect();
-                        match status_parser.make_status(&status_vec) {
-                            Ok(status) => {
-                                println!("Sending status: {status:?}");
-                                if let Err(error) =
-                                    atdome_reply_sender.send(ATDomeReply::Status(status))
-                                {
-                                    println!("Error sending reply: {error:?}");
-                                }
-                            }
-                            Err(error) => println!("Error parsing status: {error}"),
+                        ATDomeCmd::Raw(_) => {
+                            read_reply(&mut stream, &mut buffer, &counters.bytes_received)
+                                .await
+                                .map(|reply| {
+                                    ATDomeReply::Raw(String::from_utf8_lossy(&reply).into())
+                                })
+                                .map_err(|error| {
+                                    error.with_command(&atdome_cmd, CommandPhase::AwaitPrompt)
+                                })?
                         }
-                    }
-                    _ => {
-                        log::debug!("Waiting for prompt to return.");
-                        loop {
-                            let n_bytes = stream.read(&mut buffer).await?;
-                            log::debug!("{buffer:?}");
-                            if n_bytes == 0 {
-                                break;
+                        _ => {
+                            tracing::debug!("Waiting for prompt to return.");
+                            let reply =
+                                read_reply(&mut stream, &mut buffer, &counters.bytes_received)
+                                    .await
+                                    .map_err(|error| {
+                                        error.with_command(&atdome_cmd, CommandPhase::AwaitPrompt)
+                                    })?;
+                            let atdome_reply =
+                                ATDomeReply::from_command_reply(&String::from_utf8_lossy(&reply));
+                            if let ATDomeReply::Error(message) = &atdome_reply {
+                                tracing::warn!("Controller rejected the command: {message}");
                             }
+                            atdome_reply
                         }
-                        if let Err(error) = atdome_reply_sender.send(ATDomeReply::None) {
-                            log::error!("Error sending reply: {error:?}");
-                        }
-                    }
+                    };
+                    tracing::debug!(duration = ?command_start.elapsed(), "Command completed.");
+                    Ok::<ATDomeReply, ATDomeError>(atdome_reply)
+                }
+                .instrument(span)
+                .await;
+                executing_command_sender.send_replace(None);
+
+                // A broken connection leaves the controller in an unknown
+                // state, so stop the loop after reporting the error.
+                let lost_connection = matches!(&result, Err(error) if error.requires_reconnect());
+                if let Err(error) = &result {
+                    tracing::error!("{error}");
+                }
+                if atdome_reply_sender.send(result).is_err() {
+                    tracing::error!("Error sending reply: caller dropped the channel.");
+                }
+                if lost_connection {
+                    return Err(ATDomeError::Connection(
+                        "Lost connection to the controller.".to_owned(),
+                    ));
                 }
             }
             Ok(())
-        }));
+        });
 
-        Ok(ATDomeModel {
+        Ok(Connection {
             cmd_channel,
             cmd_task,
+            executing_command,
+            raw_status,
+            last_status,
+            watchdog_task: None,
         })
     }
 
+    /// Start flagging the connection unhealthy in `healthy` whenever no
+    /// valid status is received for `stale_status_timeout`.
+    fn start_watchdog(
+        &mut self,
+        stale_status_timeout: Duration,
+        healthy: Arc<watch::Sender<bool>>,
+    ) {
+        let mut last_status = self.last_status.clone();
+        let watchdog_task = task::spawn(async move {
+            loop {
+                let deadline = *last_status.borrow_and_update() + stale_status_timeout;
+                match timeout_at(deadline.into(), last_status.changed()).await {
+                    Ok(Ok(())) => continue,
+                    // The command loop stopped.
+                    Ok(Err(_)) => break,
+                    Err(_) => {
+                        tracing::warn!(
+                            "No valid status from the controller for {stale_status_timeout:?}."
+                        );
+                        healthy.send_replace(false);
+                        if last_status.changed().await.is_err() {
+                            break;
+                        }
+                        tracing::info!("Valid status received from the controller again.");
+                        healthy.send_replace(true);
+                    }
+                }
+            }
+            healthy.send_replace(false);
+        });
+        if let Some(previous) = self.watchdog_task.replace(watchdog_task) {
+            previous.abort();
+        }
+    }
+}
+
+impl Drop for Connection {
+    /// Close the connection with the controller.
+    fn drop(&mut self) {
+        self.cmd_task.abort();
+        if let Some(watchdog_task) = &self.watchdog_task {
+            watchdog_task.abort();
+        }
+    }
+}
+
+impl ATDomeModel {
+    /// Connect to the controller and start the command loop.
+    ///
+    /// Commands are written to the controller at least
+    /// `min_command_interval` apart; bursts wait in the command queue.
+    pub async fn create_and_start(
+        host: &str,
+        port: usize,
+        cmd_channel_size: usize,
+        min_command_interval: Duration,
+    ) -> ATDomeResult<ATDomeModel> {
+        let counters = Arc::new(ConnectionCounters::default());
+        let connection = Connection::open(
+            host,
+            port,
+            cmd_channel_size,
+            min_command_interval,
+            counters.clone(),
+        )
+        .await?;
+        Ok(ATDomeModel {
+            address: Mutex::new((host.to_owned(), port)),
+            cmd_channel_size,
+            min_command_interval,
+            connection: Mutex::new(Some(connection)),
+            status_waiters: StatusWaiters::default(),
+            stale_status_timeout: None,
+            healthy: Arc::new(watch::Sender::new(true)),
+            counters,
+        })
+    }
+
+    /// Flag the connection unhealthy when no valid status is received for
+    /// `stale_status_timeout`, e.g. when the controller stops answering
+    /// but keeps the connection open.
+    ///
+    /// The status is only requested by `get_status`, so callers are
+    /// expected to poll it more often than that.
+    pub fn with_stale_status_timeout(mut self, stale_status_timeout: Duration) -> ATDomeModel {
+        self.stale_status_timeout = Some(stale_status_timeout);
+        if let Some(connection) = self.connection.get_mut().unwrap() {
+            connection.start_watchdog(stale_status_timeout, self.healthy.clone());
+        }
+        self
+    }
+
+    /// Send a command to the controller and wait for the reply.
+    ///
+    /// Returns a `CommandRejected` error with the controller message if the
+    /// controller rejects the command. Failures executing the command carry
+    /// the command and the phase they occurred in.
+    pub async fn run_command(&self, atdome_cmd: ATDomeCmd) -> ATDomeResult<ATDomeReply> {
+        run_command(&self.cmd_channel()?, atdome_cmd).await
+    }
+
+    /// Close the connection and connect to the controller on `host`:`port`.
+    ///
+    /// Commands waiting on the old connection fail. If the new connection
+    /// cannot be made the model stays disconnected and can be reconnected
+    /// later.
+    pub async fn reconnect(&self, host: &str, port: usize) -> ATDomeResult<()> {
+        self.disconnect();
+        *self.address.lock().unwrap() = (host.to_owned(), port);
+        tracing::debug!("Reconnecting to the controller on {host}:{port}.");
+        let mut connection = Connection::open(
+            host,
+            port,
+            self.cmd_channel_size,
+            self.min_command_interval,
+            self.counters.clone(),
+        )
+        .await?;
+        self.counters.reconnects.fetch_add(1, Ordering::Relaxed);
+        if let Some(stale_status_timeout) = self.stale_status_timeout {
+            connection.start_watchdog(stale_status_timeout, self.healthy.clone());
+        }
+        *self.connection.lock().unwrap() = Some(connection);
+        self.healthy.send_replace(true);
+        Ok(())
+    }
+
+    /// Close the connection with the controller.
+    pub fn disconnect(&self) {
+        self.connection.lock().unwrap().take();
+        self.healthy.send_replace(false);
+    }
+
+    /// Host of the controller.
+    pub fn host(&self) -> String {
+        self.address.lock().unwrap().0.clone()
+    }
+
+    /// Port of the controller.
+    pub fn port(&self) -> usize {
+        self.address.lock().unwrap().1
+    }
+
+    /// Minimum time between commands written to the controller.
+    pub fn min_command_interval(&self) -> Duration {
+        self.min_command_interval
+    }
+
+    /// Time without a valid status before the connection is unhealthy.
+    pub fn stale_status_timeout(&self) -> Option<Duration> {
+        self.stale_status_timeout
+    }
+
+    /// Time since the latest valid status was received, or since the
+    /// connection was made if none was; None while disconnected.
+    pub fn status_age(&self) -> Option<Duration> {
+        self.connection
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|connection| connection.last_status.borrow().elapsed())
+    }
+
+    /// Whether the model is connected and, with a stale status timeout,
+    /// receiving valid status in time.
+    pub fn is_healthy(&self) -> bool {
+        *self.healthy.borrow() && !self.is_finished()
+    }
+
+    /// Receive the changes of the connection health.
+    ///
+    /// Only changes detected by the stale status watchdog and made by
+    /// `reconnect` and `disconnect` are sent.
+    pub fn subscribe_health(&self) -> watch::Receiver<bool> {
+        self.healthy.subscribe()
+    }
+
+    /// Sender to queue commands on the command loop of the connection.
+    pub fn cmd_channel(&self) -> ATDomeResult<CmdChannel> {
+        self.connection
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|connection| connection.cmd_channel.clone())
+            .ok_or_else(|| ATDomeError::Connection("Not connected to the controller.".to_owned()))
+    }
+
+    /// Get the current status of the controller.
+    ///
+    /// Callers that ask while a status request is in flight get its reply
+    /// instead of queueing another one. The request runs in its own task so
+    /// the other callers still get the reply if the first one gives up.
+    pub async fn get_status(&self) -> ATDomeResult<Status> {
+        let (status_sender, status_receiver) = oneshot::channel();
+        let in_flight = {
+            let mut status_waiters = self.status_waiters.lock().unwrap();
+            let in_flight = status_waiters.is_some();
+            status_waiters
+                .get_or_insert_with(Vec::new)
+                .push(status_sender);
+            in_flight
+        };
+        if !in_flight {
+            let cmd_channel = self.cmd_channel();
+            let status_waiters = self.status_waiters.clone();
+            task::spawn(async move {
+                let result = async {
+                    match run_command(&cmd_channel?, ATDomeCmd::GetStatus).await? {
+                        ATDomeReply::Status(status) => Ok(status),
+                        _ => Err(ATDomeError::Parse(
+                            "Controller did not reply with a status.".to_owned(),
+                        )),
+                    }
+                }
+                .await;
+                let waiters = status_waiters.lock().unwrap().take();
+                for waiter in waiters.into_iter().flatten() {
+                    if waiter
+                        .send(match &result {
+                            Ok(status) => Ok(*status),
+                            Err(error) => Err(error.duplicate()),
+                        })
+                        .is_err()
+                    {
+                        tracing::debug!("Status caller gave up before the reply.");
+                    }
+                }
+            });
+        }
+        status_receiver.await.map_err(|_| {
+            ATDomeError::Connection("Status request stopped before the reply.".to_owned())
+        })?
+    }
+
+    /// Send a command not modeled by `ATDomeCmd` and return the raw reply
+    /// received before the prompt.
+    ///
+    /// Intended for engineering use; the reply is not interpreted.
+    pub async fn send_raw(&self, command: &str) -> ATDomeResult<String> {
+        match self
+            .run_command(ATDomeCmd::Raw(command.trim_end().to_owned()))
+            .await?
+        {
+            ATDomeReply::Raw(reply) => Ok(reply),
+            _ => Err(ATDomeError::Internal(
+                "Controller command loop did not return the raw reply.".to_owned(),
+            )),
+        }
+    }
+
+    /// Number of commands waiting to be sent to the controller.
+    pub fn queued_commands(&self) -> usize {
+        self.connection
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map_or(0, |connection| {
+                connection.cmd_channel.max_capacity() - connection.cmd_channel.capacity()
+            })
+    }
+
+    /// Command currently being executed by the controller, if any.
+    pub fn executing_command(&self) -> Option<ExecutingCommand> {
+        self.connection
+            .lock()
+            .unwrap()
+            .as_ref()
+            .and_then(|connection| connection.executing_command.borrow().clone())
+    }
+
+    /// Text of the latest status reply received from the controller,
+    /// whether or not it could be parsed.
+    pub fn last_raw_status(&self) -> Option<String> {
+        self.connection
+            .lock()
+            .unwrap()
+            .as_ref()
+            .and_then(|connection| connection.raw_status.borrow().clone())
+    }
+
+    /// Statistics of the link with the controller, over all connections.
+    pub fn connection_stats(&self) -> ConnectionStats {
+        self.counters.snapshot()
+    }
+
+    /// Whether the model is disconnected or its command loop stopped.
     pub fn is_finished(&self) -> bool {
-        if let Some(cmd_task) = &self.cmd_task {
-            return cmd_task.is_finished();
-        } else {
-            return true;
+        self.connection
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_none_or(|connection| connection.cmd_task.is_finished())
+    }
+}
+
+/// Queue `atdome_cmd` on the command loop and wait for the reply.
+async fn run_command(cmd_channel: &CmdChannel, atdome_cmd: ATDomeCmd) -> ATDomeResult<ATDomeReply> {
+    let (reply_sender, reply_receiver) = oneshot::channel();
+    cmd_channel
+        .send((atdome_cmd, reply_sender))
+        .await
+        .map_err(|_| ATDomeError::Connection("Controller command loop stopped.".to_owned()))?;
+    match reply_receiver.await {
+        Ok(Ok(ATDomeReply::Error(message))) => Err(ATDomeError::CommandRejected(message)),
+        Ok(result) => result,
+        Err(_) => Err(ATDomeError::Connection(
+            "Controller command loop dropped the reply.".to_owned(),
+        )),
+    }
+}
+
+/// Read from the controller until `buffer` holds a prompt.
+///
+/// Returns the bytes received before the prompt, split off `buffer`
+/// without copying; the prompt is consumed and anything received after it
+/// stays in `buffer` for the next reply.
+async fn read_reply(
+    stream: &mut TcpStream,
+    buffer: &mut BytesMut,
+    received: &AtomicU64,
+) -> ATDomeResult<BytesMut> {
+    let mut searched = 0;
+    loop {
+        if let Some(prompt_index) = buffer[searched..].iter().position(|byte| *byte == PROMPT) {
+            let reply = buffer.split_to(searched + prompt_index);
+            buffer.advance(1);
+            return Ok(reply);
+        }
+        searched = buffer.len();
+
+        buffer.reserve(READ_BUFFER_SIZE);
+        let n_bytes = stream.read_buf(buffer).await?;
+        received.fetch_add(n_bytes as u64, Ordering::Relaxed);
+        if n_bytes == 0 {
+            return Err(ATDomeError::Connection(
+                "Connection closed by the controller.".to_owned(),
+            ));
         }
+        tracing::trace!("{:?}", &buffer[searched..]);
     }
 }
 
+/// Split a reply into lines borrowed from it.
+fn split_lines(reply: &[u8]) -> ATDomeResult<Vec<&str>> {
+    reply
+        .split(|byte| *byte == b'\n')
+        .map(|line| {
+            str::from_utf8(line).map_err(|error| {
+                ATDomeError::Parse(format!("Invalid UTF-8 in line {line:?}: {error}"))
+            })
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::mock_controller::mock_controller::{
+        run_mock_controller_with_shutdown, MockControllerConfig,
+    };
+    use crate::status::StatusFormat;
+    use tokio::time::{sleep, timeout};
+
+    /// Start a mock controller on `port` and connect a model to it.
+    async fn start_mock_and_model(port: usize) -> (ATDomeModel, oneshot::Sender<()>) {
+        start_mock_with_config_and_model(
+            MockControllerConfig {
+                port,
+                ..Default::default()
+            },
+            Duration::ZERO,
+        )
+        .await
+    }
+
+    /// Start a mock controller with `config` and connect a model to it.
+    async fn start_mock_with_config_and_model(
+        config: MockControllerConfig,
+        min_command_interval: Duration,
+    ) -> (ATDomeModel, oneshot::Sender<()>) {
+        let port = config.port;
+        let (shutdown_sender, shutdown_receiver) = oneshot::channel::<()>();
+        tokio::spawn(run_mock_controller_with_shutdown(config, async {
+            let _ = shutdown_receiver.await;
+        }));
+        for _ in 0..50 {
+            if let Ok(atdome_model) =
+                ATDomeModel::create_and_start("127.0.0.1", port, 10, min_command_interval).await
+            {
+                return (atdome_model, shutdown_sender);
+            }
+            sleep(Duration::from_millis(20)).await;
+        }
+        panic!("Failed to connect to the mock controller.");
+    }
+
+    #[tokio::test]
+    async fn test_queue_introspection() {
+        let (atdome_model, _shutdown_sender) = start_mock_and_model(5102).await;
+
+        assert_eq!(atdome_model.queued_commands(), 0);
+        atdome_model.get_status().await.unwrap();
+        assert_eq!(atdome_model.queued_commands(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_min_command_interval() {
+        let (atdome_model, _shutdown_sender) = start_mock_with_config_and_model(
+            MockControllerConfig {
+                port: 5105,
+                ..Default::default()
+            },
+            Duration::from_millis(200),
+        )
+        .await;
+
+        let start = Instant::now();
+        for _ in 0..3 {
+            atdome_model
+                .run_command(ATDomeCmd::StopMotion)
+                .await
+                .unwrap();
+        }
+        assert!(start.elapsed() >= Duration::from_millis(400));
+    }
+
+    #[test]
+    fn test_get_command() {
+        for (atdome_cmd, command) in [
+            (ATDomeCmd::MoveAz(101.0), "101.000 MV\r\n"),
+            (ATDomeCmd::CloseShutter, "SC\r\n"),
+            (ATDomeCmd::OpenShutter, "SO\r\n"),
+            (ATDomeCmd::StopMotion, "ST\r\n"),
+            (ATDomeCmd::HomeAzimuth, "HM\r\n"),
+            (ATDomeCmd::OpenShutterDropoutDoor, "DN\r\n"),
+            (ATDomeCmd::CloseShutterDropoutDoor, "UP\r\n"),
+            (ATDomeCmd::OpenShutterMainDoor, "OP\r\n"),
+            (ATDomeCmd::CloseShutterMainDoor, "CL\r\n"),
+            (ATDomeCmd::GetStatus, "+\r\n"),
+            (ATDomeCmd::SetHomeAzimuth(10.0), "10.00 HZ\r\n"),
+            (ATDomeCmd::SetTolerance(1.0), "1.00 TL\r\n"),
+            (ATDomeCmd::SetCoast(0.5), "0.50 CO\r\n"),
+            (ATDomeCmd::SetHighSpeed(5.0), "5.00 HS\r\n"),
+            (ATDomeCmd::SetWatchdogTimer(600.0), "600 WD\r\n"),
+            (ATDomeCmd::SetAzimuthMoveTimeout(120.0), "120 AT\r\n"),
+            (ATDomeCmd::SetDoorMoveTimeout(360.0), "360 DT\r\n"),
+            (ATDomeCmd::SetRainSensorEnabled(true), "1 RS\r\n"),
+            (ATDomeCmd::SetCloudSensorEnabled(false), "0 CS\r\n"),
+            (ATDomeCmd::SetAutoShutdownEnabled(true), "1 AS\r\n"),
+            (ATDomeCmd::Raw("?".to_owned()), "?\r\n"),
+            (ATDomeCmd::Unknown, ""),
+        ] {
+            assert_eq!(atdome_cmd.get_command(), command);
+        }
+    }
+
+    #[test]
+    fn test_get_command_move_az_bounded() {
+        assert_eq!(ATDomeCmd::MoveAz(12.3456).get_command(), "12.346 MV\r\n");
+        assert_eq!(ATDomeCmd::MoveAz(-10.0).get_command(), "350.000 MV\r\n");
+        assert_eq!(ATDomeCmd::MoveAz(370.0).get_command(), "10.000 MV\r\n");
+        assert_eq!(ATDomeCmd::MoveAz(359.99999).get_command(), "0.000 MV\r\n");
+    }
+
+    /// Position of the variant of `atdome_cmd` in the enum.
+    ///
+    /// The match is exhaustive, so adding a variant fails to compile until
+    /// it is added here and to the round trip test.
+    fn variant_index(atdome_cmd: &ATDomeCmd) -> usize {
+        match atdome_cmd {
+            ATDomeCmd::MoveAz(_) => 0,
+            ATDomeCmd::CloseShutter => 1,
+            ATDomeCmd::OpenShutter => 2,
+            ATDomeCmd::StopMotion => 3,
+            ATDomeCmd::HomeAzimuth => 4,
+            ATDomeCmd::OpenShutterDropoutDoor => 5,
+            ATDomeCmd::CloseShutterDropoutDoor => 6,
+            ATDomeCmd::OpenShutterMainDoor => 7,
+            ATDomeCmd::CloseShutterMainDoor => 8,
+            ATDomeCmd::GetStatus => 9,
+            ATDomeCmd::SetHomeAzimuth(_) => 10,
+            ATDomeCmd::SetTolerance(_) => 11,
+            ATDomeCmd::SetCoast(_) => 12,
+            ATDomeCmd::SetHighSpeed(_) => 13,
+            ATDomeCmd::SetWatchdogTimer(_) => 14,
+            ATDomeCmd::SetAzimuthMoveTimeout(_) => 15,
+            ATDomeCmd::SetDoorMoveTimeout(_) => 16,
+            ATDomeCmd::SetRainSensorEnabled(_) => 17,
+            ATDomeCmd::SetCloudSensorEnabled(_) => 18,
+            ATDomeCmd::SetAutoShutdownEnabled(_) => 19,
+            // Not modeled commands; they cannot round trip.
+            ATDomeCmd::Raw(_) => 20,
+            ATDomeCmd::Unknown => 21,
+        }
+    }
+
+    #[test]
+    fn test_cmd_from_str_round_trip() {
+        let atdome_cmds = [
+            ATDomeCmd::MoveAz(101.0),
+            ATDomeCmd::MoveAz(12.5),
+            ATDomeCmd::CloseShutter,
+            ATDomeCmd::OpenShutter,
+            ATDomeCmd::StopMotion,
+            ATDomeCmd::HomeAzimuth,
+            ATDomeCmd::OpenShutterDropoutDoor,
+            ATDomeCmd::CloseShutterDropoutDoor,
+            ATDomeCmd::OpenShutterMainDoor,
+            ATDomeCmd::CloseShutterMainDoor,
+            ATDomeCmd::GetStatus,
+            ATDomeCmd::SetHomeAzimuth(10.5),
+            ATDomeCmd::SetTolerance(1.0),
+            ATDomeCmd::SetCoast(0.5),
+            ATDomeCmd::SetHighSpeed(5.0),
+            ATDomeCmd::SetWatchdogTimer(600.0),
+            ATDomeCmd::SetAzimuthMoveTimeout(120.0),
+            ATDomeCmd::SetDoorMoveTimeout(360.0),
+            ATDomeCmd::SetRainSensorEnabled(false),
+            ATDomeCmd::SetCloudSensorEnabled(true),
+            ATDomeCmd::SetAutoShutdownEnabled(false),
+        ];
+        for atdome_cmd in &atdome_cmds {
+            assert_eq!(&ATDomeCmd::from_str(&atdome_cmd.get_command()), atdome_cmd);
+        }
+
+        let mut covered: Vec<usize> = atdome_cmds.iter().map(variant_index).collect();
+        covered.dedup();
+        let raw_index = variant_index(&ATDomeCmd::Raw(String::new()));
+        assert_eq!(covered, (0..raw_index).collect::<Vec<_>>());
+
+        assert_eq!(ATDomeCmd::from_str("XYZ"), ATDomeCmd::Unknown);
+    }
+
+    #[test]
+    fn test_reply_from_command_reply() {
+        assert!(matches!(
+            ATDomeReply::from_command_reply("\r\n"),
+            ATDomeReply::None
+        ));
+        match ATDomeReply::from_command_reply("Emergency Stop Active, command ignored\r\n") {
+            ATDomeReply::Error(message) => {
+                assert_eq!(message, "Emergency Stop Active, command ignored")
+            }
+            reply => panic!("Expected an error reply, got {reply:?}."),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_command_rejected_while_estop_active() {
+        let (atdome_model, _shutdown_sender) = start_mock_with_config_and_model(
+            MockControllerConfig {
+                port: 5104,
+                control_address: Some("127.0.0.1:5204".to_owned()),
+                ..Default::default()
+            },
+            Duration::ZERO,
+        )
+        .await;
+
+        atdome_model
+            .run_command(ATDomeCmd::MoveAz(10.0))
+            .await
+            .unwrap();
+        let mut control = TcpStream::connect("127.0.0.1:5204").await.unwrap();
+        control.write_all(b"estop on\n").await.unwrap();
+        let mut control_reply = [0; 16];
+        let n_bytes = control.read(&mut control_reply).await.unwrap();
+        assert_eq!(&control_reply[..n_bytes], b"ok\n");
+
+        let error = atdome_model
+            .run_command(ATDomeCmd::MoveAz(20.0))
+            .await
+            .unwrap_err();
+        assert!(matches!(error, ATDomeError::CommandRejected(_)));
+    }
+
+    #[tokio::test]
+    async fn test_set_parameters() {
+        let (atdome_model, _shutdown_sender) = start_mock_and_model(5107).await;
+
+        let status = atdome_model.get_status().await.unwrap();
+        assert_eq!(status.tolerance, 1.0);
+        assert_eq!(status.azimuth_move_timeout, 120.0);
+
+        for atdome_cmd in [
+            ATDomeCmd::SetHomeAzimuth(12.5),
+            ATDomeCmd::SetTolerance(2.0),
+            ATDomeCmd::SetCoast(0.75),
+            ATDomeCmd::SetHighSpeed(4.0),
+            ATDomeCmd::SetWatchdogTimer(300.0),
+            ATDomeCmd::SetAzimuthMoveTimeout(90.0),
+            ATDomeCmd::SetDoorMoveTimeout(240.0),
+        ] {
+            atdome_model.run_command(atdome_cmd).await.unwrap();
+        }
+
+        let status = atdome_model.get_status().await.unwrap();
+        assert_eq!(status.home_azimuth, 12.5);
+        assert_eq!(status.tolerance, 2.0);
+        assert_eq!(status.coast, 0.75);
+        assert_eq!(status.high_speed, 4.0);
+        assert_eq!(status.watchdog_timer, 300.0);
+        assert_eq!(status.azimuth_move_timeout, 90.0);
+        assert_eq!(status.door_move_timeout, 240.0);
+    }
+
+    #[tokio::test]
+    async fn test_set_sensors_enabled() {
+        let (atdome_model, _shutdown_sender) = start_mock_and_model(5106).await;
+
+        let status = atdome_model.get_status().await.unwrap();
+        assert!(status.rain_sensor_enabled);
+        assert!(!status.auto_shutdown_enabled);
+
+        for atdome_cmd in [
+            ATDomeCmd::SetRainSensorEnabled(false),
+            ATDomeCmd::SetCloudSensorEnabled(false),
+            ATDomeCmd::SetAutoShutdownEnabled(true),
+        ] {
+            atdome_model.run_command(atdome_cmd).await.unwrap();
+        }
+
+        let status = atdome_model.get_status().await.unwrap();
+        assert!(!status.rain_sensor_enabled);
+        assert!(!status.cloud_sensor_enabled);
+        assert!(status.auto_shutdown_enabled);
+    }
+
+    #[tokio::test]
+    async fn test_read_reply_keeps_bytes_after_prompt() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let controller = task::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            stream.write_all(b"banner\r\n>first>sec").await.unwrap();
+            sleep(Duration::from_millis(50)).await;
+            stream.write_all(b"ond\n>").await.unwrap();
+        });
+
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        let mut buffer = BytesMut::new();
+        let received = AtomicU64::new(0);
+
+        assert_eq!(
+            &read_reply(&mut stream, &mut buffer, &received)
+                .await
+                .unwrap()[..],
+            b"banner\r\n"
+        );
+        assert_eq!(
+            &read_reply(&mut stream, &mut buffer, &received)
+                .await
+                .unwrap()[..],
+            b"first"
+        );
+        let reply = read_reply(&mut stream, &mut buffer, &received)
+            .await
+            .unwrap();
+        assert_eq!(split_lines(&reply).unwrap(), vec!["second", ""]);
+        controller.await.unwrap();
+
+        let error = read_reply(&mut stream, &mut buffer, &received)
+            .await
+            .unwrap_err();
+        assert!(error.requires_reconnect());
+        assert_eq!(received.load(Ordering::Relaxed), 23);
+    }
+
+    #[tokio::test]
+    async fn test_read_reply_ends_at_prompt_on_open_connection() {
+        // Like the Python mock, the controller keeps the connection open
+        // after the prompt, so the reply must not wait for it to close.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (hang_up, hung_up) = oneshot::channel::<()>();
+        let controller = task::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            stream.write_all(b"0.0\n>").await.unwrap();
+            hung_up.await.ok();
+        });
+
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        let mut buffer = BytesMut::new();
+        let received = AtomicU64::new(0);
+
+        let reply = timeout(
+            Duration::from_secs(1),
+            read_reply(&mut stream, &mut buffer, &received),
+        )
+        .await
+        .expect("The reply did not end at the prompt.")
+        .unwrap();
+        assert_eq!(&reply[..], b"0.0\n");
+        assert!(buffer.is_empty());
+
+        hang_up.send(()).unwrap();
+        controller.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_stale_status_flags_connection_unhealthy() {
+        // Controller that sends the prompt and then never answers.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port() as usize;
+        let controller = task::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            stream.write_all(b">").await.unwrap();
+            let mut buffer = [0; 64];
+            while stream
+                .read(&mut buffer)
+                .await
+                .is_ok_and(|n_bytes| n_bytes > 0)
+            {}
+        });
+
+        let atdome_model = ATDomeModel::create_and_start("127.0.0.1", port, 10, Duration::ZERO)
+            .await
+            .unwrap()
+            .with_stale_status_timeout(Duration::from_millis(100));
+        let mut healthy = atdome_model.subscribe_health();
+        assert!(atdome_model.is_healthy());
+
+        let status_request = tokio::spawn(async move {
+            let _ = atdome_model.get_status().await;
+        });
+        tokio::time::timeout(Duration::from_secs(5), healthy.wait_for(|healthy| !healthy))
+            .await
+            .unwrap()
+            .unwrap();
+
+        status_request.abort();
+        controller.abort();
+    }
+
+    #[tokio::test]
+    async fn test_error_carries_command_context() {
+        // Controller that sends the prompt, reads a command and hangs up.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port() as usize;
+        let controller = task::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            stream.write_all(b">").await.unwrap();
+            let mut buffer = [0; 4];
+            stream.read_exact(&mut buffer).await.unwrap();
+            assert_eq!(&buffer, b"ST\r\n");
+        });
+
+        let atdome_model = ATDomeModel::create_and_start("127.0.0.1", port, 10, Duration::ZERO)
+            .await
+            .unwrap();
+        let error = atdome_model
+            .run_command(ATDomeCmd::StopMotion)
+            .await
+            .unwrap_err();
+        controller.await.unwrap();
+
+        assert_eq!(
+            error.command_context(),
+            Some((&ATDomeCmd::StopMotion, CommandPhase::AwaitPrompt))
+        );
+        assert!(error.requires_reconnect());
+        assert!(error
+            .get_error_message()
+            .starts_with("StopMotion failed while waiting for the prompt"));
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_get_status_share_reply() {
+        // Controller that answers every status request after a delay and
+        // returns how many it received.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port() as usize;
+        let controller = task::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            stream.write_all(b">").await.unwrap();
+            let reply = Status::default().as_string_with_format(StatusFormat::default()) + ">";
+            let mut n_requests = 0;
+            let mut buffer = [0; 16];
+            while stream.read(&mut buffer).await.unwrap() > 0 {
+                n_requests += 1;
+                sleep(Duration::from_millis(50)).await;
+                stream.write_all(reply.as_bytes()).await.unwrap();
+            }
+            n_requests
+        });
+
+        let atdome_model = ATDomeModel::create_and_start("127.0.0.1", port, 10, Duration::ZERO)
+            .await
+            .unwrap();
+        let (first, second, third) = tokio::join!(
+            atdome_model.get_status(),
+            atdome_model.get_status(),
+            atdome_model.get_status()
+        );
+        assert_eq!(first.unwrap().az_pos, second.unwrap().az_pos);
+        assert!(third.is_ok());
+
+        // A request after the reply is a new exchange.
+        atdome_model.get_status().await.unwrap();
+        drop(atdome_model);
+
+        assert_eq!(controller.await.unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_reconnect() {
+        let (atdome_model, _shutdown_sender) = start_mock_and_model(5108).await;
+        let (other_shutdown_sender, other_shutdown_receiver) = oneshot::channel::<()>();
+        tokio::spawn(run_mock_controller_with_shutdown(
+            MockControllerConfig {
+                port: 5109,
+                ..Default::default()
+            },
+            async {
+                let _ = other_shutdown_receiver.await;
+            },
+        ));
+        atdome_model.get_status().await.unwrap();
+
+        atdome_model.disconnect();
+        assert!(atdome_model.is_finished());
+        let error = atdome_model.get_status().await.unwrap_err();
+        assert_eq!(error.kind(), "Connection");
+
+        for _ in 0..50 {
+            if atdome_model.reconnect("127.0.0.1", 5109).await.is_ok() {
+                break;
+            }
+            sleep(Duration::from_millis(20)).await;
+        }
+        assert!(!atdome_model.is_finished());
+        assert_eq!(atdome_model.port(), 5109);
+        atdome_model.get_status().await.unwrap();
+        let stats = atdome_model.connection_stats();
+        assert_eq!(stats.reconnects, 1);
+        assert_eq!(stats.commands_sent, 2);
+        assert_eq!(stats.parse_failures, 0);
+        assert!(stats.bytes_sent > 0 && stats.bytes_received > stats.bytes_sent);
+
+        assert!(atdome_model.reconnect("127.0.0.1", 5110).await.is_err());
+        assert!(atdome_model.is_finished());
+        assert_eq!(atdome_model.port(), 5110);
+        let _ = other_shutdown_sender.send(());
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_after_idle_timeout() {
+        let (atdome_model, _shutdown_sender) = start_mock_with_config_and_model(
+            MockControllerConfig {
+                port: 5111,
+                idle_timeout: Some(Duration::from_millis(200)),
+                ..Default::default()
+            },
+            Duration::ZERO,
+        )
+        .await;
+        atdome_model.get_status().await.unwrap();
+
+        sleep(Duration::from_millis(500)).await;
+        let error = atdome_model.get_status().await.unwrap_err();
+        assert_eq!(error.kind(), "Connection");
+
+        atdome_model.reconnect("127.0.0.1", 5111).await.unwrap();
+        atdome_model.get_status().await.unwrap();
+        assert_eq!(atdome_model.connection_stats().reconnects, 1);
+    }
+
+    #[tokio::test]
+    async fn test_send_raw() {
+        let (atdome_model, _shutdown_sender) = start_mock_and_model(5103).await;
+
+        assert_eq!(atdome_model.last_raw_status(), None);
+        atdome_model.get_status().await.unwrap();
+        assert!(atdome_model
+            .last_raw_status()
+            .is_some_and(|raw_status| raw_status.starts_with("MAIN CLOSED 000")));
+
+        let reply = atdome_model.send_raw("+").await.unwrap();
+        assert!(reply.contains("POSN 0"));
+        assert!(!reply.contains('>'));
+
+        let reply = atdome_model.send_raw("").await.unwrap();
+        assert!(reply.is_empty());
+
+        let reply = atdome_model.send_raw("SCX").await.unwrap();
+        assert!(reply.contains("Unrecognized command \"SCX\"; expected \"SC\""));
+    }
 
     #[tokio::test]
     async fn test_atdome_model_get_status() {
-        let atdome_model = ATDomeModel::create_and_start("127.0.0.1", 5001, 10)
+        let atdome_model = ATDomeModel::create_and_start("127.0.0.1", 5001, 10, Duration::ZERO)
             .await
             .unwrap();
 
@@ -184,9 +1311,14 @@ mod tests {
 
         let get_status = (ATDomeCmd::GetStatus, rx);
 
-        atdome_model.cmd_channel.send(get_status).await.unwrap();
+        atdome_model
+            .cmd_channel()
+            .unwrap()
+            .send(get_status)
+            .await
+            .unwrap();
 
-        if let ATDomeReply::Status(status) = tx.await.unwrap() {
+        if let ATDomeReply::Status(status) = tx.await.unwrap().unwrap() {
             assert_eq!(status.az_pos, 285.0);
             assert_eq!(status.auto_shutdown_enabled, false);
             assert_eq!(status.az_home_switch, false);
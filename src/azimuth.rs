@@ -0,0 +1,90 @@
+//! Wrap-aware azimuth arithmetic.
+//!
+//! The controller moves the dome along the shortest path to the commanded
+//! azimuth, so the CSC uses the same rule to report the commanded target
+//! and to estimate how long a slew will take.
+
+/// Wrap an azimuth to the range [0, 360) deg.
+pub fn wrap_azimuth(azimuth: f32) -> f32 {
+    let wrapped = azimuth.rem_euclid(360.0);
+    // rem_euclid can round up to 360 for tiny negative values.
+    if wrapped >= 360.0 {
+        0.0
+    } else {
+        wrapped
+    }
+}
+
+/// Signed distance from `current` to `target` along the shortest path,
+/// in the range (-180, 180] deg; positive values are clockwise.
+pub fn azimuth_distance(current: f32, target: f32) -> f32 {
+    let delta = wrap_azimuth(target - current);
+    if delta > 180.0 {
+        delta - 360.0
+    } else {
+        delta
+    }
+}
+
+/// Path the dome takes to reach a commanded azimuth.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AzimuthPath {
+    /// Target azimuth, wrapped to [0, 360) deg.
+    pub target: f32,
+    /// Signed distance to travel (deg); positive values are clockwise.
+    pub distance: f32,
+}
+
+impl AzimuthPath {
+    /// Compute the shortest path from `current` to `target`.
+    pub fn new(current: f32, target: f32) -> AzimuthPath {
+        AzimuthPath {
+            target: wrap_azimuth(target),
+            distance: azimuth_distance(current, target),
+        }
+    }
+
+    /// Estimated time to complete the move at `speed` (deg/s); None if the
+    /// speed is not a positive number.
+    pub fn estimated_duration(&self, speed: f32) -> Option<f32> {
+        (speed.is_finite() && speed > 0.0).then(|| self.distance.abs() / speed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wrap_azimuth() {
+        assert_eq!(wrap_azimuth(0.0), 0.0);
+        assert_eq!(wrap_azimuth(360.0), 0.0);
+        assert_eq!(wrap_azimuth(370.0), 10.0);
+        assert_eq!(wrap_azimuth(-10.0), 350.0);
+        assert_eq!(wrap_azimuth(-1e-9), 0.0);
+    }
+
+    #[test]
+    fn test_azimuth_path_crosses_zero() {
+        let path = AzimuthPath::new(350.0, 10.0);
+
+        assert_eq!(path.target, 10.0);
+        assert_eq!(path.distance, 20.0);
+
+        let path = AzimuthPath::new(10.0, 350.0);
+
+        assert_eq!(path.target, 350.0);
+        assert_eq!(path.distance, -20.0);
+    }
+
+    #[test]
+    fn test_azimuth_path_estimated_duration() {
+        let path = AzimuthPath::new(90.0, 270.0);
+
+        assert_eq!(path.distance, 180.0);
+        assert_eq!(path.estimated_duration(2.0), Some(90.0));
+        assert_eq!(path.estimated_duration(0.0), None);
+        assert_eq!(path.estimated_duration(-2.0), None);
+        assert_eq!(path.estimated_duration(f32::NAN), None);
+    }
+}
@@ -0,0 +1,210 @@
+//! Record and replay the traffic with the ATDome controller.
+//!
+//! In record mode the proxy sits between clients (e.g. the CSC) and the
+//! controller, forwarding all traffic and recording the session of each
+//! client to its own transcript with timestamps. In
+//! replay mode the commands of a recorded session are sent again to a
+//! controller with the original timing.
+
+use clap::{Parser, Subcommand};
+use rubin_ts_atdome::{
+    error::ATDomeResult,
+    transcript::{read_transcript, Direction, TranscriptWriter},
+    version::LONG_VERSION,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+        TcpListener, TcpStream,
+    },
+    sync::mpsc,
+    time::{sleep_until, timeout, Duration, Instant},
+};
+
+use std::{error::Error, fs, net::SocketAddr, path::PathBuf};
+
+/// Record and replay the traffic with the ATDome controller.
+#[derive(Parser, Debug)]
+#[command(about, version = LONG_VERSION)]
+struct Args {
+    #[command(subcommand)]
+    mode: Mode,
+}
+
+#[derive(Subcommand, Debug)]
+enum Mode {
+    /// Forward traffic between clients and the controller, recording it.
+    Record {
+        /// Address to listen for clients on.
+        #[arg(long, default_value = "127.0.0.1:8886")]
+        listen: String,
+        /// Address of the controller.
+        #[arg(long)]
+        controller: String,
+        /// Directory to write the transcripts to, one per client
+        /// connection.
+        #[arg(long)]
+        output: PathBuf,
+    },
+    /// Send the commands of a recorded session to a controller.
+    Replay {
+        /// Address of the controller.
+        #[arg(long)]
+        controller: String,
+        /// Transcript to replay.
+        #[arg(long)]
+        input: PathBuf,
+        /// Time to keep printing replies after the last command (sec).
+        #[arg(long, default_value_t = 2.0)]
+        linger: f64,
+    },
+}
+
+/// Copy data from `reader` to `writer`, sending every chunk to the recorder.
+async fn forward(
+    mut reader: OwnedReadHalf,
+    mut writer: OwnedWriteHalf,
+    direction: Direction,
+    recorder: mpsc::UnboundedSender<(Direction, Vec<u8>)>,
+) -> ATDomeResult<()> {
+    let mut buffer = [0; 1024];
+    loop {
+        let n_bytes = reader.read(&mut buffer).await?;
+        if n_bytes == 0 {
+            return Ok(());
+        }
+        let _ = recorder.send((direction, buffer[..n_bytes].to_vec()));
+        writer.write_all(&buffer[..n_bytes]).await?;
+    }
+}
+
+async fn record(listen: &str, controller: &str, output: PathBuf) -> ATDomeResult<()> {
+    fs::create_dir_all(&output)?;
+    let listener = TcpListener::bind(listen).await?;
+    tracing::info!("Listening on {listen}, forwarding to {controller}, recording to {output:?}.");
+
+    for session in 1.. {
+        let (client, client_address) = listener.accept().await?;
+        tracing::info!("Client {client_address} connected.");
+        tokio::spawn(proxy_client(
+            client,
+            client_address,
+            controller.to_owned(),
+            output.join(format!("session-{session:04}.jsonl")),
+        ));
+    }
+    Ok(())
+}
+
+/// Forward the traffic between `client` and a new connection to the
+/// controller until either side disconnects, recording it to its own
+/// transcript at `path`.
+///
+/// If the controller cannot be reached, or the transcript cannot be
+/// created, the client is dropped; the proxy keeps serving the other
+/// clients.
+async fn proxy_client(
+    client: TcpStream,
+    client_address: SocketAddr,
+    controller: String,
+    path: PathBuf,
+) {
+    let upstream = match TcpStream::connect(&controller).await {
+        Ok(upstream) => upstream,
+        Err(error) => {
+            tracing::error!(
+                "Failed to connect to {controller} for client {client_address}: {error}"
+            );
+            return;
+        }
+    };
+    let mut transcript = match TranscriptWriter::create(&path) {
+        Ok(transcript) => transcript,
+        Err(error) => {
+            tracing::error!("Failed to create {path:?} for client {client_address}: {error}");
+            return;
+        }
+    };
+    tracing::info!("Recording client {client_address} to {path:?}.");
+
+    let (recorder, mut recorded) = mpsc::unbounded_channel::<(Direction, Vec<u8>)>();
+    // Ends once both forwarders dropped their recorder.
+    let transcript_task = tokio::spawn(async move {
+        while let Some((direction, data)) = recorded.recv().await {
+            if let Err(error) = transcript.record(direction, &data) {
+                tracing::error!("Failed to record transcript: {error}");
+            }
+        }
+    });
+
+    let (client_reader, client_writer) = client.into_split();
+    let (upstream_reader, upstream_writer) = upstream.into_split();
+
+    let to_controller = forward(
+        client_reader,
+        upstream_writer,
+        Direction::ClientToController,
+        recorder.clone(),
+    );
+    let to_client = forward(
+        upstream_reader,
+        client_writer,
+        Direction::ControllerToClient,
+        recorder,
+    );
+
+    tokio::select! {
+        result = to_controller => tracing::info!("Client {client_address} disconnected: {result:?}"),
+        result = to_client => tracing::info!("Controller disconnected from client {client_address}: {result:?}"),
+    }
+    let _ = transcript_task.await;
+}
+
+async fn replay(controller: &str, input: PathBuf, linger: f64) -> ATDomeResult<()> {
+    let entries = read_transcript(&input)?;
+    let stream = TcpStream::connect(controller).await?;
+    let (mut reader, mut writer) = stream.into_split();
+
+    let reply_task = tokio::spawn(async move {
+        let mut buffer = [0; 1024];
+        while let Ok(n_bytes) = reader.read(&mut buffer).await {
+            if n_bytes == 0 {
+                break;
+            }
+            print!("{}", String::from_utf8_lossy(&buffer[..n_bytes]));
+        }
+    });
+
+    let start_time = Instant::now();
+    for entry in entries
+        .iter()
+        .filter(|entry| entry.direction == Direction::ClientToController)
+    {
+        sleep_until(start_time + Duration::from_secs_f64(entry.elapsed)).await;
+        tracing::info!("Sending {:?}", String::from_utf8_lossy(&entry.data));
+        writer.write_all(&entry.data).await?;
+    }
+    let _ = timeout(Duration::from_secs_f64(linger), reply_task).await;
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    tracing_subscriber::fmt().init();
+
+    match Args::parse().mode {
+        Mode::Record {
+            listen,
+            controller,
+            output,
+        } => record(&listen, &controller, output).await?,
+        Mode::Replay {
+            controller,
+            input,
+            linger,
+        } => replay(&controller, input, linger).await?,
+    }
+
+    Ok(())
+}
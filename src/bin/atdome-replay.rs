@@ -0,0 +1,76 @@
+//! Act as the ATDome controller, replaying the replies of a recorded session.
+//!
+//! Reads a transcript written by `atdome-proxy record` and plays the
+//! controller side of it to every client that connects, with the original
+//! timing, so incidents seen in the field can be reproduced offline against
+//! the model or the CSC.
+
+use clap::Parser;
+use rubin_ts_atdome::{
+    error::ATDomeResult,
+    transcript::{read_transcript, serve_replies, Direction},
+    version::LONG_VERSION,
+};
+use tokio::net::TcpListener;
+
+use std::{error::Error, path::PathBuf};
+
+/// Serve the controller replies of a recorded session.
+#[derive(Parser, Debug)]
+#[command(about, version = LONG_VERSION)]
+struct Args {
+    /// Address to listen for clients on.
+    #[arg(long, default_value = "127.0.0.1:8887")]
+    listen: String,
+    /// Transcript to replay.
+    #[arg(long)]
+    input: PathBuf,
+    /// Factor to speed the replay up by; 1 keeps the original timing.
+    #[arg(long, default_value_t = 1.0)]
+    speed: f64,
+    /// Exit after replaying to the first client instead of waiting for the
+    /// next one.
+    #[arg(long)]
+    once: bool,
+}
+
+async fn serve(args: Args) -> ATDomeResult<()> {
+    let entries = read_transcript(&args.input)?;
+    let n_replies = entries
+        .iter()
+        .filter(|entry| entry.direction == Direction::ControllerToClient)
+        .count();
+
+    let listener = TcpListener::bind(&args.listen).await?;
+    tracing::info!(
+        "Listening on {}, replaying {n_replies} replies from {:?}.",
+        args.listen,
+        args.input
+    );
+
+    // Like the controller, serve one client at a time.
+    loop {
+        let (client, client_address) = listener.accept().await?;
+        tracing::info!("Client {client_address} connected.");
+        match serve_replies(client, &entries, args.speed).await {
+            Ok(()) => tracing::info!("Replayed the transcript to {client_address}."),
+            Err(error) => tracing::warn!("Replay to {client_address} stopped: {error}"),
+        }
+        if args.once {
+            return Ok(());
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    tracing_subscriber::fmt().init();
+
+    let args = Args::parse();
+    if args.speed <= 0.0 {
+        return Err("--speed must be positive.".into());
+    }
+    serve(args).await?;
+
+    Ok(())
+}
@@ -0,0 +1,64 @@
+//! Print the status of the ATDome controller.
+//!
+//! Connects to the controller, requests the status once and prints it as
+//! json or as a table.
+
+use clap::{Parser, ValueEnum};
+use rubin_ts_atdome::{controller_client::ControllerClient, version::LONG_VERSION};
+
+use std::{error::Error, time::Duration};
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum OutputFormat {
+    Json,
+    Table,
+}
+
+/// Print the status of the ATDome controller.
+#[derive(Parser, Debug)]
+#[command(about, version = LONG_VERSION)]
+struct Args {
+    /// Address of the controller.
+    #[arg(long, default_value = "127.0.0.1")]
+    host: String,
+
+    /// Port of the controller.
+    #[arg(short, long, default_value_t = 8887)]
+    port: usize,
+
+    /// Time limit for the controller replies (sec).
+    #[arg(long, default_value_t = 10.0)]
+    timeout: f32,
+
+    /// Output format.
+    #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+    format: OutputFormat,
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse();
+
+    let (mut client, _) =
+        ControllerClient::connect(&args.host, args.port, Duration::from_secs_f32(args.timeout))?;
+    let status = client.get_status()?;
+
+    match args.format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&status)?),
+        OutputFormat::Table => {
+            if let serde_json::Value::Object(fields) = serde_json::to_value(status)? {
+                let width = fields.keys().map(|name| name.len()).max().unwrap_or(0);
+                for (name, value) in fields {
+                    println!("{name:<width$}  {value}");
+                }
+                println!("{:<width$}  {}", "azimuth_error", status.azimuth_error());
+                println!(
+                    "{:<width$}  {}",
+                    "azimuth_in_position",
+                    status.azimuth_in_position()
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,98 @@
+//! Interactive terminal client for the ATDome controller.
+//!
+//! Connects to the controller (real or mock), sends the commands typed at
+//! the prompt and prints the replies. Status replies are parsed and changes
+//! in the move code are highlighted.
+
+use clap::Parser;
+use rubin_ts_atdome::{
+    controller_client::{parse_status_reply, ControllerClient},
+    move_code::MoveCode,
+    status::Status,
+    version::LONG_VERSION,
+};
+use rustyline::{error::ReadlineError, DefaultEditor};
+
+use std::{error::Error, time::Duration};
+
+const HIGHLIGHT: &str = "\x1b[1;33m";
+const RESET: &str = "\x1b[0m";
+
+/// Interactive terminal client for the ATDome controller.
+#[derive(Parser, Debug)]
+#[command(about, version = LONG_VERSION)]
+struct Args {
+    /// Address of the controller.
+    #[arg(long, default_value = "127.0.0.1")]
+    host: String,
+
+    /// Port of the controller.
+    #[arg(short, long, default_value_t = 8887)]
+    port: usize,
+
+    /// Time limit for the controller replies (sec).
+    #[arg(long, default_value_t = 10.0)]
+    timeout: f32,
+}
+
+/// Names of the flags set in a move code.
+fn move_code_flags(move_code: u8) -> Vec<String> {
+    MoveCode::active(move_code)
+        .map(|flag| format!("{flag:?}"))
+        .collect()
+}
+
+fn print_status(status: &Status, last_move_code: Option<u8>) {
+    println!("{status:#?}");
+    if last_move_code != Some(status.move_code) {
+        println!(
+            "{HIGHLIGHT}Move code changed {} -> {}: {:?}{RESET}",
+            last_move_code.map_or("unknown".to_owned(), |move_code| move_code.to_string()),
+            status.move_code,
+            move_code_flags(status.move_code)
+        );
+    }
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse();
+
+    let (mut client, banner) =
+        ControllerClient::connect(&args.host, args.port, Duration::from_secs_f32(args.timeout))?;
+    print!("{banner}");
+
+    let mut editor = DefaultEditor::new()?;
+    let mut last_move_code = None;
+
+    loop {
+        let line = match editor.readline("atdome> ") {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) | Err(ReadlineError::Eof) => break,
+            Err(error) => return Err(error.into()),
+        };
+        let command = line.trim();
+        if command.is_empty() {
+            continue;
+        }
+        let _ = editor.add_history_entry(command);
+        if command == "quit" || command == "exit" {
+            break;
+        }
+
+        let reply = client.send_command(command)?;
+
+        if command == "+" {
+            match parse_status_reply(&reply) {
+                Ok(status) => {
+                    print_status(&status, last_move_code);
+                    last_move_code = Some(status.move_code);
+                }
+                Err(error) => println!("{reply}\nFailed to parse status: {error}"),
+            }
+        } else if !reply.trim().is_empty() {
+            println!("{}", reply.trim_end());
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,75 @@
+//! Minimal blocking client for the ATDome controller.
+//!
+//! Used by the command line tools that only need to exchange a few
+//! commands with the controller and do not require the async model.
+
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    str,
+    time::Duration,
+};
+
+use crate::{
+    error::{ATDomeError, ATDomeResult},
+    status::Status,
+    status_parser::StatusParser,
+};
+
+pub struct ControllerClient {
+    stream: TcpStream,
+}
+
+impl ControllerClient {
+    /// Connect to the controller and wait for the first prompt.
+    ///
+    /// Returns the client and the welcome message sent by the controller.
+    pub fn connect(host: &str, port: usize, timeout: Duration) -> ATDomeResult<(Self, String)> {
+        let stream = TcpStream::connect(format!("{host}:{port}"))?;
+        stream.set_read_timeout(Some(timeout))?;
+        let mut client = ControllerClient { stream };
+        let banner = client.read_until_prompt()?;
+        Ok((client, banner))
+    }
+
+    /// Read from the controller until the prompt is received.
+    ///
+    /// Returns the text received before the prompt.
+    pub fn read_until_prompt(&mut self) -> ATDomeResult<String> {
+        let mut buffer = [0; 1024];
+        let mut reply = String::new();
+        loop {
+            let n_bytes = self.stream.read(&mut buffer)?;
+            if n_bytes == 0 {
+                return Err(ATDomeError::Connection(
+                    "Connection closed by the controller.".to_owned(),
+                ));
+            }
+            let text = str::from_utf8(&buffer[..n_bytes])
+                .map_err(|error| ATDomeError::Parse(format!("Invalid reply: {error}")))?;
+            reply.push_str(text);
+            if let Some(prompt_index) = reply.rfind('>') {
+                reply.truncate(prompt_index);
+                return Ok(reply);
+            }
+        }
+    }
+
+    /// Send a command and return the reply received before the prompt.
+    pub fn send_command(&mut self, command: &str) -> ATDomeResult<String> {
+        self.stream.write_all(format!("{command}\r\n").as_bytes())?;
+        self.read_until_prompt()
+    }
+
+    /// Request and parse the controller status.
+    pub fn get_status(&mut self) -> ATDomeResult<Status> {
+        let reply = self.send_command("+")?;
+        parse_status_reply(&reply)
+    }
+}
+
+/// Parse the reply of the status command.
+pub fn parse_status_reply(reply: &str) -> ATDomeResult<Status> {
+    let lines: Vec<&str> = reply.lines().filter(|line| !line.is_empty()).collect();
+    StatusParser::new()?.make_status(&lines)
+}
@@ -1,73 +1,408 @@
-//! Define a general purpose ATDomeError struct.
+//! Define a general purpose ATDomeError enum.
+//!
+//! Each variant represents a category of failure so callers can match on
+//! the kind of error instead of parsing the message. Errors coming from
+//! other libraries are wrapped so their source chain is preserved.
 
-use kafka::error as kafka_error;
 use regex::Error as RegexError;
-use salobj::error::errors::SalObjError;
-use std::{
-    error::Error,
-    fmt::{self, Debug},
-    result,
+use std::{error::Error, fmt, result};
+use thiserror::Error as ThisError;
+use tokio::{
+    sync::{mpsc, oneshot},
+    task::JoinError,
+    time::error::Elapsed,
+};
+
+use crate::{
+    ack_codes::{CMD_FAILED, CMD_NOPERM, CMD_TIMEOUT},
+    atdome_model::ATDomeCmd,
 };
 
 pub type ATDomeResult<T> = result::Result<T, ATDomeError>;
 
-#[derive(Debug)]
-pub struct ATDomeError {
-    err_msg: String,
-}
+/// Error codes reported in the errorCode event for each error category.
+///
+/// These values are part of the CSC interface and must not change.
+pub const CONNECTION_ERROR_CODE: i32 = 1;
+pub const TIMEOUT_ERROR_CODE: i32 = 2;
+pub const PARSE_ERROR_CODE: i32 = 3;
+pub const COMMAND_REJECTED_ERROR_CODE: i32 = 4;
+pub const INVALID_STATE_ERROR_CODE: i32 = 5;
+pub const BUSY_ERROR_CODE: i32 = 6;
+pub const INTERNAL_ERROR_CODE: i32 = 99;
 
-impl Error for ATDomeError {}
+/// Phase of a controller command in which an error occurred.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandPhase {
+    /// Writing the command to the controller.
+    Write,
+    /// Waiting for the controller to return the prompt.
+    AwaitPrompt,
+    /// Parsing the reply of the controller.
+    Parse,
+}
 
-impl fmt::Display for ATDomeError {
+impl fmt::Display for CommandPhase {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let err_msg = self.err_msg.clone();
-        write!(f, "ATDomeError::{err_msg}")
+        match self {
+            CommandPhase::Write => write!(f, "writing the command"),
+            CommandPhase::AwaitPrompt => write!(f, "waiting for the prompt"),
+            CommandPhase::Parse => write!(f, "parsing the reply"),
+        }
     }
 }
+
+#[derive(Debug, ThisError)]
+pub enum ATDomeError {
+    /// Failed to connect or communicate with the controller or the broker.
+    #[error("ATDomeError::Connection::{0}")]
+    Connection(String),
+    /// An operation did not complete in the expected time.
+    #[error("ATDomeError::Timeout::{0}")]
+    Timeout(String),
+    /// Failed to parse data received from the controller.
+    #[error("ATDomeError::Parse::{0}")]
+    Parse(String),
+    /// The controller rejected a command.
+    #[error("ATDomeError::CommandRejected::{0}")]
+    CommandRejected(String),
+    /// The operation is not allowed in the current state.
+    #[error("ATDomeError::InvalidState::{0}")]
+    InvalidState(String),
+    /// The CSC cannot take the command right now, e.g. because its command
+    /// queue is full; the command can be sent again later.
+    #[error("ATDomeError::Busy::{0}")]
+    Busy(String),
+    /// Any other internal failure.
+    #[error("ATDomeError::Internal::{0}")]
+    Internal(String),
+    /// I/O error on the controller socket.
+    #[error("ATDomeError::Io::{0}")]
+    Io(#[from] std::io::Error),
+    /// Invalid regular expression.
+    #[error("ATDomeError::Regex::{0}")]
+    Regex(#[from] RegexError),
+    /// Failure of a controller command; the category is that of `source`.
+    #[error("ATDomeError::Command::{command:?} failed while {phase}: {source}")]
+    Command {
+        command: ATDomeCmd,
+        phase: CommandPhase,
+        source: Box<ATDomeError>,
+    },
+}
+
 impl ATDomeError {
-    pub fn new(err_msg: &str) -> ATDomeError {
-        ATDomeError {
-            err_msg: String::from(err_msg),
+    pub fn from_error(error: impl Error) -> ATDomeError {
+        ATDomeError::Internal(error.to_string())
+    }
+
+    /// Attach the command being executed and the phase it failed in.
+    pub fn with_command(self, command: &ATDomeCmd, phase: CommandPhase) -> ATDomeError {
+        ATDomeError::Command {
+            command: command.clone(),
+            phase,
+            source: Box::new(self),
         }
     }
 
-    pub fn from_error(error: impl Error) -> ATDomeError {
-        ATDomeError {
-            err_msg: error.to_string(),
+    /// Copy of the error for another caller waiting on the same operation.
+    ///
+    /// Errors from other libraries are not `Clone`, so they are copied as
+    /// the message of an error of the same category.
+    pub fn duplicate(&self) -> ATDomeError {
+        match self {
+            ATDomeError::Connection(err_msg) => ATDomeError::Connection(err_msg.to_owned()),
+            ATDomeError::Timeout(err_msg) => ATDomeError::Timeout(err_msg.to_owned()),
+            ATDomeError::Parse(err_msg) => ATDomeError::Parse(err_msg.to_owned()),
+            ATDomeError::CommandRejected(err_msg) => {
+                ATDomeError::CommandRejected(err_msg.to_owned())
+            }
+            ATDomeError::InvalidState(err_msg) => ATDomeError::InvalidState(err_msg.to_owned()),
+            ATDomeError::Busy(err_msg) => ATDomeError::Busy(err_msg.to_owned()),
+            ATDomeError::Internal(err_msg) => ATDomeError::Internal(err_msg.to_owned()),
+            ATDomeError::Io(error) => {
+                ATDomeError::Io(std::io::Error::new(error.kind(), error.to_string()))
+            }
+            ATDomeError::Regex(error) => ATDomeError::Regex(error.clone()),
+            ATDomeError::Command {
+                command,
+                phase,
+                source,
+            } => ATDomeError::Command {
+                command: command.clone(),
+                phase: *phase,
+                source: Box::new(source.duplicate()),
+            },
+        }
+    }
+
+    /// Command and phase the error occurred in, if known.
+    pub fn command_context(&self) -> Option<(&ATDomeCmd, CommandPhase)> {
+        match self {
+            ATDomeError::Command { command, phase, .. } => Some((command, *phase)),
+            _ => None,
+        }
+    }
+
+    /// Name of the error category.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ATDomeError::Connection(_) | ATDomeError::Io(_) => "Connection",
+            ATDomeError::Timeout(_) => "Timeout",
+            ATDomeError::Parse(_) => "Parse",
+            ATDomeError::CommandRejected(_) => "CommandRejected",
+            ATDomeError::InvalidState(_) => "InvalidState",
+            ATDomeError::Busy(_) => "Busy",
+            ATDomeError::Internal(_) | ATDomeError::Regex(_) => "Internal",
+            ATDomeError::Command { source, .. } => source.kind(),
+        }
+    }
+
+    /// Numeric code of the error category.
+    pub fn error_code(&self) -> i32 {
+        match self {
+            ATDomeError::Connection(_) | ATDomeError::Io(_) => CONNECTION_ERROR_CODE,
+            ATDomeError::Timeout(_) => TIMEOUT_ERROR_CODE,
+            ATDomeError::Parse(_) => PARSE_ERROR_CODE,
+            ATDomeError::CommandRejected(_) => COMMAND_REJECTED_ERROR_CODE,
+            ATDomeError::InvalidState(_) => INVALID_STATE_ERROR_CODE,
+            ATDomeError::Busy(_) => BUSY_ERROR_CODE,
+            ATDomeError::Internal(_) | ATDomeError::Regex(_) => INTERNAL_ERROR_CODE,
+            ATDomeError::Command { source, .. } => source.error_code(),
+        }
+    }
+
+    /// SAL ack code of a command that failed with this error.
+    pub fn ack_code(&self) -> i32 {
+        match self {
+            ATDomeError::InvalidState(_) | ATDomeError::Busy(_) => CMD_NOPERM,
+            ATDomeError::Timeout(_) => CMD_TIMEOUT,
+            ATDomeError::Command { source, .. } => source.ack_code(),
+            _ => CMD_FAILED,
+        }
+    }
+
+    /// Can the operation that produced this error be retried?
+    ///
+    /// Timeouts, transient parse failures and connection problems are
+    /// retryable; everything else should drive the CSC to Fault.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            ATDomeError::Connection(_)
+            | ATDomeError::Io(_)
+            | ATDomeError::Timeout(_)
+            | ATDomeError::Parse(_)
+            | ATDomeError::Busy(_) => true,
+            ATDomeError::Command { source, .. } => source.is_retryable(),
+            _ => false,
+        }
+    }
+
+    /// Does recovering from this error require reconnecting to the
+    /// controller?
+    pub fn requires_reconnect(&self) -> bool {
+        match self {
+            ATDomeError::Connection(_) | ATDomeError::Io(_) => true,
+            ATDomeError::Command { source, .. } => source.requires_reconnect(),
+            _ => false,
         }
     }
 
-    pub fn get_error_message(&self) -> &str {
-        &self.err_msg
+    pub fn get_error_message(&self) -> String {
+        match self {
+            ATDomeError::Connection(err_msg)
+            | ATDomeError::Timeout(err_msg)
+            | ATDomeError::Parse(err_msg)
+            | ATDomeError::CommandRejected(err_msg)
+            | ATDomeError::InvalidState(err_msg)
+            | ATDomeError::Busy(err_msg)
+            | ATDomeError::Internal(err_msg) => err_msg.to_owned(),
+            ATDomeError::Io(error) => error.to_string(),
+            ATDomeError::Regex(error) => error.to_string(),
+            ATDomeError::Command {
+                command,
+                phase,
+                source,
+            } => format!(
+                "{command:?} failed while {phase}: {}",
+                source.get_error_message()
+            ),
+        }
     }
 }
 
 impl From<Box<dyn Error>> for ATDomeError {
     fn from(item: Box<dyn Error>) -> ATDomeError {
-        ATDomeError::new(&item.to_string())
+        ATDomeError::Internal(item.to_string())
+    }
+}
+
+impl From<Elapsed> for ATDomeError {
+    fn from(_: Elapsed) -> ATDomeError {
+        ATDomeError::Timeout("Deadline has elapsed.".to_owned())
     }
 }
 
-impl From<SalObjError> for ATDomeError {
-    fn from(item: SalObjError) -> ATDomeError {
-        ATDomeError::new(&item.to_string())
+/// The receiver is gone, e.g. the task reading the channel stopped.
+///
+/// Internal channels closing is a bug, not a link to recover; the model
+/// maps the failures of its command channel to `Connection` itself.
+impl<T> From<mpsc::error::SendError<T>> for ATDomeError {
+    fn from(_: mpsc::error::SendError<T>) -> ATDomeError {
+        ATDomeError::Internal("Channel closed by the receiver.".to_owned())
     }
 }
 
-impl From<kafka_error::Error> for ATDomeError {
-    fn from(item: kafka_error::Error) -> ATDomeError {
-        ATDomeError::new(&item.to_string())
+impl<T> From<mpsc::error::TrySendError<T>> for ATDomeError {
+    fn from(error: mpsc::error::TrySendError<T>) -> ATDomeError {
+        match error {
+            mpsc::error::TrySendError::Full(_) => {
+                ATDomeError::Busy("Channel full; the receiver is not keeping up.".to_owned())
+            }
+            mpsc::error::TrySendError::Closed(_) => {
+                ATDomeError::Internal("Channel closed by the receiver.".to_owned())
+            }
+        }
     }
 }
 
-impl From<RegexError> for ATDomeError {
-    fn from(item: RegexError) -> ATDomeError {
-        ATDomeError::new(&item.to_string())
+/// The sender was dropped without sending, e.g. its task stopped.
+impl From<oneshot::error::RecvError> for ATDomeError {
+    fn from(_: oneshot::error::RecvError) -> ATDomeError {
+        ATDomeError::Internal("Channel closed before the reply was sent.".to_owned())
     }
 }
 
-impl From<std::io::Error> for ATDomeError {
-    fn from(item: std::io::Error) -> ATDomeError {
-        ATDomeError::new(&item.to_string())
+impl From<JoinError> for ATDomeError {
+    fn from(error: JoinError) -> ATDomeError {
+        if error.is_cancelled() {
+            ATDomeError::Internal("Task cancelled.".to_owned())
+        } else {
+            ATDomeError::Internal(format!("Task failed: {error}."))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_io_error_keeps_source() {
+        let error = ATDomeError::from(std::io::Error::new(
+            std::io::ErrorKind::ConnectionRefused,
+            "refused",
+        ));
+
+        assert_eq!(error.kind(), "Connection");
+        assert_eq!(error.error_code(), CONNECTION_ERROR_CODE);
+        assert!(error.source().is_some());
+    }
+
+    #[test]
+    fn test_is_retryable() {
+        assert!(ATDomeError::Timeout("no reply".to_owned()).is_retryable());
+        assert!(ATDomeError::Connection("closed".to_owned()).requires_reconnect());
+        assert!(!ATDomeError::Parse("bad line".to_owned()).requires_reconnect());
+        assert!(!ATDomeError::CommandRejected("estop".to_owned()).is_retryable());
+        assert!(!ATDomeError::Internal("bug".to_owned()).is_retryable());
+    }
+
+    #[test]
+    fn test_message_error_has_no_source() {
+        let error = ATDomeError::Parse("Failed to match line".to_owned());
+
+        assert_eq!(error.error_code(), PARSE_ERROR_CODE);
+        assert!(error.source().is_none());
+    }
+
+    #[test]
+    fn test_ack_code() {
+        assert_eq!(
+            ATDomeError::InvalidState("Not enabled.".to_owned()).ack_code(),
+            CMD_NOPERM
+        );
+        assert_eq!(
+            ATDomeError::Timeout("no reply".to_owned())
+                .with_command(&ATDomeCmd::GetStatus, CommandPhase::AwaitPrompt)
+                .ack_code(),
+            CMD_TIMEOUT
+        );
+        assert_eq!(
+            ATDomeError::CommandRejected("estop".to_owned()).ack_code(),
+            CMD_FAILED
+        );
+        assert_eq!(
+            ATDomeError::Busy("queue full".to_owned()).ack_code(),
+            CMD_NOPERM
+        );
+    }
+
+    #[test]
+    fn test_duplicate() {
+        let error = ATDomeError::from(std::io::Error::new(
+            std::io::ErrorKind::ConnectionReset,
+            "reset",
+        ))
+        .with_command(&ATDomeCmd::GetStatus, CommandPhase::AwaitPrompt);
+
+        let duplicate = error.duplicate();
+
+        assert_eq!(duplicate.kind(), error.kind());
+        assert!(duplicate.requires_reconnect());
+        assert_eq!(duplicate.command_context(), error.command_context());
+        assert_eq!(duplicate.get_error_message(), error.get_error_message());
+    }
+
+    #[test]
+    fn test_with_command() {
+        let error = ATDomeError::Parse("Failed to match line".to_owned())
+            .with_command(&ATDomeCmd::GetStatus, CommandPhase::Parse);
+
+        assert_eq!(error.kind(), "Parse");
+        assert_eq!(error.error_code(), PARSE_ERROR_CODE);
+        assert!(error.is_retryable());
+        assert_eq!(
+            error.command_context(),
+            Some((&ATDomeCmd::GetStatus, CommandPhase::Parse))
+        );
+        assert_eq!(
+            error.get_error_message(),
+            "GetStatus failed while parsing the reply: Failed to match line"
+        );
+        assert!(error.source().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_from_tokio_errors() {
+        let elapsed = tokio::time::timeout(std::time::Duration::ZERO, std::future::pending::<()>())
+            .await
+            .unwrap_err();
+        assert_eq!(ATDomeError::from(elapsed).kind(), "Timeout");
+
+        let (sender, receiver) = mpsc::channel::<u8>(1);
+        sender.try_send(1).unwrap();
+        let error = ATDomeError::from(sender.try_send(2).unwrap_err());
+        assert_eq!(error.kind(), "Busy");
+        assert!(error.is_retryable());
+        drop(receiver);
+        let error = ATDomeError::from(sender.try_send(3).unwrap_err());
+        assert_eq!(error.kind(), "Internal");
+        let error = ATDomeError::from(sender.send(4).await.unwrap_err());
+        assert_eq!(error.kind(), "Internal");
+        assert!(!error.requires_reconnect());
+        assert!(!error.is_retryable());
+
+        let (reply_sender, reply_receiver) = oneshot::channel::<u8>();
+        drop(reply_sender);
+        assert_eq!(
+            ATDomeError::from(reply_receiver.await.unwrap_err()).kind(),
+            "Internal"
+        );
+
+        let task = tokio::spawn(async { panic!("boom") });
+        let error = ATDomeError::from(task.await.unwrap_err());
+        assert_eq!(error.kind(), "Internal");
+        assert!(error.get_error_message().contains("boom"));
     }
 }
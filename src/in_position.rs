@@ -0,0 +1,196 @@
+//! Decide whether the azimuth and the shutter are in position.
+//!
+//! The controller only reports positions and motion bits, so the CSC
+//! compares them against the commanded targets. A hysteresis band keeps the
+//! in-position events from chattering when the position sits right at the
+//! edge of the tolerance.
+
+use crate::{azimuth::azimuth_distance, move_code::MoveCode, status::Status};
+
+/// Tolerance used to decide that a door reached its target (%).
+pub const DOOR_TOLERANCE: f32 = 1.0;
+
+/// Extra margin an in-position door must leave `DOOR_TOLERANCE` by before
+/// the shutter is reported out of position (%).
+pub const DOOR_HYSTERESIS: f32 = 0.5;
+
+/// Targets most recently commanded to the controller.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct CommandedTargets {
+    /// Commanded azimuth (deg).
+    pub azimuth: Option<f32>,
+    /// Commanded main door opening (%).
+    pub main_door: Option<f32>,
+    /// Commanded dropout door opening (%).
+    pub dropout_door: Option<f32>,
+}
+
+/// Track the in-position state of a single axis.
+#[derive(Debug, Clone, Copy)]
+pub struct InPositionTracker {
+    /// Extra margin an in-position axis must leave the tolerance by before
+    /// it is reported out of position.
+    hysteresis: f32,
+    in_position: Option<bool>,
+}
+
+impl InPositionTracker {
+    pub fn new(hysteresis: f32) -> InPositionTracker {
+        InPositionTracker {
+            hysteresis,
+            in_position: None,
+        }
+    }
+
+    /// Current in-position state, `None` until the first update.
+    pub fn in_position(&self) -> Option<bool> {
+        self.in_position
+    }
+
+    /// Update the tracker with the distance to the target.
+    ///
+    /// Returns the new state if it changed.
+    pub fn update(&mut self, distance: f32, tolerance: f32, moving: bool) -> Option<bool> {
+        let limit = if self.in_position == Some(true) {
+            tolerance + self.hysteresis
+        } else {
+            tolerance
+        };
+        let in_position = !moving && distance.abs() <= limit;
+        if self.in_position == Some(in_position) {
+            None
+        } else {
+            self.in_position = Some(in_position);
+            Some(in_position)
+        }
+    }
+
+    /// Forget the current state so the next update is always reported.
+    pub fn reset(&mut self) {
+        self.in_position = None;
+    }
+}
+
+/// In-position trackers for the azimuth and the shutter.
+#[derive(Debug, Clone, Copy)]
+pub struct DomeInPosition {
+    pub azimuth: InPositionTracker,
+    pub shutter: InPositionTracker,
+}
+
+impl DomeInPosition {
+    /// Create the trackers with the azimuth hysteresis in deg.
+    pub fn new(azimuth_hysteresis: f32) -> DomeInPosition {
+        DomeInPosition {
+            azimuth: InPositionTracker::new(azimuth_hysteresis),
+            shutter: InPositionTracker::new(DOOR_HYSTERESIS),
+        }
+    }
+
+    /// Update the azimuth tracker; returns the new state if it changed.
+    ///
+    /// Nothing is reported until an azimuth is commanded.
+    pub fn update_azimuth(&mut self, status: &Status, targets: &CommandedTargets) -> Option<bool> {
+        let target = targets.azimuth?;
+        self.azimuth.update(
+            azimuth_distance(status.az_pos, target),
+            status.tolerance,
+            MoveCode::is_azimuth_moving(status.move_code),
+        )
+    }
+
+    /// Update the shutter tracker; returns the new state if it changed.
+    ///
+    /// Nothing is reported until a door position is commanded.
+    pub fn update_shutter(&mut self, status: &Status, targets: &CommandedTargets) -> Option<bool> {
+        if targets.main_door.is_none() && targets.dropout_door.is_none() {
+            return None;
+        }
+        let main_door_distance = targets
+            .main_door
+            .map_or(0.0, |target| status.main_door_pct - target);
+        let dropout_door_distance = targets
+            .dropout_door
+            .map_or(0.0, |target| status.dropout_door_pct - target);
+        self.shutter.update(
+            main_door_distance.abs().max(dropout_door_distance.abs()),
+            DOOR_TOLERANCE,
+            MoveCode::is_any_door_moving(status.move_code),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_in_position_tracker_hysteresis() {
+        let mut tracker = InPositionTracker::new(0.5);
+
+        assert_eq!(tracker.update(2.0, 1.0, false), Some(false));
+        assert_eq!(tracker.update(0.9, 1.0, false), Some(true));
+        // Inside the hysteresis band the state does not change.
+        assert_eq!(tracker.update(1.4, 1.0, false), None);
+        assert_eq!(tracker.update(0.9, 1.0, false), None);
+        assert_eq!(tracker.update(1.6, 1.0, false), Some(false));
+        // Re-entering requires being within the tolerance again.
+        assert_eq!(tracker.update(1.2, 1.0, false), None);
+        assert_eq!(tracker.update(1.0, 1.0, false), Some(true));
+    }
+
+    #[test]
+    fn test_in_position_tracker_moving() {
+        let mut tracker = InPositionTracker::new(0.5);
+
+        assert_eq!(tracker.update(0.0, 1.0, true), Some(false));
+        assert_eq!(tracker.update(0.0, 1.0, false), Some(true));
+    }
+
+    #[test]
+    fn test_dome_in_position_azimuth_wraps() {
+        let mut dome_in_position = DomeInPosition::new(0.5);
+        let status = Status {
+            tolerance: 1.0,
+            az_pos: 359.5,
+            ..Default::default()
+        };
+        let targets = CommandedTargets {
+            azimuth: Some(0.2),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            dome_in_position.update_azimuth(&status, &targets),
+            Some(true)
+        );
+        assert_eq!(dome_in_position.update_shutter(&status, &targets), None);
+    }
+
+    #[test]
+    fn test_dome_in_position_shutter_hysteresis() {
+        let mut dome_in_position = DomeInPosition::new(0.5);
+        let targets = CommandedTargets {
+            main_door: Some(100.0),
+            ..Default::default()
+        };
+        let status = |main_door_pct| Status {
+            main_door_pct,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            dome_in_position.update_shutter(&status(99.5), &targets),
+            Some(true)
+        );
+        // Within the hysteresis band past the door tolerance.
+        assert_eq!(
+            dome_in_position.update_shutter(&status(100.0 - DOOR_TOLERANCE - 0.4), &targets),
+            None
+        );
+        assert_eq!(
+            dome_in_position.update_shutter(&status(100.0 - DOOR_TOLERANCE - 0.6), &targets),
+            Some(false)
+        );
+    }
+}
@@ -1,8 +1,19 @@
+pub mod ack_codes;
 pub mod atdome_cmd_regex;
-pub mod atdome_csc;
+pub mod atdome_config;
+pub mod atdome_enums;
 pub mod atdome_model;
+pub mod azimuth;
+pub mod controller_client;
 pub mod error;
+pub mod in_position;
+pub mod logging;
 pub mod mock_controller;
 pub mod move_code;
+pub mod shutdown_signal;
 pub mod status;
 pub mod status_parser;
+#[cfg(feature = "test-utils")]
+pub mod test_utils;
+pub mod transcript;
+pub mod version;
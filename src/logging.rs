@@ -0,0 +1,205 @@
+//! Initialize the logging output of the executables.
+//!
+//! The level can be set for the whole process and overridden for some
+//! modules, given by their tracing target (e.g.
+//! "rubin_ts_atdome::atdome_model"); the levels can be changed at runtime
+//! with `set_log_level`, e.g. by the setLogLevel command of the CSC.
+
+use std::{
+    collections::BTreeMap,
+    sync::{Mutex, OnceLock},
+};
+
+#[cfg(feature = "cli")]
+use clap::ValueEnum;
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::{
+    fmt, layer::SubscriberExt, reload, util::SubscriberInitExt, EnvFilter, Registry,
+};
+
+use crate::error::{ATDomeError, ATDomeResult};
+
+/// Format of the log output.
+#[derive(Clone, Copy, Debug, Default)]
+#[cfg_attr(feature = "cli", derive(ValueEnum))]
+pub enum LogFormat {
+    /// Human readable text.
+    #[default]
+    Text,
+    /// One json object per line, with timestamp, level, target and fields.
+    Json,
+}
+
+/// Log level of the process, with overrides for some modules.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogLevels {
+    /// Level of the modules without an override.
+    pub default: LevelFilter,
+    /// Level of the modules, by tracing target; a target also applies to
+    /// its submodules.
+    pub modules: BTreeMap<String, LevelFilter>,
+}
+
+impl LogLevels {
+    pub fn new(default: LevelFilter) -> LogLevels {
+        LogLevels {
+            default,
+            modules: BTreeMap::new(),
+        }
+    }
+
+    pub fn with_module(mut self, target: &str, level: LevelFilter) -> ATDomeResult<LogLevels> {
+        self.set(Some(target), level)?;
+        Ok(self)
+    }
+
+    /// Set the level of `target`, or the default level if None.
+    pub fn set(&mut self, target: Option<&str>, level: LevelFilter) -> ATDomeResult<()> {
+        match target {
+            Some(target) => {
+                validate_target(target)?;
+                self.modules.insert(target.to_owned(), level);
+            }
+            None => self.default = level,
+        }
+        Ok(())
+    }
+
+    /// Filter selecting the events enabled by the levels.
+    pub fn filter(&self) -> EnvFilter {
+        let mut filter = EnvFilter::default().add_directive(self.default.into());
+        for (target, level) in &self.modules {
+            // Targets are validated when added, so the directive is valid.
+            if let Ok(directive) = format!("{target}={level}").parse() {
+                filter = filter.add_directive(directive);
+            }
+        }
+        filter
+    }
+}
+
+/// Levels and filter of the global subscriber installed by `init_logging`.
+struct LogControl {
+    levels: Mutex<LogLevels>,
+    handle: reload::Handle<EnvFilter, Registry>,
+}
+
+static LOG_CONTROL: OnceLock<LogControl> = OnceLock::new();
+
+/// Install the global log subscriber.
+pub fn init_logging(levels: LogLevels, format: LogFormat) {
+    let (filter, handle) = reload::Layer::new(levels.filter());
+    let registry = tracing_subscriber::registry().with(filter);
+    match format {
+        LogFormat::Text => registry.with(fmt::layer()).init(),
+        LogFormat::Json => registry
+            .with(fmt::layer().json().with_current_span(true))
+            .init(),
+    }
+    let _ = LOG_CONTROL.set(LogControl {
+        levels: Mutex::new(levels),
+        handle,
+    });
+}
+
+/// Change the level of `target`, or the default level if None, of the
+/// subscriber installed by `init_logging`.
+pub fn set_log_level(target: Option<&str>, level: LevelFilter) -> ATDomeResult<()> {
+    let control = LOG_CONTROL
+        .get()
+        .ok_or_else(|| ATDomeError::Internal("Logging was not initialized.".to_owned()))?;
+    let mut levels = control
+        .levels
+        .lock()
+        .map_err(|_| ATDomeError::Internal("The log levels are poisoned.".to_owned()))?;
+    levels.set(target, level)?;
+    control
+        .handle
+        .reload(levels.filter())
+        .map_err(|error| ATDomeError::Internal(format!("Failed to set the log level: {error}")))?;
+    tracing::info!(
+        "Set the log level of {} to {level}.",
+        target.unwrap_or("the process")
+    );
+    Ok(())
+}
+
+/// Parse a "target=level" override given on the command line.
+pub fn parse_module_level(value: &str) -> Result<(String, LevelFilter), String> {
+    let (target, level) = value
+        .split_once('=')
+        .ok_or_else(|| format!("Expected target=level, got {value:?}."))?;
+    validate_target(target).map_err(|error| error.to_string())?;
+    let level = level
+        .parse::<LevelFilter>()
+        .map_err(|error| format!("Invalid level {level:?}: {error}"))?;
+    Ok((target.to_owned(), level))
+}
+
+/// Level matching a Python logging level, as used by setLogLevel.
+pub fn level_from_python(level: i32) -> LevelFilter {
+    match level {
+        i32::MIN..=9 => LevelFilter::TRACE,
+        10..=19 => LevelFilter::DEBUG,
+        20..=29 => LevelFilter::INFO,
+        30..=39 => LevelFilter::WARN,
+        _ => LevelFilter::ERROR,
+    }
+}
+
+/// Reject targets that would not be read as a single filter directive.
+fn validate_target(target: &str) -> ATDomeResult<()> {
+    if target.is_empty()
+        || !target
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':')
+    {
+        return Err(ATDomeError::CommandRejected(format!(
+            "Invalid log target {target:?}."
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_levels_filter() {
+        let levels = LogLevels::new(LevelFilter::INFO)
+            .with_module("rubin_ts_atdome::atdome_model", LevelFilter::DEBUG)
+            .unwrap();
+
+        let filter = levels.filter().to_string();
+
+        assert!(filter.contains("rubin_ts_atdome::atdome_model=debug"));
+        assert!(filter.contains("info"));
+        assert!(LogLevels::new(LevelFilter::INFO)
+            .with_module("a=trace,b", LevelFilter::DEBUG)
+            .is_err());
+    }
+
+    #[test]
+    fn test_parse_module_level() {
+        assert_eq!(
+            parse_module_level("rubin_ts_atdome::atdome_model=debug"),
+            Ok((
+                "rubin_ts_atdome::atdome_model".to_owned(),
+                LevelFilter::DEBUG
+            ))
+        );
+        assert!(parse_module_level("atdome_model").is_err());
+        assert!(parse_module_level("atdome_model=loud").is_err());
+    }
+
+    #[test]
+    fn test_level_from_python() {
+        assert_eq!(level_from_python(5), LevelFilter::TRACE);
+        assert_eq!(level_from_python(10), LevelFilter::DEBUG);
+        assert_eq!(level_from_python(20), LevelFilter::INFO);
+        assert_eq!(level_from_python(30), LevelFilter::WARN);
+        assert_eq!(level_from_python(40), LevelFilter::ERROR);
+        assert_eq!(level_from_python(50), LevelFilter::ERROR);
+    }
+}
@@ -1,10 +1,150 @@
-use rubin_ts_atdome::mock_controller::mock_controller::run_mock_controller;
+use clap::Parser;
+use rubin_ts_atdome::{
+    logging::{init_logging, LogFormat, LogLevels},
+    mock_controller::mock_controller::{
+        run_mock_controller_with_shutdown, LineTerminator, MockControllerConfig, StatusChunking,
+    },
+    shutdown_signal::wait_for_shutdown_signal,
+    status::StatusFormat,
+    version::LONG_VERSION,
+};
 
-use std::error::Error;
+use tracing::level_filters::LevelFilter;
+
+use std::{error::Error, path::PathBuf, time::Duration};
+
+/// Run the ATDome mock controller.
+#[derive(Parser, Debug)]
+#[command(about, version = LONG_VERSION)]
+struct Args {
+    /// Address to bind the listener to.
+    #[arg(long, default_value = "127.0.0.1")]
+    host: String,
+
+    /// Port to listen for connections.
+    #[arg(short, long, default_value_t = 8887)]
+    port: usize,
+
+    /// Azimuth speed (deg/s).
+    #[arg(long, default_value_t = 2.4)]
+    az_speed: f32,
+
+    /// Main door speed (%/s).
+    #[arg(long, default_value_t = 100.0)]
+    main_door_speed: f32,
+
+    /// Dropout door speed (%/s).
+    #[arg(long, default_value_t = 50.0)]
+    dropout_door_speed: f32,
+
+    /// Initial azimuth of the dome (deg).
+    #[arg(long, default_value_t = 0.0)]
+    initial_az: f32,
+
+    /// Address to stream the simulated state over a WebSocket, e.g.
+    /// 127.0.0.1:8888.
+    #[arg(long)]
+    websocket_address: Option<String>,
+
+    /// Variant of the long status reply: with (long) or without (short)
+    /// the trailing homed line.
+    #[arg(long, value_enum, default_value_t = StatusFormat::Long)]
+    status_format: StatusFormat,
+
+    /// Prompt sent when the controller is ready for a command.
+    #[arg(long, default_value = ">")]
+    prompt: String,
+
+    /// Terminator of the reply lines.
+    #[arg(long, value_enum, default_value_t = LineTerminator::Lf)]
+    line_terminator: LineTerminator,
+
+    /// Write the long status reply in chunks of this size (bytes) to
+    /// exercise the client read accumulation.
+    #[arg(long)]
+    status_chunk_size: Option<usize>,
+
+    /// Delay between status reply chunks (ms).
+    #[arg(long, default_value_t = 10)]
+    status_chunk_delay: u64,
+
+    /// Text sent to new connections before the first prompt.
+    #[arg(long, default_value = "")]
+    banner: String,
+
+    /// Firmware version reported after the banner.
+    #[arg(long)]
+    firmware_version: Option<String>,
+
+    /// Address of the side-channel control port for integration tests,
+    /// e.g. 127.0.0.1:8889.
+    #[arg(long)]
+    control_address: Option<String>,
+
+    /// JSON file to load the simulated state from on startup and save it
+    /// to on shutdown.
+    #[arg(long)]
+    state_file: Option<PathBuf>,
+
+    /// Half width of the jitter added to the reported azimuth (deg).
+    #[arg(long, default_value_t = 0.0)]
+    az_jitter: f32,
+
+    /// Rate the reported azimuth drifts away from the simulated one
+    /// (deg/s).
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    az_drift_rate: f32,
+
+    /// Seed of the azimuth jitter.
+    #[arg(long, default_value_t = 0)]
+    noise_seed: u64,
+
+    /// Drop a client that sent nothing for this long (s), like the
+    /// controller does.
+    #[arg(long)]
+    idle_timeout: Option<f64>,
+
+    /// Log level (error, warn, info, debug or trace).
+    #[arg(long, default_value = "info")]
+    log_level: LevelFilter,
+
+    /// Log output format.
+    #[arg(long, value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+}
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-    run_mock_controller(8887).await?;
+    let args = Args::parse();
+
+    init_logging(LogLevels::new(args.log_level), args.log_format);
+
+    let config = MockControllerConfig {
+        host: args.host,
+        port: args.port,
+        az_speed: args.az_speed,
+        main_door_speed: args.main_door_speed,
+        dropout_door_speed: args.dropout_door_speed,
+        initial_az: args.initial_az,
+        websocket_address: args.websocket_address,
+        status_format: args.status_format,
+        prompt: args.prompt,
+        line_terminator: args.line_terminator,
+        status_chunking: args.status_chunk_size.map(|chunk_size| StatusChunking {
+            chunk_size,
+            delay: Duration::from_millis(args.status_chunk_delay),
+        }),
+        banner: args.banner,
+        firmware_version: args.firmware_version,
+        control_address: args.control_address,
+        state_file: args.state_file,
+        az_jitter: args.az_jitter,
+        az_drift_rate: args.az_drift_rate,
+        noise_seed: args.noise_seed,
+        idle_timeout: args.idle_timeout.map(Duration::from_secs_f64),
+    };
+
+    run_mock_controller_with_shutdown(config, wait_for_shutdown_signal()).await?;
 
     Ok(())
 }
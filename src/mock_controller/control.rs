@@ -0,0 +1,186 @@
+//! Side-channel control of the mock controller for integration tests.
+//!
+//! A test harness connects to the control port and sends one command per
+//! line to mutate the simulated state while the system under test talks to
+//! the main port, e.g. "estop on", "az 120.5", "scb off", "homed on",
+//! "main 50", "dropout 100" or "sensor 1". Each line is answered with "ok" or
+//! "error: <reason>".
+
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::TcpListener,
+    sync::mpsc,
+};
+
+use crate::{
+    error::{ATDomeError, ATDomeResult},
+    move_code::MoveCode,
+    status::Status,
+};
+
+/// Change to apply to the simulated state.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MockControl {
+    /// Trip or reset the emergency stop.
+    SetEstop(bool),
+    /// Jump the azimuth to a new position (deg), without moving.
+    SetAzimuth(f32),
+    /// Bring the shutter control board link up or down.
+    SetScbLink(bool),
+    /// Set whether the azimuth is homed.
+    SetHomed(bool),
+    /// Jump the main door to a new opening (%).
+    SetMainDoor(f32),
+    /// Jump the dropout door to a new opening (%).
+    SetDropoutDoor(f32),
+    /// Set the weather sensor code; 0 means no sensor tripped.
+    SetSensorCode(usize),
+}
+
+impl MockControl {
+    /// Parse a control line.
+    pub fn parse(line: &str) -> ATDomeResult<MockControl> {
+        let mut words = line.split_whitespace();
+        let (Some(name), Some(value), None) = (words.next(), words.next(), words.next()) else {
+            return Err(ATDomeError::Parse(format!(
+                "Expected '<name> <value>', got {line:?}."
+            )));
+        };
+        match name {
+            "estop" => Ok(MockControl::SetEstop(parse_flag(value)?)),
+            "az" => Ok(MockControl::SetAzimuth(parse_number(value)?)),
+            "scb" => Ok(MockControl::SetScbLink(parse_flag(value)?)),
+            "homed" => Ok(MockControl::SetHomed(parse_flag(value)?)),
+            "main" => Ok(MockControl::SetMainDoor(parse_number(value)?)),
+            "dropout" => Ok(MockControl::SetDropoutDoor(parse_number(value)?)),
+            "sensor" => Ok(MockControl::SetSensorCode(value.parse().map_err(|_| {
+                ATDomeError::Parse(format!("Invalid sensor code {value:?}."))
+            })?)),
+            _ => Err(ATDomeError::Parse(format!(
+                "Unknown control command {name:?}."
+            ))),
+        }
+    }
+
+    /// Apply the change to the simulated status.
+    pub fn apply(&self, status: &mut Status) {
+        match *self {
+            MockControl::SetEstop(active) => {
+                status.estop_active = active;
+                if active {
+                    // The controller stops all motion when the e-stop trips.
+                    status.move_code = MoveCode::EStop.byte_value();
+                    status.last_azimuth_goto = status.az_pos;
+                } else {
+                    status.move_code &= !MoveCode::EStop.byte_value();
+                }
+            }
+            MockControl::SetAzimuth(az_pos) => {
+                status.az_pos = az_pos;
+                status.last_azimuth_goto = az_pos;
+            }
+            MockControl::SetScbLink(scb_link_ok) => status.scb_link_ok = scb_link_ok,
+            MockControl::SetHomed(homed) => status.homed = homed,
+            MockControl::SetMainDoor(main_door_pct) => status.main_door_pct = main_door_pct,
+            MockControl::SetDropoutDoor(dropout_door_pct) => {
+                status.dropout_door_pct = dropout_door_pct
+            }
+            MockControl::SetSensorCode(sensor_code) => status.sensor_code = sensor_code,
+        }
+    }
+}
+
+fn parse_flag(value: &str) -> ATDomeResult<bool> {
+    match value {
+        "on" | "1" | "true" => Ok(true),
+        "off" | "0" | "false" => Ok(false),
+        _ => Err(ATDomeError::Parse(format!("Invalid flag {value:?}."))),
+    }
+}
+
+fn parse_number(value: &str) -> ATDomeResult<f32> {
+    value
+        .parse()
+        .map_err(|_| ATDomeError::Parse(format!("Invalid number {value:?}.")))
+}
+
+/// Accept control connections on `address` and forward the parsed
+/// commands to the simulation loop.
+pub async fn serve_control(sender: mpsc::Sender<MockControl>, address: &str) -> ATDomeResult<()> {
+    let listener = TcpListener::bind(address).await?;
+    tracing::info!("Serving mock controller control port on {address}.");
+    loop {
+        let (socket, _) = listener.accept().await?;
+        let sender = sender.clone();
+        tokio::spawn(async move {
+            let (reader, mut writer) = socket.into_split();
+            let mut lines = BufReader::new(reader).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                let reply = match MockControl::parse(&line) {
+                    Ok(control) => {
+                        tracing::info!(?control, "Applying control command.");
+                        if sender.send(control).await.is_err() {
+                            break;
+                        }
+                        "ok\n".to_owned()
+                    }
+                    Err(error) => format!("error: {}\n", error.get_error_message()),
+                };
+                if writer.write_all(reply.as_bytes()).await.is_err() {
+                    break;
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            MockControl::parse("estop on").unwrap(),
+            MockControl::SetEstop(true)
+        );
+        assert_eq!(
+            MockControl::parse("az 120.5").unwrap(),
+            MockControl::SetAzimuth(120.5)
+        );
+        assert_eq!(
+            MockControl::parse("scb off").unwrap(),
+            MockControl::SetScbLink(false)
+        );
+        assert_eq!(
+            MockControl::parse("sensor 3").unwrap(),
+            MockControl::SetSensorCode(3)
+        );
+        assert!(MockControl::parse("sensor -1").is_err());
+        assert!(MockControl::parse("az").is_err());
+        assert!(MockControl::parse("az north").is_err());
+        assert!(MockControl::parse("spin 10").is_err());
+    }
+
+    #[test]
+    fn test_apply_estop() {
+        let mut status = Status {
+            az_pos: 10.0,
+            last_azimuth_goto: 90.0,
+            move_code: MoveCode::AzimuthPositive.byte_value(),
+            ..Default::default()
+        };
+
+        MockControl::SetEstop(true).apply(&mut status);
+
+        assert!(status.estop_active);
+        assert!(MoveCode::is_estopped(status.move_code));
+        assert!(!MoveCode::is_azimuth_moving(status.move_code));
+        assert_eq!(status.last_azimuth_goto, 10.0);
+
+        MockControl::SetEstop(false).apply(&mut status);
+
+        assert!(!status.estop_active);
+        assert_eq!(status.move_code, 0);
+    }
+}
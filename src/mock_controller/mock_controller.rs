@@ -2,147 +2,718 @@
 
 use crate::atdome_model::ATDomeReply;
 use crate::error::ATDomeError;
+use crate::mock_controller::control::{serve_control, MockControl};
+use crate::mock_controller::noise::AzimuthNoise;
+use crate::mock_controller::websocket::{serve_state_stream, MockState};
 use crate::move_code::MoveCode;
 use crate::{
-    atdome_cmd_regex::ATDomeCmdRegex, atdome_model::ATDomeCmd, error::ATDomeResult, status::Status,
+    atdome_cmd_regex::{ATDomeCmdRegex, CmdParseError},
+    atdome_model::ATDomeCmd,
+    azimuth::wrap_azimuth,
+    error::ATDomeResult,
+    status::{Status, StatusFormat},
 };
-use std::str;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+#[cfg(feature = "cli")]
+use clap::ValueEnum;
+use std::{
+    future::{self, Future},
+    path::{Path, PathBuf},
+    str,
+};
+use tokio::io::{AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use tokio::sync::mpsc::error::TryRecvError;
-use tokio::sync::{mpsc, oneshot};
-use tokio::time::{sleep, Duration};
-use tokio::{net::TcpListener, task};
+use tokio::sync::{mpsc, oneshot, watch};
+use tokio::time::{sleep, timeout, Duration, Instant};
+use tokio::{fs, net::TcpListener};
+
+/// Reply to motion commands while the emergency stop is active.
+const ESTOP_ACTIVE_REPLY: &str = "Emergency Stop Active, command ignored";
+
+/// Reply to shutter commands while the link to the shutter control board
+/// is down.
+const SCB_LINK_DOWN_REPLY: &str = "Top Comm Link down, command ignored";
+
+/// Period of the simulation loop.
+const CYCLE_TIME: Duration = Duration::from_millis(50);
+
+/// Configuration of the mock controller.
+#[derive(Debug, Clone)]
+pub struct MockControllerConfig {
+    /// Address to bind the listener to.
+    pub host: String,
+    /// Port to listen for connections.
+    pub port: usize,
+    /// Azimuth speed (deg/s).
+    pub az_speed: f32,
+    /// Main door speed (%/s).
+    pub main_door_speed: f32,
+    /// Dropout door speed (%/s).
+    pub dropout_door_speed: f32,
+    /// Azimuth of the dome when the mock starts (deg).
+    pub initial_az: f32,
+    /// Address to stream the simulated state over a WebSocket, e.g.
+    /// "127.0.0.1:8888"; the state is not streamed if not set.
+    pub websocket_address: Option<String>,
+    /// Variant of the long status reply to send.
+    pub status_format: StatusFormat,
+    /// Prompt sent when the controller is ready for a command.
+    pub prompt: String,
+    /// Terminator of the reply lines.
+    pub line_terminator: LineTerminator,
+    /// Write the long status reply in chunks with delays between them;
+    /// the reply is written at once if not set.
+    pub status_chunking: Option<StatusChunking>,
+    /// Text sent to new connections before the first prompt.
+    pub banner: String,
+    /// Firmware version reported after the banner; no firmware line is
+    /// sent if not set.
+    pub firmware_version: Option<String>,
+    /// Address of the side-channel control port used by integration tests
+    /// to mutate the simulated state, e.g. "127.0.0.1:8889"; not served if
+    /// not set.
+    pub control_address: Option<String>,
+    /// JSON file the simulated status is loaded from on startup and saved
+    /// to on shutdown, so the dome keeps its position and door state across
+    /// restarts; when the file exists `initial_az` is ignored.
+    pub state_file: Option<PathBuf>,
+    /// Half width of the uniform jitter added to the reported azimuth and
+    /// encoder counts (deg); the simulated motion is not affected.
+    pub az_jitter: f32,
+    /// Rate the reported azimuth drifts away from the simulated one
+    /// (deg/s).
+    pub az_drift_rate: f32,
+    /// Seed of the jitter, so a noisy run can be reproduced.
+    pub noise_seed: u64,
+    /// Drop a client that sent nothing for this long, like the controller
+    /// does; clients are never dropped if not set.
+    pub idle_timeout: Option<Duration>,
+}
+
+impl Default for MockControllerConfig {
+    fn default() -> MockControllerConfig {
+        MockControllerConfig {
+            host: "127.0.0.1".to_owned(),
+            port: 8887,
+            az_speed: 2.4,
+            main_door_speed: 100.0,
+            dropout_door_speed: 50.0,
+            initial_az: 0.0,
+            websocket_address: None,
+            status_format: StatusFormat::default(),
+            prompt: ">".to_owned(),
+            line_terminator: LineTerminator::default(),
+            status_chunking: None,
+            banner: String::new(),
+            firmware_version: None,
+            control_address: None,
+            state_file: None,
+            az_jitter: 0.0,
+            az_drift_rate: 0.0,
+            noise_seed: 0,
+            idle_timeout: None,
+        }
+    }
+}
+
+/// Line terminator used in the replies of the controller.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(ValueEnum))]
+pub enum LineTerminator {
+    /// "\n"
+    #[default]
+    Lf,
+    /// "\r\n"
+    CrLf,
+}
+
+impl LineTerminator {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LineTerminator::Lf => "\n",
+            LineTerminator::CrLf => "\r\n",
+        }
+    }
+}
+
+/// Split a reply in chunks written with a delay between them.
+///
+/// Forces the client to accumulate the reply over several reads.
+#[derive(Debug, Clone, Copy)]
+pub struct StatusChunking {
+    /// Size of each chunk (bytes).
+    pub chunk_size: usize,
+    /// Delay between chunks.
+    pub delay: Duration,
+}
 
 struct MockControllerCmd {
     pub atdome_cmd: ATDomeCmd,
     pub tx: oneshot::Sender<ATDomeReply>,
 }
 
-pub async fn run_mock_controller(port: usize) -> ATDomeResult<()> {
-    let listener = TcpListener::bind(&format!("127.0.0.1:{port}")).await?;
+/// Run the mock controller until the process exits.
+pub async fn run_mock_controller(config: MockControllerConfig) -> ATDomeResult<()> {
+    run_mock_controller_with_shutdown(config, future::pending()).await
+}
+
+/// Run the mock controller until `shutdown` completes.
+///
+/// When `shutdown` completes the listener is closed and the simulation
+/// task is joined before returning.
+pub async fn run_mock_controller_with_shutdown(
+    config: MockControllerConfig,
+    shutdown: impl Future<Output = ()>,
+) -> ATDomeResult<()> {
+    let listener = TcpListener::bind(&format!("{}:{}", config.host, config.port)).await?;
+    run_mock_controller_on(listener, config, shutdown).await
+}
+
+/// Run the mock controller on an already bound `listener` until `shutdown`
+/// completes.
+///
+/// Binding first lets the caller know the mock accepts connections (and
+/// which port it got) before starting clients; `host` and `port` of the
+/// configuration are ignored.
+pub async fn run_mock_controller_on(
+    listener: TcpListener,
+    config: MockControllerConfig,
+    shutdown: impl Future<Output = ()>,
+) -> ATDomeResult<()> {
+    let (state_sender, _) = watch::channel(MockState::default());
+    let (control_tx, control_rx) = mpsc::channel::<MockControl>(100);
+    serve_mock_controller(
+        listener,
+        config,
+        shutdown,
+        state_sender,
+        control_tx,
+        control_rx,
+    )
+    .await
+}
+
+/// Run the mock controller on `listener` until `shutdown` completes.
+///
+/// The simulated state is published on `state_sender` and the changes
+/// received on `control_rx` are applied to it, in addition to the optional
+/// WebSocket and control ports of the configuration.
+pub(crate) async fn serve_mock_controller(
+    listener: TcpListener,
+    config: MockControllerConfig,
+    shutdown: impl Future<Output = ()>,
+    state_sender: watch::Sender<MockState>,
+    control_tx: mpsc::Sender<MockControl>,
+    mut control_rx: mpsc::Receiver<MockControl>,
+) -> ATDomeResult<()> {
     let (tx, mut rx) = mpsc::channel::<MockControllerCmd>(100);
 
-    tokio::spawn(async move {
-        let mut status = Status::default();
-        status.scb_link_ok = true;
-        status.high_speed = 6.0;
-        status.main_door_encoder_closed = 118449181478;
-        status.main_door_encoder_opened = 8287616388;
-        status.dropout_door_encoder_closed = 5669776578;
-        status.dropout_door_encoder_opened = 5710996184;
-
-        // How much the dome can move per cycle.
-        // 1 cycle is equal to 50 milliseconds.
-        // This is equivalent to 6 deg/s.
-        let delta_az_per_cycle = 0.12;
-        // How much the main door can move per cycle (in %).
-        let main_door_move_speed = 5;
-        // How much the dropout door can move per cycle (in %).
-        let dropout_door_move_speed = 2.5;
+    let websocket_task = config.websocket_address.clone().map(|websocket_address| {
+        let state_receiver = state_sender.subscribe();
+        tokio::spawn(async move { serve_state_stream(state_receiver, &websocket_address).await })
+    });
+
+    let control_task = config.control_address.clone().map(|control_address| {
+        tokio::spawn(async move { serve_control(control_tx, &control_address).await })
+    });
+
+    let saved_status = match &config.state_file {
+        Some(state_file) => load_state(state_file).await?,
+        None => None,
+    };
+
+    let serve_config = config.clone();
+    let state_file = config.state_file.clone();
+    let simulation_task = tokio::spawn(async move {
+        let mut status = saved_status.unwrap_or_else(|| initial_status(&config));
+        let mut noise =
+            AzimuthNoise::new(config.az_jitter, config.az_drift_rate, config.noise_seed);
+
+        // Motion is based on the time elapsed between updates rather than
+        // on the number of cycles, so tests can pause and advance the tokio
+        // clock to run long motions instantly.
+        let mut last_update = Instant::now();
 
         loop {
+            while let Ok(control) = control_rx.try_recv() {
+                control.apply(&mut status);
+            }
             match rx.try_recv() {
                 Ok(cmd) => {
-                    let _ = match cmd.atdome_cmd {
-                        ATDomeCmd::GetStatus => cmd.tx.send(ATDomeReply::Status(status)),
-                        ATDomeCmd::MoveAz(new_az) => {
-                            status.last_azimuth_goto = new_az;
-                            cmd.tx.send(ATDomeReply::None)
+                    let reply = match execute_command(&mut status, cmd.atdome_cmd) {
+                        ATDomeReply::Status(reported) => {
+                            ATDomeReply::Status(noise.apply(&reported))
                         }
-                        ATDomeCmd::StopMotion => {
-                            if status.last_azimuth_goto != status.az_pos {
-                                // This makes sure the dome "stops moving"
-                                // if it was moving before. It is just a way
-                                // to emulate the operation and does not have
-                                // any physics to it.
-                                status.last_azimuth_goto = status.az_pos;
-                                if status.move_code & MoveCode::AzimuthPositive.byte_value() > 0 {
-                                    status.move_code =
-                                        status.move_code ^ MoveCode::AzimuthPositive.byte_value();
-                                } else if status.move_code & MoveCode::AzimuthNegative.byte_value()
-                                    > 0
-                                {
-                                    status.move_code =
-                                        status.move_code ^ MoveCode::AzimuthNegative.byte_value();
-                                }
-                            }
-                            cmd.tx.send(ATDomeReply::None)
-                        }
-                        ATDomeCmd::OpenShutter => cmd.tx.send(ATDomeReply::None),
-                        ATDomeCmd::Unknown => cmd.tx.send(ATDomeReply::None),
-                        ATDomeCmd::HomeAzimuth => cmd.tx.send(ATDomeReply::None),
-                        ATDomeCmd::CloseShutter => cmd.tx.send(ATDomeReply::None),
-                        ATDomeCmd::OpenShutterMainDoor => cmd.tx.send(ATDomeReply::None),
-                        ATDomeCmd::CloseShutterMainDoor => cmd.tx.send(ATDomeReply::None),
-                        ATDomeCmd::OpenShutterDropoutDoor => cmd.tx.send(ATDomeReply::None),
-                        ATDomeCmd::CloseShutterDropoutDoor => cmd.tx.send(ATDomeReply::None),
+                        reply => reply,
                     };
+                    let _ = cmd.tx.send(reply);
                 }
                 Err(err) => match err {
                     TryRecvError::Empty => {}
-                    TryRecvError::Disconnected => break,
+                    TryRecvError::Disconnected => break status,
                 },
             };
-            // TODO Emulate behaviour here
-            if status.az_pos != status.last_azimuth_goto
-                && (status.move_code == 0
-                    || status.move_code == MoveCode::AzimuthPositive.byte_value()
-                    || status.move_code == MoveCode::AzimuthNegative.byte_value())
-            {
-                let delta_az = status.last_azimuth_goto - status.az_pos;
-                if delta_az.abs() > delta_az_per_cycle {
-                    if delta_az > 0.0 {
-                        if status.move_code == 0 {
-                            status.move_code =
-                                status.move_code ^ MoveCode::AzimuthPositive.byte_value();
-                        }
-                        status.az_pos += delta_az_per_cycle;
-                    } else {
-                        if status.move_code == 0 {
-                            status.move_code =
-                                status.move_code ^ MoveCode::AzimuthNegative.byte_value();
-                        }
-                        status.az_pos -= delta_az_per_cycle;
-                    }
-                } else {
-                    if status.move_code & MoveCode::AzimuthPositive.byte_value() > 0 {
-                        status.move_code =
-                            status.move_code ^ MoveCode::AzimuthPositive.byte_value();
-                    } else if status.move_code & MoveCode::AzimuthNegative.byte_value() > 0 {
-                        status.move_code =
-                            status.move_code ^ MoveCode::AzimuthNegative.byte_value();
-                    }
-                    status.move_code = 0;
-                    status.az_pos = status.last_azimuth_goto;
+            let now = Instant::now();
+            let elapsed = now.duration_since(last_update).as_secs_f32();
+            last_update = now;
+            update_doors(
+                &mut status,
+                config.main_door_speed * elapsed,
+                config.dropout_door_speed * elapsed,
+            );
+            update_homing(&mut status, config.az_speed * elapsed);
+            update_azimuth(&mut status, config.az_speed * elapsed);
+            noise.advance(elapsed);
+            state_sender.send_if_modified(|state| {
+                let new_state = MockState::from(&status);
+                let modified = *state != new_state;
+                *state = new_state;
+                modified
+            });
+            // Then sleep until the next cycle
+            sleep(CYCLE_TIME).await;
+        }
+    });
+
+    let result = tokio::select! {
+        result = serve(listener, tx, serve_config) => result,
+        _ = shutdown => {
+            tracing::info!("Shutting down mock controller.");
+            Ok(())
+        }
+    };
+
+    for background_task in [websocket_task, control_task].into_iter().flatten() {
+        background_task.abort();
+    }
+
+    // The listener and the command sender are dropped at this point, which
+    // stops the simulation loop.
+    match simulation_task.await {
+        Ok(status) => {
+            if let Some(state_file) = &state_file {
+                if let Err(error) = save_state(state_file, &status).await {
+                    tracing::error!("Failed to save mock state to {state_file:?}: {error:?}");
                 }
             }
-            // Then sleep for 50 milliseconds
-            sleep(Duration::from_millis(50)).await;
         }
-    });
+        Err(error) => tracing::error!("Simulation task failed: {error:?}"),
+    }
+
+    result
+}
+
+/// What the mock does with a chunk of bytes received from a client.
+#[derive(Debug, PartialEq)]
+pub enum ClientInput {
+    /// A command to execute.
+    Command(ATDomeCmd),
+    /// Text that is not a command; the client gets the error.
+    Invalid(CmdParseError),
+    /// An empty line or bytes that are not UTF-8; only a new prompt is sent.
+    Ignored,
+}
+
+/// Decode a chunk of bytes received from a client.
+///
+/// Never panics, whatever the client sends.
+pub fn decode_client_input(atdome_cmd_regex: &ATDomeCmdRegex, data: &[u8]) -> ClientInput {
+    let Ok(text) = str::from_utf8(data) else {
+        return ClientInput::Ignored;
+    };
+    let text = text.trim_end_matches(['\r', '\n']);
+    match atdome_cmd_regex.into_atdome_cmd(text) {
+        Ok(atdome_cmd) => ClientInput::Command(atdome_cmd),
+        Err(_) if text.trim().is_empty() => ClientInput::Ignored,
+        Err(error) => ClientInput::Invalid(error),
+    }
+}
+
+/// Execute a command on the simulated controller and return the reply.
+pub fn execute_command(status: &mut Status, atdome_cmd: ATDomeCmd) -> ATDomeReply {
+    match atdome_cmd {
+        ATDomeCmd::GetStatus => ATDomeReply::Status(*status),
+        ATDomeCmd::MoveAz(_) if status.estop_active => {
+            ATDomeReply::Error(ESTOP_ACTIVE_REPLY.to_owned())
+        }
+        ATDomeCmd::MoveAz(new_az) => {
+            status.last_azimuth_goto = new_az;
+            ATDomeReply::None
+        }
+        ATDomeCmd::HomeAzimuth if status.estop_active => {
+            ATDomeReply::Error(ESTOP_ACTIVE_REPLY.to_owned())
+        }
+        ATDomeCmd::HomeAzimuth => {
+            // Rotate to the home switch, which the simulation puts at the
+            // home azimuth.
+            status.last_azimuth_goto = status.home_azimuth;
+            status.move_code |= MoveCode::AzimuthHoming.byte_value();
+            ATDomeReply::None
+        }
+        ATDomeCmd::OpenShutter
+        | ATDomeCmd::CloseShutter
+        | ATDomeCmd::OpenShutterMainDoor
+        | ATDomeCmd::CloseShutterMainDoor
+        | ATDomeCmd::OpenShutterDropoutDoor
+        | ATDomeCmd::CloseShutterDropoutDoor
+            if !status.scb_link_ok =>
+        {
+            ATDomeReply::Error(SCB_LINK_DOWN_REPLY.to_owned())
+        }
+        ATDomeCmd::StopMotion => {
+            status.move_code &= !MoveCode::AzimuthHoming.byte_value();
+            stop_door(&mut status.move_code, MAIN_DOOR);
+            stop_door(&mut status.move_code, DROPOUT_DOOR);
+            if status.last_azimuth_goto != status.az_pos {
+                // This makes sure the dome "stops moving" if it was moving
+                // before. It is just a way to emulate the operation and does
+                // not have any physics to it.
+                status.last_azimuth_goto = status.az_pos;
+                if MoveCode::AzimuthPositive.is_set(status.move_code) {
+                    status.move_code ^= MoveCode::AzimuthPositive.byte_value();
+                } else if MoveCode::AzimuthNegative.is_set(status.move_code) {
+                    status.move_code ^= MoveCode::AzimuthNegative.byte_value();
+                }
+            }
+            ATDomeReply::None
+        }
+        ATDomeCmd::OpenShutter
+        | ATDomeCmd::CloseShutter
+        | ATDomeCmd::OpenShutterMainDoor
+        | ATDomeCmd::CloseShutterMainDoor
+        | ATDomeCmd::OpenShutterDropoutDoor
+        | ATDomeCmd::CloseShutterDropoutDoor
+            if status.estop_active =>
+        {
+            ATDomeReply::Error(ESTOP_ACTIVE_REPLY.to_owned())
+        }
+        ATDomeCmd::OpenShutter => {
+            start_door(status, MAIN_DOOR, true);
+            start_door(status, DROPOUT_DOOR, true);
+            ATDomeReply::None
+        }
+        ATDomeCmd::CloseShutter => {
+            start_door(status, MAIN_DOOR, false);
+            start_door(status, DROPOUT_DOOR, false);
+            ATDomeReply::None
+        }
+        ATDomeCmd::OpenShutterMainDoor => {
+            start_door(status, MAIN_DOOR, true);
+            ATDomeReply::None
+        }
+        ATDomeCmd::CloseShutterMainDoor => {
+            start_door(status, MAIN_DOOR, false);
+            ATDomeReply::None
+        }
+        ATDomeCmd::OpenShutterDropoutDoor => {
+            start_door(status, DROPOUT_DOOR, true);
+            ATDomeReply::None
+        }
+        ATDomeCmd::CloseShutterDropoutDoor => {
+            start_door(status, DROPOUT_DOOR, false);
+            ATDomeReply::None
+        }
+        ATDomeCmd::SetHomeAzimuth(home_azimuth) => {
+            status.home_azimuth = home_azimuth;
+            ATDomeReply::None
+        }
+        ATDomeCmd::SetTolerance(tolerance) => {
+            status.tolerance = tolerance;
+            ATDomeReply::None
+        }
+        ATDomeCmd::SetCoast(coast) => {
+            status.coast = coast;
+            ATDomeReply::None
+        }
+        ATDomeCmd::SetHighSpeed(high_speed) => {
+            status.high_speed = high_speed;
+            ATDomeReply::None
+        }
+        ATDomeCmd::SetWatchdogTimer(watchdog_timer) => {
+            status.watchdog_timer = watchdog_timer;
+            ATDomeReply::None
+        }
+        ATDomeCmd::SetAzimuthMoveTimeout(timeout) => {
+            status.azimuth_move_timeout = timeout;
+            ATDomeReply::None
+        }
+        ATDomeCmd::SetDoorMoveTimeout(timeout) => {
+            status.door_move_timeout = timeout;
+            ATDomeReply::None
+        }
+        ATDomeCmd::SetRainSensorEnabled(enabled) => {
+            status.rain_sensor_enabled = enabled;
+            ATDomeReply::None
+        }
+        ATDomeCmd::SetCloudSensorEnabled(enabled) => {
+            status.cloud_sensor_enabled = enabled;
+            ATDomeReply::None
+        }
+        ATDomeCmd::SetAutoShutdownEnabled(enabled) => {
+            status.auto_shutdown_enabled = enabled;
+            ATDomeReply::None
+        }
+        ATDomeCmd::Raw(_) => ATDomeReply::None,
+        ATDomeCmd::Unknown => ATDomeReply::None,
+    }
+}
+
+/// Rotate a homing azimuth toward the home switch by at most `max_step`
+/// (deg); once there the azimuth is homed and the homing flag cleared.
+pub fn update_homing(status: &mut Status, max_step: f32) {
+    if !MoveCode::is_homing(status.move_code) || MoveCode::EStop.is_set(status.move_code) {
+        return;
+    }
+    let delta_az = status.azimuth_error();
+    if delta_az.abs() > max_step {
+        status.az_pos = wrap_azimuth(status.az_pos + max_step.copysign(delta_az));
+    } else {
+        status.az_pos = status.last_azimuth_goto;
+        status.move_code &= !MoveCode::AzimuthHoming.byte_value();
+        status.homed = true;
+    }
+}
+
+/// Opening and closing flags of a door in the move code.
+#[derive(Debug, Clone, Copy)]
+struct DoorFlags {
+    opening: MoveCode,
+    closing: MoveCode,
+}
+
+const MAIN_DOOR: DoorFlags = DoorFlags {
+    opening: MoveCode::MainDoorOpening,
+    closing: MoveCode::MainDoorClosing,
+};
+
+const DROPOUT_DOOR: DoorFlags = DoorFlags {
+    opening: MoveCode::DropoutDoorOpening,
+    closing: MoveCode::DropoutDoorClosing,
+};
+
+/// Flag a door as opening or closing, reversing it if it was moving the
+/// other way.
+fn start_door(status: &mut Status, door: DoorFlags, open: bool) {
+    let (start, reverse) = if open {
+        (door.opening, door.closing)
+    } else {
+        (door.closing, door.opening)
+    };
+    status.move_code &= !reverse.byte_value();
+    status.move_code |= start.byte_value();
+}
+
+/// Clear the opening and closing flags of a door.
+fn stop_door(move_code: &mut u8, door: DoorFlags) {
+    *move_code &= !(door.opening.byte_value() | door.closing.byte_value());
+}
 
+/// Move a door flagged as opening or closing by at most `max_step` (%),
+/// clearing its flag once it is fully open or closed; return the new
+/// opening (%).
+fn step_door(move_code: &mut u8, door: DoorFlags, door_pct: f32, max_step: f32) -> f32 {
+    let target = if door.opening.is_set(*move_code) {
+        100.0
+    } else if door.closing.is_set(*move_code) {
+        0.0
+    } else {
+        return door_pct;
+    };
+    if (target - door_pct).abs() > max_step {
+        door_pct + max_step.copysign(target - door_pct)
+    } else {
+        stop_door(move_code, door);
+        target
+    }
+}
+
+/// Move the doors flagged in the move code by at most `main_step` and
+/// `dropout_step` (%), stopping them once fully open or closed.
+pub fn update_doors(status: &mut Status, main_step: f32, dropout_step: f32) {
+    if MoveCode::EStop.is_set(status.move_code) {
+        return;
+    }
+    status.main_door_pct = step_door(
+        &mut status.move_code,
+        MAIN_DOOR,
+        status.main_door_pct,
+        main_step,
+    );
+    status.dropout_door_pct = step_door(
+        &mut status.move_code,
+        DROPOUT_DOOR,
+        status.dropout_door_pct,
+        dropout_step,
+    );
+}
+
+/// Move the simulated azimuth toward the last commanded position by at most
+/// `max_step` (deg), updating the motion code.
+pub fn update_azimuth(status: &mut Status, max_step: f32) {
+    // Like the controller, take the shortest path, crossing 0 if needed.
+    let delta_az = status.azimuth_error();
+    // Only rotate if nothing else (doors, homing or emergency stop) is
+    // flagged in the move code.
+    if delta_az != 0.0
+        && (status.move_code == 0
+            || status.move_code == MoveCode::AzimuthPositive.byte_value()
+            || status.move_code == MoveCode::AzimuthNegative.byte_value())
+    {
+        if delta_az.abs() > max_step {
+            if delta_az > 0.0 {
+                if status.move_code == 0 {
+                    status.move_code ^= MoveCode::AzimuthPositive.byte_value();
+                }
+                status.az_pos = wrap_azimuth(status.az_pos + max_step);
+            } else {
+                if status.move_code == 0 {
+                    status.move_code ^= MoveCode::AzimuthNegative.byte_value();
+                }
+                status.az_pos = wrap_azimuth(status.az_pos - max_step);
+            }
+        } else {
+            if MoveCode::AzimuthPositive.is_set(status.move_code) {
+                status.move_code ^= MoveCode::AzimuthPositive.byte_value();
+            } else if MoveCode::AzimuthNegative.is_set(status.move_code) {
+                status.move_code ^= MoveCode::AzimuthNegative.byte_value();
+            }
+            status.move_code = 0;
+            status.az_pos = status.last_azimuth_goto;
+        }
+    }
+    if let Some(encoder_counts) = status.degrees_to_encoder_counts(status.az_pos) {
+        status.encoder_counts = encoder_counts;
+    }
+}
+
+/// Encoder counts per turn of the simulated azimuth drive; the encoder
+/// reads 0 at azimuth 0.
+const ENCODER_COUNTS_PER_360: u64 = 4018143232;
+
+/// Status of the simulated controller when no saved state is available.
+fn initial_status(config: &MockControllerConfig) -> Status {
+    Status {
+        scb_link_ok: true,
+        rain_sensor_enabled: true,
+        cloud_sensor_enabled: true,
+        high_speed: 6.0,
+        home_azimuth: 10.0,
+        coast: 0.5,
+        tolerance: 1.0,
+        azimuth_move_timeout: 120.0,
+        watchdog_timer: 600.0,
+        dropout_timer: 5.0,
+        reversal_delay: 4.0,
+        door_move_timeout: 360.0,
+        main_door_encoder_closed: 118449181478,
+        main_door_encoder_opened: 8287616388,
+        dropout_door_encoder_closed: 5669776578,
+        dropout_door_encoder_opened: 5710996184,
+        encoder_counts_per_360: ENCODER_COUNTS_PER_360,
+        az_pos: config.initial_az,
+        last_azimuth_goto: config.initial_az,
+        ..Default::default()
+    }
+}
+
+/// Load the simulated status saved by a previous run, if any.
+async fn load_state(state_file: &Path) -> ATDomeResult<Option<Status>> {
+    let contents = match fs::read_to_string(state_file).await {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(error) => return Err(ATDomeError::from(error)),
+    };
+    let status = serde_json::from_str(&contents).map_err(ATDomeError::from_error)?;
+    tracing::info!("Loaded mock state from {state_file:?}.");
+    Ok(Some(status))
+}
+
+/// Save the simulated status so the next run starts from it.
+async fn save_state(state_file: &Path, status: &Status) -> ATDomeResult<()> {
+    let contents = serde_json::to_string_pretty(status).map_err(ATDomeError::from_error)?;
+    fs::write(state_file, contents).await?;
+    tracing::info!("Saved mock state to {state_file:?}.");
+    Ok(())
+}
+
+/// Text sent to new connections, ending with the first prompt.
+fn welcome_message(config: &MockControllerConfig) -> String {
+    let mut welcome_message = config.banner.to_owned();
+    if let Some(firmware_version) = &config.firmware_version {
+        welcome_message.push_str(&format!(
+            "Firmware version: {firmware_version}{}",
+            config.line_terminator.as_str()
+        ));
+    }
+    welcome_message.push_str(&config.prompt);
+    welcome_message
+}
+
+/// Write a reply, in chunks if requested.
+async fn write_reply<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    reply: &[u8],
+    chunking: Option<StatusChunking>,
+) -> std::io::Result<()> {
+    let Some(chunking) = chunking else {
+        return writer.write_all(reply).await;
+    };
+    for (index, chunk) in reply.chunks(chunking.chunk_size.max(1)).enumerate() {
+        if index > 0 {
+            sleep(chunking.delay).await;
+        }
+        writer.write_all(chunk).await?;
+        writer.flush().await?;
+    }
+    Ok(())
+}
+
+/// Accept connections and respond to commands.
+async fn serve(
+    listener: TcpListener,
+    tx: mpsc::Sender<MockControllerCmd>,
+    config: MockControllerConfig,
+) -> ATDomeResult<()> {
+    let welcome_message = welcome_message(&config);
     let atdome_cmd_regex = ATDomeCmdRegex::new();
 
     loop {
-        let (mut socket, _) = listener.accept().await?;
+        let (mut socket, client_address) = listener.accept().await?;
 
         let mut buf = vec![0; 1024];
+        // Reused for the status replies of the connection.
+        let mut status_reply = String::with_capacity(1024);
 
-        // write prompt
-        socket.write_all(b">").await?;
+        // write the banner and the prompt
+        socket.write_all(welcome_message.as_bytes()).await?;
 
         loop {
-            match socket.read(&mut buf).await {
+            let read = socket.read(&mut buf);
+            let read = match config.idle_timeout {
+                Some(idle_timeout) => match timeout(idle_timeout, read).await {
+                    Ok(read) => read,
+                    Err(_) => {
+                        tracing::info!(
+                            "Dropping client {client_address}, silent for {idle_timeout:?}."
+                        );
+                        break;
+                    }
+                },
+                None => read.await,
+            };
+            match read {
                 // Return value of `Ok(0)` signifies that the remote has
                 // closed
                 Ok(0) => break,
                 Ok(n) => {
-                    if let Ok(cmd) = str::from_utf8(&buf[..n]) {
-                        let cmd_trimmed = cmd.trim_end_matches("\r\n");
-                        let atdome_cmd = atdome_cmd_regex.into_atdome_cmd(cmd_trimmed);
-                        if matches!(atdome_cmd, ATDomeCmd::Unknown) {
-                            println!("Unknown dome command: {cmd_trimmed}.");
-                        } else {
+                    match decode_client_input(&atdome_cmd_regex, &buf[..n]) {
+                        ClientInput::Ignored => {}
+                        ClientInput::Invalid(error) => {
+                            tracing::warn!("{error}.");
+                            let reply =
+                                format!("Error: {error}{}", config.line_terminator.as_str());
+                            let _ = socket.write_all(reply.as_bytes()).await;
+                        }
+                        ClientInput::Command(atdome_cmd) => {
                             let (mock_controller_tx, mock_controller_rx) = oneshot::channel();
                             let mock_controller_cmd = MockControllerCmd {
                                 atdome_cmd,
@@ -151,18 +722,33 @@ pub async fn run_mock_controller(port: usize) -> ATDomeResult<()> {
                             let _ = tx.send(mock_controller_cmd).await;
                             if let Ok(mock_controller_response) = mock_controller_rx.await {
                                 if let ATDomeReply::Status(status) = mock_controller_response {
-                                    let _ =
-                                        socket.write_all(&status.as_string().into_bytes()).await;
+                                    status_reply.clear();
+                                    let _ = status.write_with_format(
+                                        &mut status_reply,
+                                        config.status_format,
+                                        config.line_terminator.as_str(),
+                                    );
+                                    let _ = write_reply(
+                                        &mut socket,
+                                        status_reply.as_bytes(),
+                                        config.status_chunking,
+                                    )
+                                    .await;
+                                } else if let ATDomeReply::Error(message) = mock_controller_response
+                                {
+                                    let reply =
+                                        format!("{message}{}", config.line_terminator.as_str());
+                                    let _ = socket.write_all(reply.as_bytes()).await;
                                 }
                             } else {
-                                println!(
+                                tracing::error!(
                                     "Internal error when requesting response from controller loop."
                                 );
                                 break;
                             }
                         }
                     }
-                    if socket.write_all(b">").await.is_err() {
+                    if socket.write_all(config.prompt.as_bytes()).await.is_err() {
                         // Unexpected socket error. There isn't much we can
                         // do here so just stop processing.
                         return Ok(());
@@ -171,9 +757,312 @@ pub async fn run_mock_controller(port: usize) -> ATDomeResult<()> {
                 Err(error) => {
                     // Unexpected socket error. There isn't much we can do
                     // here so just stop processing.
-                    return Err(ATDomeError::new(&error.to_string()));
+                    return Err(ATDomeError::from(error));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_welcome_message_default() {
+        assert_eq!(welcome_message(&MockControllerConfig::default()), ">");
+    }
+
+    #[test]
+    fn test_welcome_message_with_firmware() {
+        let config = MockControllerConfig {
+            banner: "ATDome controller\r\n".to_owned(),
+            firmware_version: Some("2.1".to_owned()),
+            line_terminator: LineTerminator::CrLf,
+            prompt: "$ ".to_owned(),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            welcome_message(&config),
+            "ATDome controller\r\nFirmware version: 2.1\r\n$ "
+        );
+    }
+
+    #[test]
+    fn test_decode_client_input() {
+        let atdome_cmd_regex = ATDomeCmdRegex::new();
+
+        assert_eq!(
+            decode_client_input(&atdome_cmd_regex, b"12.5 MV\r\n"),
+            ClientInput::Command(ATDomeCmd::MoveAz(12.5))
+        );
+        assert_eq!(
+            decode_client_input(&atdome_cmd_regex, b"\r\n"),
+            ClientInput::Ignored
+        );
+        assert_eq!(
+            decode_client_input(&atdome_cmd_regex, b"\xff\xfe MV\r\n"),
+            ClientInput::Ignored
+        );
+        assert!(matches!(
+            decode_client_input(&atdome_cmd_regex, b"1e999 MV\r\n"),
+            ClientInput::Invalid(_)
+        ));
+    }
+
+    #[test]
+    fn test_shutter_commands_need_scb_link() {
+        let mut status = initial_status(&MockControllerConfig::default());
+        MockControl::SetScbLink(false).apply(&mut status);
+
+        assert!(status.as_string().contains("Top Comm Link OK:    0"));
+        assert!(matches!(
+            execute_command(&mut status, ATDomeCmd::OpenShutter),
+            ATDomeReply::Error(message) if message == SCB_LINK_DOWN_REPLY
+        ));
+        assert!(matches!(
+            execute_command(&mut status, ATDomeCmd::MoveAz(10.0)),
+            ATDomeReply::None
+        ));
+
+        MockControl::SetScbLink(true).apply(&mut status);
+        assert!(matches!(
+            execute_command(&mut status, ATDomeCmd::CloseShutterMainDoor),
+            ATDomeReply::None
+        ));
+    }
+
+    #[test]
+    fn test_update_azimuth() {
+        let mut status = Status {
+            last_azimuth_goto: 10.0,
+            ..Default::default()
+        };
+
+        update_azimuth(&mut status, 4.0);
+        assert_eq!(status.az_pos, 4.0);
+        assert!(MoveCode::AzimuthPositive.is_set(status.move_code));
+
+        update_azimuth(&mut status, 100.0);
+        assert_eq!(status.az_pos, 10.0);
+        assert_eq!(status.move_code, 0);
+
+        // Moving to 350 goes through 0 rather than the long way around.
+        status.last_azimuth_goto = 350.0;
+        update_azimuth(&mut status, 15.0);
+        assert_eq!(status.az_pos, 355.0);
+        assert!(MoveCode::AzimuthNegative.is_set(status.move_code));
+
+        // The encoder follows the azimuth.
+        status.encoder_counts_per_360 = ENCODER_COUNTS_PER_360;
+        update_azimuth(&mut status, 15.0);
+        assert!((status.encoder_azimuth().unwrap() - 350.0).abs() < 1e-3);
+
+        // Any other motion flag holds the azimuth.
+        status.last_azimuth_goto = 10.0;
+        for flag in [MoveCode::MainDoorOpening, MoveCode::EStop] {
+            status.move_code = flag.byte_value();
+            update_azimuth(&mut status, 15.0);
+            assert_eq!(status.az_pos, 350.0);
+            assert_eq!(status.move_code, flag.byte_value());
+        }
+    }
+
+    #[test]
+    fn test_update_doors() {
+        let mut status = initial_status(&MockControllerConfig::default());
+
+        execute_command(&mut status, ATDomeCmd::OpenShutter);
+        assert!(MoveCode::MainDoorOpening.is_set(status.move_code));
+        assert!(MoveCode::DropoutDoorOpening.is_set(status.move_code));
+
+        update_doors(&mut status, 60.0, 30.0);
+        assert_eq!(status.main_door_pct, 60.0);
+        assert_eq!(status.dropout_door_pct, 30.0);
+
+        // The main door stops once fully open, the dropout door goes on.
+        update_doors(&mut status, 60.0, 30.0);
+        assert_eq!(status.main_door_pct, 100.0);
+        assert_eq!(status.dropout_door_pct, 60.0);
+        assert_eq!(status.move_code, MoveCode::DropoutDoorOpening.byte_value());
+
+        // Closing reverses a moving door.
+        execute_command(&mut status, ATDomeCmd::CloseShutterDropoutDoor);
+        assert_eq!(status.move_code, MoveCode::DropoutDoorClosing.byte_value());
+        update_doors(&mut status, 60.0, 30.0);
+        assert_eq!(status.dropout_door_pct, 30.0);
+
+        // Stopping leaves the door where it is.
+        execute_command(&mut status, ATDomeCmd::StopMotion);
+        assert_eq!(status.move_code, 0);
+        update_doors(&mut status, 60.0, 30.0);
+        assert_eq!(status.dropout_door_pct, 30.0);
+
+        // The doors do not move, and cannot be started, during an
+        // emergency stop.
+        execute_command(&mut status, ATDomeCmd::CloseShutterMainDoor);
+        status.move_code |= MoveCode::EStop.byte_value();
+        update_doors(&mut status, 60.0, 30.0);
+        assert_eq!(status.main_door_pct, 100.0);
+        MockControl::SetEstop(true).apply(&mut status);
+        assert!(matches!(
+            execute_command(&mut status, ATDomeCmd::CloseShutter),
+            ATDomeReply::Error(message) if message == ESTOP_ACTIVE_REPLY
+        ));
+    }
+
+    #[test]
+    fn test_update_homing() {
+        let mut status = initial_status(&MockControllerConfig::default());
+        assert!(!status.homed);
+        assert_eq!(status.home_azimuth, 10.0);
+
+        execute_command(&mut status, ATDomeCmd::HomeAzimuth);
+        assert!(MoveCode::is_homing(status.move_code));
+
+        update_homing(&mut status, 6.0);
+        update_azimuth(&mut status, 6.0);
+        assert_eq!(status.az_pos, 6.0);
+        assert!(!status.homed);
+        assert!(MoveCode::is_homing(status.move_code));
+
+        update_homing(&mut status, 6.0);
+        assert_eq!(status.az_pos, 10.0);
+        assert!(status.homed);
+        assert_eq!(status.move_code, 0);
+
+        // Stopping aborts the homing.
+        status.homed = false;
+        execute_command(&mut status, ATDomeCmd::MoveAz(30.0));
+        update_azimuth(&mut status, 100.0);
+        execute_command(&mut status, ATDomeCmd::HomeAzimuth);
+        update_homing(&mut status, 6.0);
+        execute_command(&mut status, ATDomeCmd::StopMotion);
+        update_homing(&mut status, 6.0);
+        assert_eq!(status.az_pos, 24.0);
+        assert_eq!(status.move_code, 0);
+        assert!(!status.homed);
+
+        MockControl::SetEstop(true).apply(&mut status);
+        assert!(matches!(
+            execute_command(&mut status, ATDomeCmd::HomeAzimuth),
+            ATDomeReply::Error(message) if message == ESTOP_ACTIVE_REPLY
+        ));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_motion_follows_tokio_time() {
+        let state_file =
+            std::env::temp_dir().join(format!("atdome-mock-motion-{}.json", std::process::id()));
+        let status = Status {
+            last_azimuth_goto: 90.0,
+            ..initial_status(&MockControllerConfig::default())
+        };
+        save_state(&state_file, &status).await.unwrap();
+
+        let config = MockControllerConfig {
+            port: 0,
+            state_file: Some(state_file.clone()),
+            ..Default::default()
+        };
+        let (shutdown_sender, shutdown_receiver) = oneshot::channel::<()>();
+        let mock = tokio::spawn(run_mock_controller_with_shutdown(config, async {
+            let _ = shutdown_receiver.await;
+        }));
+
+        // 90 deg at 2.4 deg/s takes 37.5 s of (paused) tokio time.
+        sleep(Duration::from_secs(60)).await;
+        shutdown_sender.send(()).unwrap();
+        mock.await.unwrap().unwrap();
+
+        let status = load_state(&state_file).await.unwrap().unwrap();
+        std::fs::remove_file(&state_file).unwrap();
+        assert_eq!(status.az_pos, 90.0);
+        assert_eq!(status.move_code, 0);
+    }
+
+    #[tokio::test]
+    async fn test_idle_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let config = MockControllerConfig {
+            idle_timeout: Some(Duration::from_millis(300)),
+            ..Default::default()
+        };
+        let (shutdown_sender, shutdown_receiver) = oneshot::channel::<()>();
+        let mock = tokio::spawn(run_mock_controller_on(listener, config, async {
+            let _ = shutdown_receiver.await;
+        }));
+
+        let mut client = tokio::net::TcpStream::connect(address).await.unwrap();
+        let mut buf = vec![0; 1024];
+        assert!(client.read(&mut buf).await.unwrap() > 0);
+
+        // Commands keep the connection open past the timeout.
+        for _ in 0..3 {
+            sleep(Duration::from_millis(150)).await;
+            client.write_all(b"+\n").await.unwrap();
+            loop {
+                let n = client.read(&mut buf).await.unwrap();
+                assert!(n > 0);
+                if buf[..n].ends_with(b">") {
+                    break;
                 }
             }
         }
+
+        // Then the silent client is dropped.
+        let read = timeout(Duration::from_secs(2), client.read(&mut buf)).await;
+        assert_eq!(read.unwrap().unwrap(), 0);
+
+        // The next client is served.
+        let mut client = tokio::net::TcpStream::connect(address).await.unwrap();
+        assert!(client.read(&mut buf).await.unwrap() > 0);
+
+        shutdown_sender.send(()).unwrap();
+        mock.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_save_and_load_state() {
+        let state_file =
+            std::env::temp_dir().join(format!("atdome-mock-state-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&state_file);
+        assert!(load_state(&state_file).await.unwrap().is_none());
+
+        let status = Status {
+            az_pos: 123.5,
+            main_door_pct: 40.0,
+            homed: true,
+            ..initial_status(&MockControllerConfig::default())
+        };
+        save_state(&state_file, &status).await.unwrap();
+        let loaded = load_state(&state_file).await.unwrap().unwrap();
+        std::fs::remove_file(&state_file).unwrap();
+
+        assert_eq!(loaded.az_pos, 123.5);
+        assert_eq!(loaded.main_door_pct, 40.0);
+        assert!(loaded.homed);
+        assert!(loaded.scb_link_ok);
+    }
+
+    #[tokio::test]
+    async fn test_write_reply_chunked() {
+        let (mut writer, mut reader) = tokio::io::duplex(1024);
+        let reply = Status::default().as_string();
+        let chunking = StatusChunking {
+            chunk_size: 16,
+            delay: Duration::from_millis(1),
+        };
+
+        write_reply(&mut writer, reply.as_bytes(), Some(chunking))
+            .await
+            .unwrap();
+        drop(writer);
+
+        let mut received = String::new();
+        reader.read_to_string(&mut received).await.unwrap();
+        assert_eq!(received, reply);
     }
 }
@@ -1 +1,4 @@
+pub mod control;
 pub mod mock_controller;
+pub mod noise;
+pub mod websocket;
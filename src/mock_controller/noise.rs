@@ -0,0 +1,128 @@
+//! Sensor noise of the mock controller.
+//!
+//! The simulated dome moves exactly; only the azimuth and encoder counts in
+//! the status replies are perturbed, the way a real encoder reads, so the
+//! deadbands and in-position hysteresis of the CSC can be exercised without
+//! the mock chasing its own noise.
+
+use crate::{azimuth::wrap_azimuth, status::Status};
+
+/// Jitter and drift added to the reported azimuth.
+#[derive(Debug, Clone)]
+pub struct AzimuthNoise {
+    /// Half width of the uniform jitter (deg).
+    jitter: f32,
+    /// Rate the reported azimuth drifts away from the true one (deg/s).
+    drift_rate: f32,
+    /// Drift accumulated so far (deg).
+    drift: f32,
+    /// State of the random number generator.
+    rng_state: u64,
+}
+
+impl AzimuthNoise {
+    /// Noise with a jitter of up to `jitter` (deg) on either side and a
+    /// drift of `drift_rate` (deg/s); the same `seed` gives the same noise.
+    pub fn new(jitter: f32, drift_rate: f32, seed: u64) -> AzimuthNoise {
+        AzimuthNoise {
+            jitter: jitter.abs(),
+            drift_rate,
+            drift: 0.0,
+            rng_state: seed,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.jitter > 0.0 || self.drift_rate != 0.0
+    }
+
+    /// Accumulate the drift over `elapsed` (s).
+    pub fn advance(&mut self, elapsed: f32) {
+        self.drift += self.drift_rate * elapsed;
+    }
+
+    /// Copy of `status` with the noise added to the azimuth and the encoder
+    /// counts.
+    pub fn apply(&mut self, status: &Status) -> Status {
+        let mut reported = *status;
+        if !self.is_enabled() {
+            return reported;
+        }
+        let jitter = self.jitter * self.next_unit();
+        reported.az_pos = wrap_azimuth(status.az_pos + self.drift + jitter);
+        if let Some(encoder_counts) = reported.degrees_to_encoder_counts(reported.az_pos) {
+            reported.encoder_counts = encoder_counts;
+        }
+        reported
+    }
+
+    /// Uniform random number in [-1, 1), from a splitmix64 generator.
+    fn next_unit(&mut self) -> f32 {
+        self.rng_state = self.rng_state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.rng_state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        // The top 24 bits fit exactly in an f32.
+        (z >> 40) as f32 / (1u64 << 23) as f32 - 1.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn still_status(az_pos: f32) -> Status {
+        Status {
+            az_pos,
+            last_azimuth_goto: az_pos,
+            encoder_counts_per_360: 4018143232,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_jitter() {
+        let status = still_status(0.5);
+        let mut noise = AzimuthNoise::new(0.1, 0.0, 42);
+        let mut same_seed = AzimuthNoise::new(0.1, 0.0, 42);
+
+        let reported: Vec<Status> = (0..1000).map(|_| noise.apply(&status)).collect();
+
+        for reported in &reported {
+            let error = (reported.az_pos - 0.5).abs();
+            assert!(error <= 0.1 + 1e-6, "{}", reported.az_pos);
+            assert_eq!(
+                Some(reported.encoder_counts),
+                reported.degrees_to_encoder_counts(reported.az_pos)
+            );
+            assert_eq!(reported.az_pos, same_seed.apply(&status).az_pos);
+        }
+        assert!(reported.iter().any(|reported| reported.az_pos > 0.55));
+        assert!(reported.iter().any(|reported| reported.az_pos < 0.45));
+        // The true position is not affected.
+        assert_eq!(status.az_pos, 0.5);
+    }
+
+    #[test]
+    fn test_drift() {
+        let status = still_status(359.9);
+        let mut noise = AzimuthNoise::new(0.0, 0.01, 1);
+
+        noise.advance(20.0);
+
+        assert!((noise.apply(&status).az_pos - 0.1).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_disabled() {
+        let status = still_status(10.0);
+        let mut noise = AzimuthNoise::new(0.0, 0.0, 1);
+        noise.advance(100.0);
+
+        assert!(!noise.is_enabled());
+        let reported = noise.apply(&status);
+        assert_eq!(reported.az_pos, status.az_pos);
+        assert_eq!(reported.encoder_counts, status.encoder_counts);
+    }
+}
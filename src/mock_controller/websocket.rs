@@ -0,0 +1,91 @@
+//! Stream the simulated state of the mock controller over a WebSocket.
+//!
+//! Used to drive browser-based visualizations during demos and tests.
+
+#[cfg(feature = "websocket")]
+use axum::{
+    extract::ws::{Message, WebSocket, WebSocketUpgrade},
+    routing::get,
+    Router,
+};
+use serde::Serialize;
+#[cfg(feature = "websocket")]
+use tokio::net::TcpListener;
+use tokio::sync::watch;
+
+#[cfg(not(feature = "websocket"))]
+use crate::error::ATDomeError;
+use crate::{error::ATDomeResult, status::Status};
+
+/// Subset of the simulated status streamed to the clients.
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct MockState {
+    pub az_pos: f32,
+    pub last_azimuth_goto: f32,
+    pub main_door_pct: f32,
+    pub dropout_door_pct: f32,
+    pub move_code: u8,
+}
+
+impl From<&Status> for MockState {
+    fn from(status: &Status) -> MockState {
+        MockState {
+            az_pos: status.az_pos,
+            last_azimuth_goto: status.last_azimuth_goto,
+            main_door_pct: status.main_door_pct,
+            dropout_door_pct: status.dropout_door_pct,
+            move_code: status.move_code,
+        }
+    }
+}
+
+/// Send the state to the client every time it changes.
+#[cfg(feature = "websocket")]
+async fn stream_state(mut socket: WebSocket, mut state: watch::Receiver<MockState>) {
+    loop {
+        let message = match serde_json::to_string(&*state.borrow_and_update()) {
+            Ok(message) => message,
+            Err(error) => {
+                tracing::error!("Failed to serialize mock state: {error}");
+                break;
+            }
+        };
+        if socket.send(Message::Text(message)).await.is_err() {
+            tracing::debug!("WebSocket client disconnected.");
+            break;
+        }
+        if state.changed().await.is_err() {
+            break;
+        }
+    }
+}
+
+/// Serve the state stream on `address` under the `/state` path.
+#[cfg(feature = "websocket")]
+pub async fn serve_state_stream(
+    state: watch::Receiver<MockState>,
+    address: &str,
+) -> ATDomeResult<()> {
+    let app = Router::new().route(
+        "/state",
+        get(move |websocket: WebSocketUpgrade| async move {
+            websocket.on_upgrade(move |socket| stream_state(socket, state))
+        }),
+    );
+    let listener = TcpListener::bind(address).await?;
+    tracing::info!("Streaming mock state on {address}.");
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+/// Fail to serve the state stream; the crate was built without the
+/// websocket feature.
+#[cfg(not(feature = "websocket"))]
+pub async fn serve_state_stream(
+    _state: watch::Receiver<MockState>,
+    address: &str,
+) -> ATDomeResult<()> {
+    let message = format!("Cannot stream mock state on {address}: built without websocket.");
+    tracing::error!("{message}");
+    Err(ATDomeError::Internal(message))
+}
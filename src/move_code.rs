@@ -2,6 +2,28 @@
 //!
 //! This enumaration contains the different codes for the dome motion.
 
+use crate::error::{ATDomeError, ATDomeResult};
+
+/// Pairs of flags that the controller can never report at the same time.
+const CONFLICTING_FLAGS: [(MoveCode, MoveCode, &str); 3] = [
+    (
+        MoveCode::AzimuthPositive,
+        MoveCode::AzimuthNegative,
+        "azimuth moving positive and negative",
+    ),
+    (
+        MoveCode::MainDoorClosing,
+        MoveCode::MainDoorOpening,
+        "main door opening and closing",
+    ),
+    (
+        MoveCode::DropoutDoorClosing,
+        MoveCode::DropoutDoorOpening,
+        "dropout door opening and closing",
+    ),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MoveCode {
     AzimuthPositive,
     AzimuthNegative,
@@ -14,6 +36,18 @@ pub enum MoveCode {
 }
 
 impl MoveCode {
+    /// All the flags, in bit order.
+    pub const ALL: [MoveCode; 8] = [
+        MoveCode::AzimuthPositive,
+        MoveCode::AzimuthNegative,
+        MoveCode::MainDoorClosing,
+        MoveCode::MainDoorOpening,
+        MoveCode::DropoutDoorClosing,
+        MoveCode::DropoutDoorOpening,
+        MoveCode::AzimuthHoming,
+        MoveCode::EStop,
+    ];
+
     pub fn byte_value(&self) -> u8 {
         match self {
             MoveCode::AzimuthPositive => 0x01,
@@ -26,4 +60,139 @@ impl MoveCode {
             MoveCode::EStop => 0x80,
         }
     }
+
+    /// Is this flag set in the raw move code?
+    pub fn is_set(&self, move_code: u8) -> bool {
+        move_code & self.byte_value() > 0
+    }
+
+    /// Flags set in the raw move code, in bit order.
+    pub fn active(move_code: u8) -> impl Iterator<Item = MoveCode> {
+        MoveCode::ALL
+            .into_iter()
+            .filter(move |flag| flag.is_set(move_code))
+    }
+
+    /// Is the dome rotating in either direction?
+    pub fn is_azimuth_moving(move_code: u8) -> bool {
+        MoveCode::AzimuthPositive.is_set(move_code) || MoveCode::AzimuthNegative.is_set(move_code)
+    }
+
+    /// Is either the main or the dropout door moving?
+    pub fn is_any_door_moving(move_code: u8) -> bool {
+        [
+            MoveCode::MainDoorClosing,
+            MoveCode::MainDoorOpening,
+            MoveCode::DropoutDoorClosing,
+            MoveCode::DropoutDoorOpening,
+        ]
+        .iter()
+        .any(|flag| flag.is_set(move_code))
+    }
+
+    /// Is the emergency stop flag set?
+    pub fn is_estopped(move_code: u8) -> bool {
+        MoveCode::EStop.is_set(move_code)
+    }
+
+    /// Is the dome running the azimuth homing sequence?
+    pub fn is_homing(move_code: u8) -> bool {
+        MoveCode::AzimuthHoming.is_set(move_code)
+    }
+
+    /// Check that a raw move code does not contain mutually exclusive flags.
+    ///
+    /// Returns the move code unchanged if it is consistent, or an error
+    /// describing the impossible combination otherwise.
+    pub fn validate(move_code: u8) -> ATDomeResult<u8> {
+        for (first, second, description) in CONFLICTING_FLAGS {
+            let mask = first.byte_value() | second.byte_value();
+            if move_code & mask == mask {
+                return Err(ATDomeError::Parse(format!(
+                    "Invalid move code {move_code:#04x}: {description} simultaneously."
+                )));
+            }
+        }
+        Ok(move_code)
+    }
+}
+
+impl TryFrom<u8> for MoveCode {
+    type Error = ATDomeError;
+
+    /// Convert a raw move code with a single flag set into a MoveCode.
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        let value = MoveCode::validate(value)?;
+        match value {
+            0x01 => Ok(MoveCode::AzimuthPositive),
+            0x02 => Ok(MoveCode::AzimuthNegative),
+            0x04 => Ok(MoveCode::MainDoorClosing),
+            0x08 => Ok(MoveCode::MainDoorOpening),
+            0x10 => Ok(MoveCode::DropoutDoorClosing),
+            0x20 => Ok(MoveCode::DropoutDoorOpening),
+            0x40 => Ok(MoveCode::AzimuthHoming),
+            0x80 => Ok(MoveCode::EStop),
+            _ => Err(ATDomeError::Parse(format!(
+                "Move code {value:#04x} does not correspond to a single motion flag."
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_try_from_single_flag() {
+        let move_code = MoveCode::try_from(0x08).unwrap();
+
+        assert_eq!(move_code, MoveCode::MainDoorOpening);
+    }
+
+    #[test]
+    fn test_try_from_conflicting_azimuth() {
+        let move_code = MoveCode::try_from(0x03);
+
+        assert!(move_code.is_err());
+    }
+
+    #[test]
+    fn test_try_from_multiple_flags() {
+        let move_code = MoveCode::try_from(0x05);
+
+        assert!(move_code.is_err());
+    }
+
+    #[test]
+    fn test_predicates() {
+        assert!(MoveCode::is_azimuth_moving(0x02));
+        assert!(!MoveCode::is_azimuth_moving(0x04));
+        assert!(MoveCode::is_any_door_moving(0x20));
+        assert!(!MoveCode::is_any_door_moving(0x41));
+        assert!(MoveCode::is_estopped(0x80));
+        assert!(MoveCode::is_homing(0x40));
+        assert!(!MoveCode::is_homing(0x00));
+    }
+
+    #[test]
+    fn test_active() {
+        assert_eq!(MoveCode::active(0x00).count(), 0);
+        assert_eq!(
+            MoveCode::active(0x89).collect::<Vec<_>>(),
+            [
+                MoveCode::AzimuthPositive,
+                MoveCode::MainDoorOpening,
+                MoveCode::EStop
+            ]
+        );
+        assert_eq!(MoveCode::active(0xFF).collect::<Vec<_>>(), MoveCode::ALL);
+    }
+
+    #[test]
+    fn test_validate() {
+        assert_eq!(MoveCode::validate(0x05).unwrap(), 0x05);
+        assert!(MoveCode::validate(0x0C).is_err());
+        assert!(MoveCode::validate(0x30).is_err());
+    }
 }
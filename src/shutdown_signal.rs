@@ -0,0 +1,25 @@
+//! Wait for the signals that request the process to shutdown.
+
+use tokio::signal::{
+    self,
+    unix::{signal, SignalKind},
+};
+
+/// Wait until the process receives SIGINT or SIGTERM.
+pub async fn wait_for_shutdown_signal() {
+    match signal(SignalKind::terminate()) {
+        Ok(mut sigterm) => {
+            tokio::select! {
+                _ = signal::ctrl_c() => tracing::info!("Received SIGINT."),
+                _ = sigterm.recv() => tracing::info!("Received SIGTERM."),
+            }
+        }
+        Err(error) => {
+            tracing::error!("Failed to install SIGTERM handler: {error:?}. Only handling SIGINT.");
+            if let Err(error) = signal::ctrl_c().await {
+                tracing::error!("Failed to listen for SIGINT: {error:?}.");
+            }
+            tracing::info!("Received SIGINT.");
+        }
+    }
+}
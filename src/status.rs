@@ -1,6 +1,50 @@
 //! Define the Status struct, representing all information available from the ATDome controller.
 
-#[derive(Debug, Default, Clone, Copy)]
+use std::fmt;
+
+#[cfg(feature = "cli")]
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    azimuth::{azimuth_distance, wrap_azimuth},
+    error::{ATDomeError, ATDomeResult},
+};
+
+/// Space reserved for a rendered status reply (bytes).
+const STATUS_REPLY_CAPACITY: usize = 1024;
+
+/// Bits of `sensor_code` set by the controller when a weather sensor trips.
+pub const RAIN_SENSOR_TRIPPED: usize = 0x01;
+pub const CLOUD_SENSOR_TRIPPED: usize = 0x02;
+
+/// Variant of the long status reply sent by the controller.
+///
+/// Depending on the firmware version the reply may or may not end with the
+/// "Dome has been homed" line; the parser must accept both.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(ValueEnum))]
+pub enum StatusFormat {
+    /// Reply without the trailing homed line.
+    Short,
+    /// Reply ending with the homed line.
+    #[default]
+    Long,
+}
+
+/// Weather sensor flags decoded from the status.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SensorFlags {
+    /// Is the rain-snow sensor tripped?
+    pub rain_active: bool,
+    /// Is the cloud sensor tripped?
+    pub clouds_active: bool,
+    /// Does the controller close the shutter on bad weather?
+    pub auto_shutdown_enabled: bool,
+}
+
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Status {
     pub auto_shutdown_enabled: bool,
     pub az_home_switch: bool,
@@ -34,36 +78,404 @@ pub struct Status {
 
 impl Status {
     pub fn as_string(&self) -> String {
-        format!(
-            "MAIN CLOSED 000
-DROP CLOSED 000
-[OFF] 00
-POSN {}
--- {:03}
-Dome not homed
-Emergency Stop Active: 0
-Top Comm Link OK:    1
-Home Azimuth: 10.00
-High Speed (degrees):  5.00
-Coast (degrees): 0.50
-Tolerance (degrees): 1.00
-Encoder Counts per 360: 4018143232
-Encoder Counts:  111615089
-Last Azimuth GoTo: {}
-Azimuth Move Timeout (secs): 120
-Rain-Snow enabled:  1
-Cloud Sensor enabled: 1
-Watchdog Reset Time: 600
-Dropout Timer: 5
-Reverse Delay: 4
-Main Door Encoder Closed: 118449181478
-Main Door Encoder Opened: 8287616388
-Dropout Encoder Closed: 5669776578
-Dropout Encoder Opened: 5710996184
-Door Move Timeout (secs): 360
-Dome has been homed: False
-",
-            self.az_pos, self.move_code, self.last_azimuth_goto,
+        self.as_string_with_format(StatusFormat::Long)
+    }
+
+    /// Format the status as the controller reply in the given variant.
+    pub fn as_string_with_format(&self, status_format: StatusFormat) -> String {
+        let mut reply = String::with_capacity(STATUS_REPLY_CAPACITY);
+        self.write_with_format(&mut reply, status_format, "\n")
+            .expect("Writing to a String cannot fail.");
+        reply
+    }
+
+    /// Write the status as the controller reply in the given variant,
+    /// ending each line with `line_terminator`.
+    ///
+    /// Nothing is allocated, so the mock can render the reply into the same
+    /// buffer on every poll.
+    pub fn write_with_format<W: fmt::Write>(
+        &self,
+        writer: &mut W,
+        status_format: StatusFormat,
+        line_terminator: &str,
+    ) -> fmt::Result {
+        let t = line_terminator;
+        write!(
+            writer,
+            "MAIN {} {:03.0}{t}\
+             DROP {} {:03.0}{t}\
+             [{}] {:02}{t}\
+             POSN {}{t}\
+             -- {:03}{t}\
+             Dome {}homed{t}\
+             Emergency Stop Active: {}{t}\
+             Top Comm Link OK:    {}{t}\
+             Home Azimuth: {:.2}{t}\
+             High Speed (degrees):  {:.2}{t}\
+             Coast (degrees): {:.2}{t}\
+             Tolerance (degrees): {:.2}{t}\
+             Encoder Counts per 360: 4018143232{t}\
+             Encoder Counts:  111615089{t}\
+             Last Azimuth GoTo: {}{t}\
+             Azimuth Move Timeout (secs): {:.0}{t}\
+             Rain-Snow enabled:  {}{t}\
+             Cloud Sensor enabled: {}{t}\
+             Watchdog Reset Time: {:.0}{t}\
+             Dropout Timer: {:.0}{t}\
+             Reverse Delay: {:.0}{t}\
+             Main Door Encoder Closed: 118449181478{t}\
+             Main Door Encoder Opened: 8287616388{t}\
+             Dropout Encoder Closed: 5669776578{t}\
+             Dropout Encoder Opened: 5710996184{t}\
+             Door Move Timeout (secs): {:.0}{t}",
+            door_state_name(self.main_door_pct),
+            self.main_door_pct,
+            door_state_name(self.dropout_door_pct),
+            self.dropout_door_pct,
+            if self.auto_shutdown_enabled {
+                "ON"
+            } else {
+                "OFF"
+            },
+            self.sensor_code,
+            self.az_pos,
+            self.move_code,
+            if self.homed { "" } else { "not " },
+            self.estop_active as u8,
+            self.scb_link_ok as u8,
+            self.home_azimuth,
+            self.high_speed,
+            self.coast,
+            self.tolerance,
+            self.last_azimuth_goto,
+            self.azimuth_move_timeout,
+            self.rain_sensor_enabled as u8,
+            self.cloud_sensor_enabled as u8,
+            self.watchdog_timer,
+            self.dropout_timer,
+            self.reversal_delay,
+            self.door_move_timeout,
+        )?;
+        if status_format == StatusFormat::Long {
+            write!(
+                writer,
+                "Dome has been homed: {}{t}",
+                if self.homed { "True" } else { "False" }
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Distance from the azimuth to the last commanded azimuth along the
+    /// shortest path, in (-180, 180] deg; positive values are clockwise.
+    pub fn azimuth_error(&self) -> f32 {
+        azimuth_distance(self.az_pos, self.last_azimuth_goto)
+    }
+
+    /// Is the azimuth within the controller tolerance of the last
+    /// commanded azimuth? The dome may still be moving.
+    pub fn azimuth_in_position(&self) -> bool {
+        self.azimuth_error().abs() <= self.tolerance
+    }
+
+    /// Azimuth (deg) of `encoder_counts`, wrapped to [0, 360); None if the
+    /// controller did not report `encoder_counts_per_360`.
+    pub fn encoder_counts_to_degrees(&self, encoder_counts: u64) -> Option<f32> {
+        if self.encoder_counts_per_360 == 0 {
+            return None;
+        }
+        let turns = (encoder_counts % self.encoder_counts_per_360) as f64
+            / self.encoder_counts_per_360 as f64;
+        Some(wrap_azimuth((turns * 360.0) as f32))
+    }
+
+    /// Encoder counts of the azimuth `degrees`, within one turn; None if
+    /// the controller did not report `encoder_counts_per_360`.
+    pub fn degrees_to_encoder_counts(&self, degrees: f32) -> Option<u64> {
+        if self.encoder_counts_per_360 == 0 {
+            return None;
+        }
+        let turns = wrap_azimuth(degrees) as f64 / 360.0;
+        let encoder_counts = (turns * self.encoder_counts_per_360 as f64).round() as u64;
+        Some(encoder_counts % self.encoder_counts_per_360)
+    }
+
+    /// Azimuth (deg) given by the encoder counts.
+    pub fn encoder_azimuth(&self) -> Option<f32> {
+        self.encoder_counts_to_degrees(self.encoder_counts)
+    }
+
+    /// Percent open of the main door whose encoder reads `encoder`, from
+    /// the encoder extremes; None if the extremes are not known.
+    pub fn main_door_pct_from_encoder(&self, encoder: u64) -> Option<f32> {
+        door_pct_from_encoder(
+            encoder,
+            self.main_door_encoder_closed,
+            self.main_door_encoder_opened,
         )
     }
+
+    /// Percent open of the dropout door whose encoder reads `encoder`,
+    /// from the encoder extremes; None if the extremes are not known.
+    pub fn dropout_door_pct_from_encoder(&self, encoder: u64) -> Option<f32> {
+        door_pct_from_encoder(
+            encoder,
+            self.dropout_door_encoder_closed,
+            self.dropout_door_encoder_opened,
+        )
+    }
+
+    /// Weather sensor flags.
+    pub fn sensor_flags(&self) -> SensorFlags {
+        SensorFlags {
+            rain_active: self.sensor_code & RAIN_SENSOR_TRIPPED != 0,
+            clouds_active: self.sensor_code & CLOUD_SENSOR_TRIPPED != 0,
+            auto_shutdown_enabled: self.auto_shutdown_enabled,
+        }
+    }
+
+    /// Description of the weather sensors that tripped, or None if the
+    /// sensor code is clear.
+    pub fn weather_trip(&self) -> Option<String> {
+        if self.sensor_code == 0 {
+            return None;
+        }
+        let mut sensors = Vec::new();
+        if self.sensor_code & RAIN_SENSOR_TRIPPED != 0 {
+            sensors.push("rain-snow".to_owned());
+        }
+        if self.sensor_code & CLOUD_SENSOR_TRIPPED != 0 {
+            sensors.push("cloud".to_owned());
+        }
+        let unknown = self.sensor_code & !(RAIN_SENSOR_TRIPPED | CLOUD_SENSOR_TRIPPED);
+        if unknown != 0 {
+            sensors.push(format!("unknown ({unknown:#04x})"));
+        }
+        Some(format!("Weather sensor tripped: {}", sensors.join(", ")))
+    }
+
+    /// Refuse shutter commands the controller cannot execute in this
+    /// state: with the emergency stop active or without the link to the
+    /// shutter control board the doors do not move.
+    pub fn check_shutter_interlock(&self) -> ATDomeResult<()> {
+        let mut reasons = Vec::new();
+        if self.estop_active {
+            reasons.push("the emergency stop is active");
+        }
+        if !self.scb_link_ok {
+            reasons.push("the link to the shutter control board is down");
+        }
+        if reasons.is_empty() {
+            Ok(())
+        } else {
+            Err(ATDomeError::CommandRejected(format!(
+                "Shutter interlock: {}.",
+                reasons.join(" and ")
+            )))
+        }
+    }
+}
+
+/// Name the controller uses for a door at the given opening (%); a door
+/// partially open is reported as "AJAR".
+fn door_state_name(door_pct: f32) -> &'static str {
+    if door_pct <= 0.0 {
+        "CLOSED"
+    } else if door_pct >= 100.0 {
+        "OPENED"
+    } else {
+        "AJAR"
+    }
+}
+
+/// Percent open, clamped to [0, 100], of a door whose encoder reads
+/// `encoder`, given the encoder values with the door closed and opened.
+///
+/// The encoder may count up or down as the door opens. Returns None if the
+/// extremes are equal, e.g. not reported by the controller.
+pub fn door_pct_from_encoder(encoder: u64, closed: u64, opened: u64) -> Option<f32> {
+    if closed == opened {
+        return None;
+    }
+    let fraction = (encoder as f64 - closed as f64) / (opened as f64 - closed as f64);
+    Some((fraction * 100.0).clamp(0.0, 100.0) as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::status_parser::StatusParser;
+
+    #[test]
+    fn test_write_with_format() {
+        let status = Status {
+            az_pos: 90.0,
+            homed: true,
+            ..Default::default()
+        };
+        let mut reply = String::new();
+
+        status
+            .write_with_format(&mut reply, StatusFormat::Long, "\r\n")
+            .unwrap();
+
+        assert_eq!(reply, status.as_string().replace('\n', "\r\n"));
+        assert_eq!(reply.matches("\r\n").count(), 27);
+        assert!(reply.ends_with("Dome has been homed: True\r\n"));
+    }
+
+    #[test]
+    fn test_write_ajar_doors() {
+        let status = Status {
+            main_door_pct: 42.0,
+            dropout_door_pct: 100.0,
+            ..Default::default()
+        };
+        let reply = status.as_string();
+
+        assert!(reply.starts_with("MAIN AJAR 042\nDROP OPENED 100\n"));
+        let lines: Vec<&str> = reply.lines().collect();
+        let parsed = StatusParser::new().unwrap().make_status(&lines).unwrap();
+        assert_eq!(parsed.main_door_pct, 42.0);
+        assert_eq!(parsed.dropout_door_pct, 100.0);
+    }
+
+    #[test]
+    fn test_azimuth_error_wraps() {
+        let status = Status {
+            az_pos: 359.5,
+            last_azimuth_goto: 0.3,
+            tolerance: 1.0,
+            ..Default::default()
+        };
+        assert!((status.azimuth_error() - 0.8).abs() < 1e-4);
+        assert!(status.azimuth_in_position());
+
+        let status = Status {
+            az_pos: 0.5,
+            last_azimuth_goto: 358.0,
+            tolerance: 1.0,
+            ..Default::default()
+        };
+        assert!((status.azimuth_error() + 2.5).abs() < 1e-4);
+        assert!(!status.azimuth_in_position());
+    }
+
+    #[test]
+    fn test_encoder_counts_conversion() {
+        assert_eq!(Status::default().encoder_azimuth(), None);
+        assert_eq!(Status::default().degrees_to_encoder_counts(10.0), None);
+
+        let status = Status {
+            encoder_counts: 111615089,
+            encoder_counts_per_360: 4018143232,
+            ..Default::default()
+        };
+        assert!((status.encoder_azimuth().unwrap() - 10.0).abs() < 1e-4);
+        assert_eq!(status.degrees_to_encoder_counts(10.0), Some(111615090));
+        assert_eq!(status.degrees_to_encoder_counts(370.0), Some(111615090));
+        assert_eq!(status.degrees_to_encoder_counts(360.0), Some(0));
+        let counts = status.degrees_to_encoder_counts(-90.0).unwrap();
+        assert!((status.encoder_counts_to_degrees(counts).unwrap() - 270.0).abs() < 1e-4);
+        assert_eq!(
+            status.encoder_counts_to_degrees(4018143232 + 111615089),
+            status.encoder_azimuth()
+        );
+    }
+
+    #[test]
+    fn test_door_pct_from_encoder() {
+        let status = Status {
+            main_door_encoder_closed: 118449181478,
+            main_door_encoder_opened: 8287616388,
+            dropout_door_encoder_closed: 5669776578,
+            dropout_door_encoder_opened: 5710996184,
+            ..Default::default()
+        };
+        // The main door encoder counts down as it opens, the dropout door
+        // encoder up.
+        assert_eq!(status.main_door_pct_from_encoder(118449181478), Some(0.0));
+        assert_eq!(status.main_door_pct_from_encoder(8287616388), Some(100.0));
+        let half_open = (118449181478 + 8287616388) / 2;
+        assert!((status.main_door_pct_from_encoder(half_open).unwrap() - 50.0).abs() < 1e-3);
+        assert_eq!(status.dropout_door_pct_from_encoder(5669776578), Some(0.0));
+        assert_eq!(
+            status.dropout_door_pct_from_encoder(5710996184),
+            Some(100.0)
+        );
+
+        // Readings beyond the extremes are clamped.
+        assert_eq!(status.main_door_pct_from_encoder(0), Some(100.0));
+        assert_eq!(status.dropout_door_pct_from_encoder(0), Some(0.0));
+
+        assert_eq!(Status::default().main_door_pct_from_encoder(10), None);
+    }
+
+    #[test]
+    fn test_weather_trip() {
+        assert_eq!(Status::default().weather_trip(), None);
+
+        let status = Status {
+            sensor_code: RAIN_SENSOR_TRIPPED | CLOUD_SENSOR_TRIPPED,
+            ..Default::default()
+        };
+        assert_eq!(
+            status.weather_trip().unwrap(),
+            "Weather sensor tripped: rain-snow, cloud"
+        );
+
+        assert_eq!(
+            status.sensor_flags(),
+            SensorFlags {
+                rain_active: true,
+                clouds_active: true,
+                auto_shutdown_enabled: false,
+            }
+        );
+
+        let status = Status {
+            sensor_code: 0x08,
+            ..Default::default()
+        };
+        assert_eq!(
+            status.weather_trip().unwrap(),
+            "Weather sensor tripped: unknown (0x08)"
+        );
+    }
+
+    #[test]
+    fn test_check_shutter_interlock() {
+        let status = Status {
+            scb_link_ok: true,
+            ..Default::default()
+        };
+        assert!(status.check_shutter_interlock().is_ok());
+
+        let status = Status {
+            estop_active: true,
+            scb_link_ok: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            status
+                .check_shutter_interlock()
+                .unwrap_err()
+                .get_error_message(),
+            "Shutter interlock: the emergency stop is active."
+        );
+
+        let status = Status {
+            estop_active: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            status
+                .check_shutter_interlock()
+                .unwrap_err()
+                .get_error_message(),
+            "Shutter interlock: the emergency stop is active and the link to the shutter \
+             control board is down."
+        );
+    }
 }
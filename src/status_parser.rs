@@ -129,7 +129,7 @@ impl StatusParser {
     pub fn make_status(self, lines: &[&str]) -> ATDomeResult<Status> {
         let length = lines.len();
         if length != 27 && length != 28 {
-            return Err(ATDomeError::new(&format!(
+            return Err(ATDomeError::Parse(format!(
                 "Got {length}; expected 26 or 28."
             )));
         }
@@ -223,18 +223,18 @@ impl StatusParser {
                 if let Ok(value) = group.as_str().parse::<T>() {
                     Ok::<T, ATDomeError>(value)
                 } else {
-                    Err(ATDomeError::new(&format!(
+                    Err(ATDomeError::Parse(format!(
                         "Cannot convert string to return type: {}",
                         group.as_str()
                     )))
                 }
             } else {
-                return Err(ATDomeError::new(&format!(
+                return Err(ATDomeError::Parse(format!(
                     "Could not find expected group 1 in captured group: {capture:?}"
                 )));
             }
         } else {
-            return Err(ATDomeError::new(&format!("Failed to match {line}")));
+            return Err(ATDomeError::Parse(format!("Failed to match {line}")));
         }
     }
 
@@ -242,7 +242,7 @@ impl StatusParser {
         if let Some(capture) = regex.captures(&line) {
             return Ok(capture.get(extract_group).is_some());
         } else {
-            return Err(ATDomeError::new(&format!("Failed to match {line}")));
+            return Err(ATDomeError::Parse(format!("Failed to match {line}")));
         }
     }
 }
@@ -250,6 +250,7 @@ impl StatusParser {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::status::StatusFormat;
 
     #[test]
     fn test_status_parser_new() {
@@ -332,4 +333,21 @@ mod tests {
         assert_eq!(status.dropout_door_encoder_opened, 5710964429);
         assert_eq!(status.door_move_timeout, 360.0);
     }
+
+    #[test]
+    fn test_make_status_both_formats() {
+        let status = Status {
+            az_pos: 90.0,
+            ..Default::default()
+        };
+
+        for status_format in [StatusFormat::Short, StatusFormat::Long] {
+            let reply = status.as_string_with_format(status_format);
+            let lines: Vec<&str> = reply.split("\n").collect();
+
+            let parsed_status = StatusParser::new().unwrap().make_status(&lines).unwrap();
+
+            assert_eq!(parsed_status.az_pos, 90.0);
+        }
+    }
 }
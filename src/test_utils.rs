@@ -0,0 +1,287 @@
+//! Run the mock controller from integration tests.
+//!
+//! Enabled with the `test-utils` feature, so crates that talk to the ATDome
+//! controller can test against the same simulation the CSC uses:
+//!
+//! ```ignore
+//! let mut mock = MockControllerHandle::start().await?;
+//! // Point the system under test at mock.host():mock.port() and move...
+//! mock.wait_for_az(90.0, 0.5, Duration::from_secs(60)).await?;
+//! mock.inject_fault(MockFault::EStop).await?;
+//! mock.shutdown().await?;
+//! ```
+
+use std::net::SocketAddr;
+
+use tokio::{
+    net::TcpListener,
+    sync::{mpsc, oneshot, watch},
+    task::JoinHandle,
+    time::{timeout, Duration},
+};
+
+use crate::{
+    atdome_enums::ShutterDoorState,
+    azimuth::azimuth_distance,
+    error::{ATDomeError, ATDomeResult},
+    mock_controller::{
+        control::MockControl,
+        mock_controller::{serve_mock_controller, MockControllerConfig},
+        websocket::MockState,
+    },
+    move_code::MoveCode,
+    status::RAIN_SENSOR_TRIPPED,
+};
+
+/// Shutter door of the dome.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Door {
+    Main,
+    Dropout,
+}
+
+/// Fault that can be injected into the simulation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MockFault {
+    /// The emergency stop trips, stopping all motion.
+    EStop,
+    /// The link to the shutter control board goes down.
+    ScbLinkDown,
+    /// The azimuth loses its home.
+    NotHomed,
+    /// The rain-snow sensor trips.
+    RainSensor,
+}
+
+impl MockFault {
+    fn control(&self, active: bool) -> MockControl {
+        match self {
+            MockFault::EStop => MockControl::SetEstop(active),
+            MockFault::ScbLinkDown => MockControl::SetScbLink(!active),
+            MockFault::NotHomed => MockControl::SetHomed(!active),
+            MockFault::RainSensor => {
+                MockControl::SetSensorCode(if active { RAIN_SENSOR_TRIPPED } else { 0 })
+            }
+        }
+    }
+}
+
+/// Mock controller running in the background of a test.
+///
+/// The mock is stopped when the handle is dropped; use `shutdown` to wait
+/// for it to finish and see how it exited.
+pub struct MockControllerHandle {
+    address: SocketAddr,
+    state: watch::Receiver<MockState>,
+    control: mpsc::Sender<MockControl>,
+    shutdown: Option<oneshot::Sender<()>>,
+    task: Option<JoinHandle<ATDomeResult<()>>>,
+}
+
+impl MockControllerHandle {
+    /// Start the mock with the default configuration on an ephemeral port.
+    pub async fn start() -> ATDomeResult<MockControllerHandle> {
+        MockControllerHandle::start_with_config(MockControllerConfig {
+            port: 0,
+            ..Default::default()
+        })
+        .await
+    }
+
+    /// Start the mock with `config`; a port of 0 picks an ephemeral port.
+    pub async fn start_with_config(
+        config: MockControllerConfig,
+    ) -> ATDomeResult<MockControllerHandle> {
+        let listener = TcpListener::bind(&format!("{}:{}", config.host, config.port)).await?;
+        let address = listener.local_addr()?;
+        let (state_sender, state) = watch::channel(MockState::default());
+        let (control, control_rx) = mpsc::channel::<MockControl>(100);
+        let (shutdown, shutdown_receiver) = oneshot::channel::<()>();
+        let task = tokio::spawn(serve_mock_controller(
+            listener,
+            config,
+            async move {
+                let _ = shutdown_receiver.await;
+            },
+            state_sender,
+            control.clone(),
+            control_rx,
+        ));
+        tracing::info!("Started mock controller on {address}.");
+
+        Ok(MockControllerHandle {
+            address,
+            state,
+            control,
+            shutdown: Some(shutdown),
+            task: Some(task),
+        })
+    }
+
+    pub fn host(&self) -> String {
+        self.address.ip().to_string()
+    }
+
+    pub fn port(&self) -> usize {
+        self.address.port() as usize
+    }
+
+    /// Latest simulated state.
+    pub fn state(&self) -> MockState {
+        self.state.borrow().clone()
+    }
+
+    /// Wait until the azimuth is within `tolerance` (deg) of `az` and not
+    /// moving.
+    pub async fn wait_for_az(
+        &mut self,
+        az: f32,
+        tolerance: f32,
+        time_limit: Duration,
+    ) -> ATDomeResult<MockState> {
+        self.wait_for(
+            &format!("azimuth {az}"),
+            |state| {
+                azimuth_distance(state.az_pos, az).abs() <= tolerance
+                    && !MoveCode::is_azimuth_moving(state.move_code)
+            },
+            time_limit,
+        )
+        .await
+    }
+
+    /// Wait until `door` reaches `door_state`.
+    pub async fn wait_for_door_state(
+        &mut self,
+        door: Door,
+        door_state: ShutterDoorState,
+        time_limit: Duration,
+    ) -> ATDomeResult<MockState> {
+        self.wait_for(
+            &format!("{door:?} door {door_state:?}"),
+            |state| mock_door_state(state, door) == door_state,
+            time_limit,
+        )
+        .await
+    }
+
+    /// Inject `fault` into the simulation.
+    pub async fn inject_fault(&self, fault: MockFault) -> ATDomeResult<()> {
+        self.apply(fault.control(true)).await
+    }
+
+    /// Undo a fault injected with `inject_fault`.
+    pub async fn clear_fault(&self, fault: MockFault) -> ATDomeResult<()> {
+        self.apply(fault.control(false)).await
+    }
+
+    /// Apply a change to the simulated state.
+    pub async fn apply(&self, control: MockControl) -> ATDomeResult<()> {
+        self.control
+            .send(control)
+            .await
+            .map_err(|_| ATDomeError::Connection("The mock controller stopped.".to_owned()))
+    }
+
+    /// Stop the mock and wait for it to finish.
+    pub async fn shutdown(mut self) -> ATDomeResult<()> {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+        match self.task.take() {
+            Some(task) => task.await?,
+            None => Ok(()),
+        }
+    }
+
+    async fn wait_for(
+        &mut self,
+        description: &str,
+        condition: impl FnMut(&MockState) -> bool,
+        time_limit: Duration,
+    ) -> ATDomeResult<MockState> {
+        let result = timeout(time_limit, self.state.wait_for(condition))
+            .await
+            .map(|result| result.map(|state| state.clone()));
+        match result {
+            Ok(Ok(state)) => Ok(state),
+            Ok(Err(_)) => Err(ATDomeError::Connection(
+                "The mock controller stopped.".to_owned(),
+            )),
+            Err(_) => Err(ATDomeError::Timeout(format!(
+                "No {description} after {time_limit:?}; state is {:?}.",
+                self.state()
+            ))),
+        }
+    }
+}
+
+impl Drop for MockControllerHandle {
+    fn drop(&mut self) {
+        if let Some(task) = &self.task {
+            task.abort();
+        }
+    }
+}
+
+/// State of `door` in the simulation.
+fn mock_door_state(state: &MockState, door: Door) -> ShutterDoorState {
+    let (door_pct, opening, closing) = match door {
+        Door::Main => (
+            state.main_door_pct,
+            MoveCode::MainDoorOpening,
+            MoveCode::MainDoorClosing,
+        ),
+        Door::Dropout => (
+            state.dropout_door_pct,
+            MoveCode::DropoutDoorOpening,
+            MoveCode::DropoutDoorClosing,
+        ),
+    };
+    if opening.is_set(state.move_code) {
+        ShutterDoorState::Opening
+    } else if closing.is_set(state.move_code) {
+        ShutterDoorState::Closing
+    } else if door_pct <= 0.0 {
+        ShutterDoorState::Closed
+    } else if door_pct >= 100.0 {
+        ShutterDoorState::Opened
+    } else {
+        ShutterDoorState::PartiallyOpened
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::atdome_model::{ATDomeCmd, ATDomeModel};
+
+    #[tokio::test]
+    async fn test_mock_controller_handle() {
+        let mut mock = MockControllerHandle::start().await.unwrap();
+        let model = ATDomeModel::create_and_start(&mock.host(), mock.port(), 10, Duration::ZERO)
+            .await
+            .unwrap();
+
+        model.run_command(ATDomeCmd::MoveAz(5.0)).await.unwrap();
+        let state = mock
+            .wait_for_az(5.0, 0.1, Duration::from_secs(10))
+            .await
+            .unwrap();
+        assert_eq!(state.last_azimuth_goto, 5.0);
+
+        mock.apply(MockControl::SetMainDoor(100.0)).await.unwrap();
+        mock.wait_for_door_state(Door::Main, ShutterDoorState::Opened, Duration::from_secs(1))
+            .await
+            .unwrap();
+
+        mock.inject_fault(MockFault::EStop).await.unwrap();
+        let error = mock
+            .wait_for_az(90.0, 0.1, Duration::from_millis(200))
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), "Timeout");
+
+        mock.shutdown().await.unwrap();
+    }
+}
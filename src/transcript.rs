@@ -0,0 +1,190 @@
+//! Record of the traffic exchanged with the ATDome controller.
+//!
+//! Transcripts are stored as json lines, one entry per chunk of data, with
+//! the time elapsed since the beginning of the session. A transcript holds
+//! a single session, i.e. one client connection. The data is kept as the
+//! raw bytes, since the serial bridge may send bytes that are not UTF-8.
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    time::Instant,
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{self, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    time::{self, Duration},
+};
+
+use crate::error::{ATDomeError, ATDomeResult};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Direction {
+    ClientToController,
+    ControllerToClient,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TranscriptEntry {
+    /// Time since the beginning of the session (sec).
+    pub elapsed: f64,
+    pub direction: Direction,
+    pub data: Vec<u8>,
+}
+
+pub struct TranscriptWriter {
+    writer: BufWriter<File>,
+    start_time: Instant,
+}
+
+impl TranscriptWriter {
+    pub fn create(path: &Path) -> ATDomeResult<TranscriptWriter> {
+        Ok(TranscriptWriter {
+            writer: BufWriter::new(File::create(path)?),
+            start_time: Instant::now(),
+        })
+    }
+
+    /// Append an entry, timestamped with the time since the writer was
+    /// created.
+    pub fn record(&mut self, direction: Direction, data: &[u8]) -> ATDomeResult<()> {
+        let entry = TranscriptEntry {
+            elapsed: self.start_time.elapsed().as_secs_f64(),
+            direction,
+            data: data.to_vec(),
+        };
+        let line = serde_json::to_string(&entry).map_err(ATDomeError::from_error)?;
+        writeln!(self.writer, "{line}")?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Read all entries of a transcript file.
+pub fn read_transcript(path: &Path) -> ATDomeResult<Vec<TranscriptEntry>> {
+    BufReader::new(File::open(path)?)
+        .lines()
+        .filter(|line| !matches!(line, Ok(line) if line.trim().is_empty()))
+        .map(|line| {
+            let line = line?;
+            serde_json::from_str(&line)
+                .map_err(|error| ATDomeError::Parse(format!("Invalid transcript entry: {error}")))
+        })
+        .collect()
+}
+
+/// Play the controller side of `entries` to the client on `stream`.
+///
+/// The replies are written with their recorded timing, relative to the
+/// first entry and divided by `speed`, regardless of what the client sends;
+/// the commands of the client are logged so they can be compared with the
+/// transcript. Returns once every reply is written, or with an error if the
+/// client disconnects first.
+pub async fn serve_replies<S: AsyncRead + AsyncWrite>(
+    stream: S,
+    entries: &[TranscriptEntry],
+    speed: f64,
+) -> ATDomeResult<()> {
+    let Some(first) = entries.first() else {
+        return Ok(());
+    };
+    let (mut reader, mut writer) = io::split(stream);
+    let start_time = time::Instant::now();
+
+    let send_replies = async {
+        for entry in entries
+            .iter()
+            .filter(|entry| entry.direction == Direction::ControllerToClient)
+        {
+            let offset = (entry.elapsed - first.elapsed).max(0.0) / speed;
+            time::sleep_until(start_time + Duration::from_secs_f64(offset)).await;
+            writer.write_all(&entry.data).await?;
+        }
+        writer.flush().await?;
+        Ok(())
+    };
+    let log_commands = async {
+        let mut buffer = [0; 1024];
+        while let Ok(n_bytes) = reader.read(&mut buffer).await {
+            if n_bytes == 0 {
+                break;
+            }
+            tracing::info!("Received {:?}", String::from_utf8_lossy(&buffer[..n_bytes]));
+        }
+    };
+
+    tokio::select! {
+        result = send_replies => result,
+        _ = log_commands => Err(ATDomeError::Connection(
+            "The client disconnected before the end of the transcript.".to_owned(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_and_read() {
+        let path = std::env::temp_dir().join("atdome_transcript_test.jsonl");
+
+        let mut writer = TranscriptWriter::create(&path).unwrap();
+        writer
+            .record(Direction::ClientToController, b"+\r\n")
+            .unwrap();
+        writer
+            .record(Direction::ControllerToClient, b"MAIN CLOSED 000\n>")
+            .unwrap();
+        writer
+            .record(Direction::ControllerToClient, b"\xff\x00>")
+            .unwrap();
+        drop(writer);
+
+        let entries = read_transcript(&path).unwrap();
+
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].direction, Direction::ClientToController);
+        assert_eq!(entries[0].data, b"+\r\n");
+        assert_eq!(entries[1].data, b"MAIN CLOSED 000\n>");
+        assert_eq!(entries[2].data, b"\xff\x00>");
+        assert!(entries[1].elapsed >= entries[0].elapsed);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_serve_replies() {
+        let entries = vec![
+            TranscriptEntry {
+                elapsed: 10.0,
+                direction: Direction::ClientToController,
+                data: b"+\r\n".to_vec(),
+            },
+            TranscriptEntry {
+                elapsed: 10.5,
+                direction: Direction::ControllerToClient,
+                data: b"MAIN CLOSED 000\n".to_vec(),
+            },
+            TranscriptEntry {
+                elapsed: 12.0,
+                direction: Direction::ControllerToClient,
+                data: b">".to_vec(),
+            },
+        ];
+        let (mut client, server) = io::duplex(1024);
+
+        let start_time = time::Instant::now();
+        let replay_task = tokio::spawn(async move { serve_replies(server, &entries, 2.0).await });
+        client.write_all(b"+\r\n").await.unwrap();
+
+        let mut replies = String::new();
+        client.read_to_string(&mut replies).await.unwrap();
+        replay_task.await.unwrap().unwrap();
+
+        assert_eq!(replies, "MAIN CLOSED 000\n>");
+        assert!(start_time.elapsed() >= Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,20 @@
+//! Build metadata embedded at compile time.
+
+/// Version of the crate.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Short git hash of the commit the binaries were built from.
+pub const GIT_HASH: &str = env!("ATDOME_GIT_HASH");
+
+/// UTC date and time of the build.
+pub const BUILD_DATE: &str = env!("ATDOME_BUILD_DATE");
+
+/// Version string including the git hash and build date.
+pub const LONG_VERSION: &str = concat!(
+    env!("CARGO_PKG_VERSION"),
+    " (",
+    env!("ATDOME_GIT_HASH"),
+    " ",
+    env!("ATDOME_BUILD_DATE"),
+    ")"
+);
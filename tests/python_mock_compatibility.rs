@@ -0,0 +1,99 @@
+//! Wire-protocol compatibility tests against the Python ts_atdome mock.
+//!
+//! These tests only run when `ATDOME_PYTHON_MOCK_ADDRESS` is set to the
+//! `host:port` of a running Python mock controller, e.g.:
+//!
+//! ```text
+//! ATDOME_PYTHON_MOCK_ADDRESS=127.0.0.1:5001 cargo test --test python_mock_compatibility
+//! ```
+
+use rubin_ts_atdome::atdome_model::{ATDomeCmd, ATDomeModel, ATDomeReply};
+use tokio::{
+    sync::oneshot,
+    time::{timeout, Duration},
+};
+
+const PYTHON_MOCK_ADDRESS: &str = "ATDOME_PYTHON_MOCK_ADDRESS";
+const REPLY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Connect to the Python mock, or return None if the tests are disabled.
+async fn connect() -> Option<ATDomeModel> {
+    let Ok(address) = std::env::var(PYTHON_MOCK_ADDRESS) else {
+        println!("{PYTHON_MOCK_ADDRESS} not set; skipping.");
+        return None;
+    };
+    let (host, port) = address
+        .rsplit_once(':')
+        .expect("Address must be in the form host:port.");
+    let port = port.parse().expect("Invalid port.");
+    Some(
+        ATDomeModel::create_and_start(host, port, 10, Duration::ZERO)
+            .await
+            .expect("Failed to connect to the Python mock."),
+    )
+}
+
+async fn send(atdome_model: &ATDomeModel, atdome_cmd: ATDomeCmd) -> ATDomeReply {
+    let (reply_sender, reply_receiver) = oneshot::channel();
+    atdome_model
+        .cmd_channel()
+        .unwrap()
+        .send((atdome_cmd, reply_sender))
+        .await
+        .unwrap();
+    timeout(REPLY_TIMEOUT, reply_receiver)
+        .await
+        .expect("Timed out waiting for reply.")
+        .expect("Reply channel closed.")
+        .expect("Command failed.")
+}
+
+#[tokio::test]
+async fn test_get_status() {
+    let Some(atdome_model) = connect().await else {
+        return;
+    };
+
+    let reply = send(&atdome_model, ATDomeCmd::GetStatus).await;
+
+    assert!(matches!(reply, ATDomeReply::Status(_)));
+}
+
+#[tokio::test]
+async fn test_full_command_set() {
+    let Some(atdome_model) = connect().await else {
+        return;
+    };
+
+    let commands = [
+        ATDomeCmd::MoveAz(10.0),
+        ATDomeCmd::StopMotion,
+        ATDomeCmd::HomeAzimuth,
+        ATDomeCmd::StopMotion,
+        ATDomeCmd::OpenShutter,
+        ATDomeCmd::CloseShutter,
+        ATDomeCmd::OpenShutterMainDoor,
+        ATDomeCmd::CloseShutterMainDoor,
+        ATDomeCmd::OpenShutterDropoutDoor,
+        ATDomeCmd::CloseShutterDropoutDoor,
+        ATDomeCmd::StopMotion,
+    ];
+
+    for atdome_cmd in commands {
+        let command = format!("{atdome_cmd:?}");
+        let reply = send(&atdome_model, atdome_cmd).await;
+        assert!(
+            matches!(reply, ATDomeReply::None),
+            "Unexpected reply to {command}: {reply:?}"
+        );
+
+        // The status must still be parsed after every command.
+        let status = send(&atdome_model, ATDomeCmd::GetStatus).await;
+        assert!(
+            matches!(status, ATDomeReply::Status(_)),
+            "Failed to get status after {command}."
+        );
+    }
+
+    assert!(!atdome_model.is_finished());
+}
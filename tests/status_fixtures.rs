@@ -0,0 +1,182 @@
+//! Regression tests of the status parser against the status replies in
+//! `tests/fixtures/status`.
+//!
+//! `synthetic` holds hand-written replies in the controller's format, whose
+//! values are checked against `EXPECTED`. `captured` is for dumps recorded
+//! from the controller; every reply there must parse.
+
+use std::{fs, path::PathBuf};
+
+use rubin_ts_atdome::{controller_client::parse_status_reply, status::Status};
+
+/// Values expected from a fixture.
+struct Expected {
+    file: &'static str,
+    main_door_pct: f32,
+    dropout_door_pct: f32,
+    auto_shutdown_enabled: bool,
+    az_home_switch: bool,
+    az_pos: f32,
+    move_code: u8,
+    homed: bool,
+    estop_active: bool,
+    scb_link_ok: bool,
+}
+
+const EXPECTED: [Expected; 5] = [
+    Expected {
+        file: "homed_closed.txt",
+        main_door_pct: 0.0,
+        dropout_door_pct: 0.0,
+        auto_shutdown_enabled: false,
+        az_home_switch: false,
+        az_pos: 262.91,
+        move_code: 0,
+        homed: true,
+        estop_active: false,
+        scb_link_ok: true,
+    },
+    Expected {
+        file: "unhomed.txt",
+        main_door_pct: 0.0,
+        dropout_door_pct: 0.0,
+        auto_shutdown_enabled: false,
+        az_home_switch: true,
+        az_pos: 0.0,
+        move_code: 0,
+        homed: false,
+        estop_active: false,
+        scb_link_ok: true,
+    },
+    Expected {
+        file: "doors_ajar.txt",
+        main_door_pct: 42.0,
+        dropout_door_pct: 17.0,
+        auto_shutdown_enabled: true,
+        az_home_switch: false,
+        az_pos: 262.91,
+        move_code: 0,
+        homed: true,
+        estop_active: false,
+        scb_link_ok: true,
+    },
+    Expected {
+        file: "estop.txt",
+        main_door_pct: 0.0,
+        dropout_door_pct: 0.0,
+        auto_shutdown_enabled: false,
+        az_home_switch: false,
+        az_pos: 262.91,
+        move_code: 129,
+        homed: true,
+        estop_active: true,
+        scb_link_ok: false,
+    },
+    Expected {
+        file: "short_firmware.txt",
+        main_door_pct: 0.0,
+        dropout_door_pct: 0.0,
+        auto_shutdown_enabled: false,
+        az_home_switch: false,
+        az_pos: 262.91,
+        move_code: 0,
+        homed: true,
+        estop_active: false,
+        scb_link_ok: true,
+    },
+];
+
+fn fixtures_dir(kind: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/status")
+        .join(kind)
+}
+
+/// Paths of the replies in the `kind` fixtures directory.
+fn fixture_paths(kind: &str) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(fixtures_dir(kind))
+        .expect("Failed to list the fixtures.")
+        .map(|entry| entry.expect("Failed to read a fixture entry.").path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "txt"))
+        .collect();
+    paths.sort();
+    paths
+}
+
+/// Errors of the replies in `paths` that fail to parse.
+fn parse_failures(paths: &[PathBuf]) -> Vec<String> {
+    paths
+        .iter()
+        .filter_map(|path| {
+            let reply = fs::read_to_string(path).ok()?;
+            parse_status_reply(&reply)
+                .err()
+                .map(|error| format!("{}: {error}", path.display()))
+        })
+        .collect()
+}
+
+fn parse_fixture(file: &str) -> Status {
+    let path = fixtures_dir("synthetic").join(file);
+    let reply =
+        fs::read_to_string(&path).unwrap_or_else(|error| panic!("Failed to read {file}: {error}"));
+    parse_status_reply(&reply).unwrap_or_else(|error| panic!("Failed to parse {file}: {error}"))
+}
+
+#[test]
+fn test_synthetic_fixtures_parse() {
+    let paths = fixture_paths("synthetic");
+    assert!(!paths.is_empty());
+
+    let failures = parse_failures(&paths);
+
+    assert!(failures.is_empty(), "{failures:#?}");
+}
+
+#[test]
+fn test_captured_fixtures_parse() {
+    let failures = parse_failures(&fixture_paths("captured"));
+
+    assert!(failures.is_empty(), "{failures:#?}");
+}
+
+#[test]
+fn test_fixture_values() {
+    for expected in EXPECTED {
+        let status = parse_fixture(expected.file);
+
+        assert_eq!(
+            status.main_door_pct, expected.main_door_pct,
+            "{}",
+            expected.file
+        );
+        assert_eq!(
+            status.dropout_door_pct, expected.dropout_door_pct,
+            "{}",
+            expected.file
+        );
+        assert_eq!(
+            status.auto_shutdown_enabled, expected.auto_shutdown_enabled,
+            "{}",
+            expected.file
+        );
+        assert_eq!(
+            status.az_home_switch, expected.az_home_switch,
+            "{}",
+            expected.file
+        );
+        assert_eq!(status.az_pos, expected.az_pos, "{}", expected.file);
+        assert_eq!(status.move_code, expected.move_code, "{}", expected.file);
+        assert_eq!(status.homed, expected.homed, "{}", expected.file);
+        assert_eq!(
+            status.estop_active, expected.estop_active,
+            "{}",
+            expected.file
+        );
+        assert_eq!(
+            status.scb_link_ok, expected.scb_link_ok,
+            "{}",
+            expected.file
+        );
+    }
+}